@@ -0,0 +1,32 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+/// The system allocator, counting the allocations made on each thread, so tests running in
+/// parallel don't see each other's.
+struct CountingAllocator;
+
+thread_local! {
+	static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+		// The counter may already be gone while the thread is being torn down.
+		let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+		System.alloc(layout)
+	}
+
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+		System.dealloc(ptr, layout)
+	}
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Runs `f`, returning its result and how many allocations it made.
+pub fn count_allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+	let before = ALLOCATIONS.with(Cell::get);
+	let result = f();
+	(result, ALLOCATIONS.with(Cell::get) - before)
+}
@@ -7,8 +7,11 @@ use crate::{
 	class::{self, Class},
 	environment::{self, Environment},
 	interpreter::function::Callable,
-	parser::{Expr, Stmt},
-	token::{Token, TokenType},
+	parser::{
+		Argument, BlockExpr, CallExpr, Expr, FunctionExpr, IfExpr, MatchExpr, Stmt,
+		SuperCallExpr, SuperExpr,
+	},
+	token::{format_number, Token, TokenType},
 };
 
 pub mod function;
@@ -114,7 +117,7 @@ impl Display for Value {
 		match self {
 			Value::Null => write!(f, "nil"),
 			Value::Bool(v) => write!(f, "{v}"),
-			Value::Number(n) => write!(f, "{n}"),
+			Value::Number(n) => write!(f, "{}", format_number(*n)),
 			Value::String(s) => write!(f, "{s}"),
 			Value::Instance(instance) => write!(f, "{}", instance.borrow()),
 			Value::Function(callable) => write!(f, "{}", callable.type_name()),
@@ -196,8 +199,38 @@ pub enum Error {
 		superclass_ident: Token,
 		value: Value,
 	},
+	/// There's no value a range could evaluate to yet.
+	UnsupportedRange(Token),
+	/// Nothing can be spread into arguments, as there are no lists yet.
+	UnsupportedSpread(Token),
+	/// Functions can't be called with named arguments yet.
+	UnsupportedNamedArgument(Token),
+	/// `break` and `continue` are only parsed so far.
+	UnsupportedLoopControl(Token),
+	/// There's no module loader to import with.
+	UnsupportedImport(Token),
+	/// There's no value apart from `nil` for `undefined` to evaluate to.
+	UnsupportedUndefined(Token),
+	/// Exceptions are only parsed so far.
+	UnsupportedThrow(Token),
+	/// Exceptions are only parsed so far, so there's nothing to catch.
+	UnsupportedTry(Token),
+	/// Static methods are only parsed so far.
+	UnsupportedStaticMethod(Token),
+	/// Field declarations are only parsed so far.
+	UnsupportedField(Token),
+	/// `??` is only parsed so far.
+	UnsupportedCoalesce(Token),
+	/// `?.` is only parsed so far.
+	UnsupportedOptionalChaining(Token),
+	/// `is` is only parsed so far.
+	UnsupportedTypeTest(Token),
 
 	ReturnStatement(Value),
+	AssertionFailed {
+		keyword: Token,
+		message: Option<Value>,
+	},
 }
 
 impl Display for Error {
@@ -301,8 +334,54 @@ impl Display for Error {
 					value.type_name()
 				)
 			}
+			Error::UnsupportedRange(Token { line, .. }) => {
+				write!(f, "[line {line}] ranges can't be evaluated yet")
+			}
+			Error::UnsupportedSpread(Token { line, .. }) => {
+				write!(f, "[line {line}] arguments can't be spread yet")
+			}
+			Error::UnsupportedNamedArgument(Token { lexeme, line, .. }) => {
+				write!(f, "[line {line}] named argument `{lexeme}` isn't supported yet")
+			}
+			Error::UnsupportedLoopControl(Token { lexeme, line, .. }) => {
+				write!(f, "[line {line}] `{lexeme}` can't be executed yet")
+			}
+			Error::UnsupportedImport(Token { line, .. }) => {
+				write!(f, "[line {line}] modules can't be imported yet")
+			}
+			Error::UnsupportedUndefined(Token { line, .. }) => {
+				write!(f, "[line {line}] `undefined` can't be evaluated yet")
+			}
+			Error::UnsupportedThrow(Token { line, .. }) => {
+				write!(f, "[line {line}] `throw` can't be executed yet")
+			}
+			Error::UnsupportedTry(Token { line, .. }) => {
+				write!(f, "[line {line}] `try` can't be executed yet")
+			}
+			Error::UnsupportedStaticMethod(Token { line, lexeme, .. }) => {
+				write!(f, "[line {line}] static method `{lexeme}` can't be declared yet")
+			}
+			Error::UnsupportedField(Token { line, lexeme, .. }) => {
+				write!(f, "[line {line}] field `{lexeme}` can't be declared yet")
+			}
+			Error::UnsupportedCoalesce(Token { line, .. }) => {
+				write!(f, "[line {line}] `??` can't be evaluated yet")
+			}
+			Error::UnsupportedOptionalChaining(Token { line, .. }) => {
+				write!(f, "[line {line}] `?.` can't be evaluated yet")
+			}
+			Error::UnsupportedTypeTest(Token { line, .. }) => {
+				write!(f, "[line {line}] `is` can't be evaluated yet")
+			}
 
 			Error::ReturnStatement(_) => write!(f, "return"),
+			Error::AssertionFailed {
+				keyword: Token { line, .. },
+				message,
+			} => match message {
+				Some(message) => write!(f, "[line {line}] assertion failed: {message}"),
+				None => write!(f, "[line {line}] assertion failed"),
+			},
 		}
 	}
 }
@@ -317,20 +396,26 @@ impl Interpreter {
 	pub fn interpret(&mut self, statements: impl IntoIterator<Item = Stmt>) -> Result<(), Error> {
 		for statement in statements {
 			match statement {
-				Stmt::Print(expr) => {
-					let v = self.eval(expr)?;
-					println!("{v}");
+				Stmt::Print(exprs) => {
+					let values = exprs
+						.into_iter()
+						.map(|expr| self.eval(expr).map(|v| v.to_string()))
+						.collect::<Result<Vec<_>, _>>()?;
+					println!("{}", values.join(" "));
 				}
 				Stmt::Expr(expr) => {
 					self.eval(expr)?;
 				}
-				Stmt::Var { name, initializer } => {
+				Stmt::Var {
+					name, initializer, ..
+				} => {
 					let value = match initializer {
 						Some(expr) => Some(self.eval(expr)?),
 						None => None,
 					};
 					self.environment.borrow_mut().define(name.lexeme, value);
 				}
+				Stmt::Empty => {}
 				Stmt::Block(statements) => {
 					let env = Environment::new(Rc::clone(&self.environment));
 					// println!("new block, new env {env:?}");
@@ -347,7 +432,9 @@ impl Interpreter {
 						self.interpret(std::iter::once(*else_branch))?;
 					}
 				}
-				Stmt::While { condition, body } => {
+				Stmt::While {
+					condition, body, ..
+				} => {
 					let body = *body;
 					while self.eval(condition.clone())?.is_truthy() {
 						self.interpret(std::iter::once(body.clone()))?;
@@ -369,14 +456,26 @@ impl Interpreter {
 					keyword: _keyword,
 					value,
 				} => {
-					let value = self.eval(value)?;
+					let value = match value {
+						Some(value) => self.eval(value)?,
+						None => Value::Null,
+					};
 					return Err(Error::ReturnStatement(value));
 				}
 				Stmt::Class {
 					name,
 					superclass,
 					methods,
+					static_methods,
+					fields,
 				} => {
+					if let Some(method) = static_methods.into_iter().next() {
+						return Err(Error::UnsupportedStaticMethod(method.name));
+					}
+					if let Some((field, _)) = fields.into_iter().next() {
+						return Err(Error::UnsupportedField(field));
+					}
+
 					let superclass = match superclass {
 						Some(superclass) => match self.eval(Expr::Variable(superclass.clone()))? {
 							Value::Class(class) => Some(class),
@@ -430,6 +529,25 @@ impl Interpreter {
 						Value::Class(Class::new(name, superclass, class_methods)),
 					)?;
 				}
+				Stmt::Throw { keyword, .. } => return Err(Error::UnsupportedThrow(keyword)),
+				Stmt::Import { keyword, .. } => return Err(Error::UnsupportedImport(keyword)),
+				Stmt::Try { keyword, .. } => return Err(Error::UnsupportedTry(keyword)),
+				Stmt::Assert {
+					keyword,
+					condition,
+					message,
+				} => {
+					if !self.eval(condition)?.is_truthy() {
+						let message = match message {
+							Some(message) => Some(self.eval(message)?),
+							None => None,
+						};
+						return Err(Error::AssertionFailed { keyword, message });
+					}
+				}
+				Stmt::Break { keyword, .. } | Stmt::Continue { keyword, .. } => {
+					return Err(Error::UnsupportedLoopControl(keyword));
+				}
 			}
 		}
 		Ok(())
@@ -454,6 +572,10 @@ impl Interpreter {
 				token_type: TokenType::Number(v),
 				..
 			}) => Ok(Value::Number(v)),
+			Expr::Literal(Token {
+				token_type: TokenType::Integer(v),
+				..
+			}) => Ok(Value::Number(v as f64)),
 			Expr::Literal(Token {
 				token_type: TokenType::String(v),
 				..
@@ -470,6 +592,12 @@ impl Interpreter {
 				token_type: TokenType::Nil,
 				..
 			}) => Ok(Value::Null),
+			Expr::Literal(
+				token @ Token {
+					token_type: TokenType::Undefined,
+					..
+				},
+			) => Err(Error::UnsupportedUndefined(token)),
 			Expr::Literal(token) => Err(Error::UnexpectedLiteral(token)),
 			Expr::Variable(ref token) => self.look_up_variable(token.clone(), expr),
 			ref expr @ Expr::Assign {
@@ -725,6 +853,9 @@ impl Interpreter {
 						})?;
 				Ok(Value::Bool(left >= right))
 			}
+			Expr::Binary { operator, .. } if operator.token_type == TokenType::Is => {
+				Err(Error::UnsupportedTypeTest(operator))
+			}
 			Expr::Binary { operator, .. } => Err(Error::InvalidBinaryOperator(operator)),
 			Expr::Logical {
 				left,
@@ -756,18 +887,21 @@ impl Interpreter {
 					self.eval(*right)
 				}
 			}
+			Expr::Logical { operator, .. }
+				if operator.token_type == TokenType::QuestionQuestion =>
+			{
+				Err(Error::UnsupportedCoalesce(operator))
+			}
 			Expr::Logical { operator, .. } => Err(Error::InvalidLogicalOperator(operator)),
-			Expr::Call {
-				callee,
-				closing_parenthesis,
-				arguments,
-			} => {
-				let callee = self.eval(*callee)?;
+			Expr::Call(call) => {
+				let CallExpr {
+					callee,
+					closing_parenthesis,
+					arguments,
+				} = *call;
+				let callee = self.eval(callee)?;
 
-				let mut evaluted_arguments = Vec::with_capacity(arguments.len());
-				for argument in arguments {
-					evaluted_arguments.push(self.eval(argument)?);
-				}
+				let evaluted_arguments = self.eval_arguments(arguments)?;
 
 				let Some(function) = callee.as_callable() else {
                     return Err(Error::InvalidFunctionCallee(closing_parenthesis));
@@ -786,7 +920,14 @@ impl Interpreter {
 					result => result,
 				}
 			}
-			Expr::Get { object, name } => {
+			Expr::Get {
+				object,
+				name,
+				optional,
+			} => {
+				if optional {
+					return Err(Error::UnsupportedOptionalChaining(name));
+				}
 				let object = self.eval(*object)?;
 				let instance = object.into_instance().map_err(|object| {
 					Error::InvalidPropertyAccessTarget {
@@ -821,23 +962,13 @@ impl Interpreter {
 				let var = self.look_up_variable(keyword.clone(), expr)?;
 				Ok(var)
 			}
-			Expr::Super {
-				ref keyword,
-				ref method,
-			} => {
+			Expr::Super(ref super_expr) => {
+				let SuperExpr {
+					ref keyword,
+					ref method,
+				} = **super_expr;
 				let distance = self.locals.get(&expr).unwrap();
-
-				let superclass =
-					Environment::get_at(Rc::clone(&self.environment), "super", *distance)
-						.into_class()
-						.expect("super is not a class");
-				let object =
-					Environment::get_at(Rc::clone(&self.environment), "this", *distance - 1)
-						.into_instance()
-						.map_err(|object| Error::InvalidPropertyAccessTarget {
-							target_type: object.type_name(),
-							token: keyword.clone(),
-						})?;
+				let (superclass, object) = self.super_and_this(*distance, keyword)?;
 
 				let method =
 					superclass
@@ -848,6 +979,116 @@ impl Interpreter {
 
 				Ok(Value::Function(Rc::new(method.bind(object))))
 			}
+			Expr::SuperCall(ref super_call) => {
+				let distance = self.locals.get(&expr).unwrap();
+				let (superclass, object) = self.super_and_this(*distance, &super_call.keyword)?;
+				let Expr::SuperCall(super_call) = expr else {
+					unreachable!()
+				};
+				let SuperCallExpr {
+					closing_parenthesis,
+					arguments,
+					..
+				} = *super_call;
+
+				let evaluted_arguments = self.eval_arguments(arguments)?;
+
+				// Without an initializer, there's nothing to run, as if it took no parameters.
+				let Some(initializer) = superclass.find_method("init") else {
+					if !evaluted_arguments.is_empty() {
+						return Err(Error::InvalidNumberOfParameters {
+							expected: 0,
+							got: evaluted_arguments.len(),
+							token: closing_parenthesis,
+						});
+					}
+					return Ok(Value::Instance(object));
+				};
+				let initializer = initializer.bind(object);
+				if evaluted_arguments.len() != initializer.arity() {
+					return Err(Error::InvalidNumberOfParameters {
+						expected: initializer.arity(),
+						got: evaluted_arguments.len(),
+						token: closing_parenthesis,
+					});
+				}
+
+				match initializer.call(self, evaluted_arguments) {
+					Err(Error::ReturnStatement(value)) => Ok(value),
+					result => result,
+				}
+			}
+			Expr::Function(function) => {
+				let FunctionExpr {
+					keyword,
+					name,
+					params,
+					body,
+					..
+				} = *function;
+				// A named function gets an environment of its own to find itself in, like the
+				// scope the resolver gives it.
+				let closure = match name {
+					Some(_) => Environment::new(Rc::clone(&self.environment)),
+					None => Rc::clone(&self.environment),
+				};
+				let function: Rc<dyn Callable> = Rc::new(function::Function {
+					// Anonymous functions are displayed as `fun`.
+					declaration_name: name.clone().unwrap_or(keyword),
+					declaration_params: params,
+					declaration_body: body,
+					closure: Rc::clone(&closure),
+					is_initializer: false,
+				});
+				if let Some(name) = name {
+					closure
+						.borrow_mut()
+						.define(name.lexeme, Some(Value::Function(Rc::clone(&function))));
+				}
+				Ok(Value::Function(function))
+			}
+			Expr::Match(match_expr) => {
+				let MatchExpr {
+					subject,
+					arms,
+					default,
+					..
+				} = *match_expr;
+				let subject = self.eval(subject)?;
+				for (pattern, value) in arms {
+					if self.eval(pattern)?.is_equal(&subject) {
+						return self.eval(value);
+					}
+				}
+				match default {
+					Some(default) => self.eval(default),
+					None => Ok(Value::Null),
+				}
+			}
+			Expr::Block(block) => {
+				let BlockExpr {
+					statements, value, ..
+				} = *block;
+				let env = Environment::new(Rc::clone(&self.environment));
+				let original = std::mem::replace(&mut self.environment, env);
+				let result = self.interpret(statements).and_then(|()| self.eval(value));
+				self.environment = original;
+				result
+			}
+			Expr::If(if_expr) => {
+				let IfExpr {
+					condition,
+					then_branch,
+					else_branch,
+					..
+				} = *if_expr;
+				if self.eval(condition)?.is_truthy() {
+					self.eval(then_branch)
+				} else {
+					self.eval(else_branch)
+				}
+			}
+			Expr::Range { start, .. } => Err(Error::UnsupportedRange(start.first_token().clone())),
 		}
 	}
 
@@ -866,7 +1107,72 @@ impl Interpreter {
 		}
 	}
 
+	/// Evaluates call arguments from left to right.
+	fn eval_arguments(&mut self, arguments: Vec<Argument>) -> Result<Vec<Value>, Error> {
+		let mut values = Vec::with_capacity(arguments.len());
+		for argument in arguments {
+			if argument.spread {
+				return Err(Error::UnsupportedSpread(argument.expr.first_token().clone()));
+			}
+			if let Some(name) = argument.name {
+				return Err(Error::UnsupportedNamedArgument(name));
+			}
+			values.push(self.eval(argument.expr)?);
+		}
+		Ok(values)
+	}
+
+	/// The superclass and the instance that `super` refers to, `distance` scopes up.
+	fn super_and_this(
+		&self,
+		distance: i32,
+		keyword: &Token,
+	) -> Result<(Class, Rc<RefCell<class::Instance>>), Error> {
+		let superclass = Environment::get_at(Rc::clone(&self.environment), "super", distance)
+			.into_class()
+			.expect("super is not a class");
+		let object = Environment::get_at(Rc::clone(&self.environment), "this", distance - 1)
+			.into_instance()
+			.map_err(|object| Error::InvalidPropertyAccessTarget {
+				target_type: object.type_name(),
+				token: keyword.clone(),
+			})?;
+		Ok((superclass, object))
+	}
+
 	pub fn resolve(&mut self, expr: Expr, depth: i32) {
 		self.locals.insert(expr, depth);
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::{Error, Interpreter};
+	use crate::parser::Parser;
+	use crate::resolver::Resolver;
+	use crate::scanner::Scanner;
+
+	fn run(source: &str) -> Result<(), Error> {
+		let statements = Parser::new(Scanner::new(source).scan_tokens())
+			.parse()
+			.unwrap();
+		let mut interpreter = Interpreter::default();
+		Resolver::new(&mut interpreter)
+			.resolve_statements(statements.clone())
+			.unwrap();
+		interpreter.interpret(statements)
+	}
+
+	#[test]
+	fn test_named_function_expression_recursion() {
+		let source = "var f = fun fact(n) { if (n <= 1) return 1; return n * fact(n - 1); };\n\
+			assert f(5) == 120;\n\
+			{ var g = fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); };\n\
+			assert g(10) == 55; }";
+		run(source).unwrap();
+
+		// The name isn't visible outside the function.
+		let err = run("var f = fun fact(n) { return n; }; fact(1);").unwrap_err();
+		assert!(matches!(err, Error::UnknownVariable(name) if name.lexeme == "fact"));
+	}
+}
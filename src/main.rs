@@ -13,6 +13,8 @@ use crate::interpreter::Interpreter;
 use crate::resolver::Resolver;
 use crate::scanner::Scanner;
 
+#[cfg(test)]
+mod alloc_count;
 mod class;
 mod cli;
 mod environment;
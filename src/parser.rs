@@ -1,18 +1,32 @@
 #![allow(clippy::while_let_loop)]
 
-use std::fmt::Display;
+use std::{borrow::Cow, collections::{HashMap, HashSet}, fmt::Display};
 
-use crate::token::{next_universal_index, Token, TokenType};
+use crate::token::{format_number, Token, TokenType};
+use arena::{ExprKind, StmtKind};
+use builder::{NodeBuilder, Tree};
+use visitor::ExprVisitor;
 
-#[derive(Debug, Clone)]
+pub mod arena;
+pub mod builder;
+pub mod lint;
+pub mod printer;
+pub mod transform;
+pub mod visitor;
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub enum Stmt {
 	Expr(Expr),
-	Print(Expr),
+	/// `print a, b;` prints its values separated by spaces.
+	Print(Vec<Expr>),
 	Var {
 		name: Token,
 		initializer: Option<Expr>,
+		scope: VarScope,
 	},
 	Block(Vec<Stmt>),
+	/// A lone `;`, which does nothing. `;;;` is three of them.
+	Empty,
 	If {
 		condition: Expr,
 		then_branch: Box<Stmt>,
@@ -21,24 +35,311 @@ pub enum Stmt {
 	While {
 		condition: Expr,
 		body: Box<Stmt>,
+		/// `name: while ...`, for `break name;` and `continue name;` in nested loops.
+		label: Option<Token>,
+	},
+	/// `break;`, or `break label;` to leave an enclosing loop other than the innermost one.
+	Break {
+		keyword: Token,
+		label: Option<Token>,
+	},
+	/// `continue;`, or `continue label;`, like [Stmt::Break].
+	Continue {
+		keyword: Token,
+		label: Option<Token>,
 	},
 	Function(FunctionStatement),
 	Return {
 		keyword: Token,
-		value: Expr,
+		/// `None` for a bare `return;`.
+		value: Option<Expr>,
 	},
 	Class {
 		name: Token,
 		superclass: Option<Token>,
 		methods: Vec<FunctionStatement>,
+		/// Methods declared with a `class` prefix, called on the class itself.
+		static_methods: Vec<FunctionStatement>,
+		/// `var` declarations in the class body, in declaration order.
+		fields: Vec<(Token, Option<Expr>)>,
+	},
+	Throw {
+		keyword: Token,
+		value: Expr,
+	},
+	Try {
+		keyword: Token,
+		body: Box<Stmt>,
+		catch_name: Option<Token>,
+		catch_body: Box<Stmt>,
+	},
+	Assert {
+		keyword: Token,
+		condition: Expr,
+		message: Option<Expr>,
+	},
+	/// `import "path";` or `import "path" as alias;`. The path is a string literal, which the
+	/// embedder's loader makes sense of.
+	Import {
+		keyword: Token,
+		path: Token,
+		alias: Option<Token>,
 	},
 }
 
-#[derive(Debug, Clone)]
-pub struct FunctionStatement {
+/// The qualifier of a variable declaration, as in `global var x;`. Embedders with module scoping
+/// make sense of it. The interpreter doesn't.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash)]
+pub enum VarScope {
+	/// Just `var`.
+	#[default]
+	Unqualified,
+	Global,
+	Local,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct FunctionStatement<S = Stmt> {
 	pub name: Token,
 	pub params: Vec<Token>,
-	pub body: Vec<Stmt>,
+	pub body: Vec<S>,
+}
+
+#[allow(dead_code)]
+impl<S> FunctionStatement<S> {
+	pub fn arity(&self) -> usize {
+		self.params.len()
+	}
+
+	/// The function's name, or the lexeme of the name token if it isn't an identifier, e.g. for
+	/// a function expression without a name.
+	pub fn name_str(&self) -> &str {
+		match &self.name.token_type {
+			TokenType::Identifier(name) => name,
+			_ => &self.name.lexeme,
+		}
+	}
+
+	/// The first parameter whose name was already taken by an earlier one.
+	pub fn has_duplicate_params(&self) -> Option<&Token> {
+		let mut seen = HashSet::new();
+		self.params.iter().find(|param| !seen.insert(param.lexeme.as_str()))
+	}
+}
+
+#[allow(dead_code)]
+impl Stmt {
+	/// Flattens an `if`/`else if`/`else` ladder into its `(condition, branch)` pairs, in source
+	/// order, and the final `else` branch. A statement other than `if` has no branches.
+	pub fn if_chain_branches(&self) -> (Vec<(&Expr, &Stmt)>, Option<&Stmt>) {
+		let mut branches = Vec::new();
+		let mut stmt = self;
+		loop {
+			let Stmt::If {
+				condition,
+				then_branch,
+				else_branch,
+			} = stmt
+			else {
+				// Only reachable for `self`; an `else` that isn't an `if` ends the chain below.
+				return (branches, None);
+			};
+			branches.push((condition, then_branch.as_ref()));
+			match else_branch.as_deref() {
+				Some(else_if @ Stmt::If { .. }) => stmt = else_if,
+				else_branch => return (branches, else_branch),
+			}
+		}
+	}
+
+	/// Statement counterpart of [Expr::structurally_eq].
+	pub fn structurally_eq(&self, other: &Stmt) -> bool {
+		fn all_eq(a: &[Stmt], b: &[Stmt]) -> bool {
+			a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.structurally_eq(b))
+		}
+
+		fn functions_eq(a: &FunctionStatement, b: &FunctionStatement) -> bool {
+			a.name.token_type == b.name.token_type
+				&& a.params.len() == b.params.len()
+				&& a.params
+					.iter()
+					.zip(&b.params)
+					.all(|(a, b)| a.token_type == b.token_type)
+				&& all_eq(&a.body, &b.body)
+		}
+
+		match (self, other) {
+			(Stmt::Expr(a), Stmt::Expr(b)) => a.structurally_eq(b),
+			(Stmt::Print(a), Stmt::Print(b)) => {
+				a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.structurally_eq(b))
+			}
+			(
+				Stmt::Var {
+					name,
+					initializer,
+					scope,
+				},
+				Stmt::Var {
+					name: other_name,
+					initializer: other_initializer,
+					scope: other_scope,
+				},
+			) => {
+				name.token_type == other_name.token_type
+					&& scope == other_scope
+					&& match (initializer, other_initializer) {
+						(Some(a), Some(b)) => a.structurally_eq(b),
+						(None, None) => true,
+						_ => false,
+					}
+			}
+			(Stmt::Block(a), Stmt::Block(b)) => all_eq(a, b),
+			(Stmt::Empty, Stmt::Empty) => true,
+			(
+				Stmt::If {
+					condition,
+					then_branch,
+					else_branch,
+				},
+				Stmt::If {
+					condition: other_condition,
+					then_branch: other_then_branch,
+					else_branch: other_else_branch,
+				},
+			) => {
+				condition.structurally_eq(other_condition)
+					&& then_branch.structurally_eq(other_then_branch)
+					&& match (else_branch, other_else_branch) {
+						(Some(a), Some(b)) => a.structurally_eq(b),
+						(None, None) => true,
+						_ => false,
+					}
+			}
+			(
+				Stmt::While {
+					condition,
+					body,
+					label,
+				},
+				Stmt::While {
+					condition: other_condition,
+					body: other_body,
+					label: other_label,
+				},
+			) => {
+				label.as_ref().map(|t| &t.token_type) == other_label.as_ref().map(|t| &t.token_type)
+					&& condition.structurally_eq(other_condition)
+					&& body.structurally_eq(other_body)
+			}
+			(Stmt::Break { label, .. }, Stmt::Break { label: other_label, .. })
+			| (Stmt::Continue { label, .. }, Stmt::Continue { label: other_label, .. }) => {
+				label.as_ref().map(|t| &t.token_type) == other_label.as_ref().map(|t| &t.token_type)
+			}
+			(Stmt::Function(a), Stmt::Function(b)) => functions_eq(a, b),
+			(
+				Stmt::Return { value, .. },
+				Stmt::Return {
+					value: other_value,
+					..
+				},
+			) => match (value, other_value) {
+				(Some(a), Some(b)) => a.structurally_eq(b),
+				(None, None) => true,
+				_ => false,
+			},
+			(
+				Stmt::Class {
+					name,
+					superclass,
+					methods,
+					static_methods,
+					fields,
+				},
+				Stmt::Class {
+					name: other_name,
+					superclass: other_superclass,
+					methods: other_methods,
+					static_methods: other_static_methods,
+					fields: other_fields,
+				},
+			) => {
+				fn all_functions_eq(a: &[FunctionStatement], b: &[FunctionStatement]) -> bool {
+					a.len() == b.len() && a.iter().zip(b).all(|(a, b)| functions_eq(a, b))
+				}
+
+				name.token_type == other_name.token_type
+					&& superclass.as_ref().map(|t| &t.token_type)
+						== other_superclass.as_ref().map(|t| &t.token_type)
+					&& all_functions_eq(methods, other_methods)
+					&& all_functions_eq(static_methods, other_static_methods)
+					&& fields.len() == other_fields.len()
+					&& fields.iter().zip(other_fields).all(|((a, a_init), (b, b_init))| {
+						a.token_type == b.token_type
+							&& match (a_init, b_init) {
+								(Some(a), Some(b)) => a.structurally_eq(b),
+								(None, None) => true,
+								_ => false,
+							}
+					})
+			}
+			(
+				Stmt::Throw { value, .. },
+				Stmt::Throw {
+					value: other_value,
+					..
+				},
+			) => value.structurally_eq(other_value),
+			(
+				Stmt::Try {
+					body,
+					catch_name,
+					catch_body,
+					..
+				},
+				Stmt::Try {
+					body: other_body,
+					catch_name: other_catch_name,
+					catch_body: other_catch_body,
+					..
+				},
+			) => {
+				body.structurally_eq(other_body)
+					&& catch_name.as_ref().map(|t| &t.token_type)
+						== other_catch_name.as_ref().map(|t| &t.token_type)
+					&& catch_body.structurally_eq(other_catch_body)
+			}
+			(
+				Stmt::Assert {
+					condition, message, ..
+				},
+				Stmt::Assert {
+					condition: other_condition,
+					message: other_message,
+					..
+				},
+			) => {
+				condition.structurally_eq(other_condition)
+					&& match (message, other_message) {
+						(Some(a), Some(b)) => a.structurally_eq(b),
+						(None, None) => true,
+						_ => false,
+					}
+			}
+			(
+				Stmt::Import { path, alias, .. },
+				Stmt::Import {
+					path: other_path,
+					alias: other_alias,
+					..
+				},
+			) => {
+				path.token_type == other_path.token_type
+					&& alias.as_ref().map(|t| &t.token_type)
+						== other_alias.as_ref().map(|t| &t.token_type)
+			}
+			_ => false,
+		}
+	}
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
@@ -64,15 +365,16 @@ pub enum Expr {
 		operator: Token,
 		right: Box<Expr>,
 	},
-	Call {
-		callee: Box<Expr>,
-		closing_parenthesis: Token,
-		arguments: Vec<Expr>,
-	},
+	Call(Box<CallExpr>),
 	Get {
 		object: Box<Expr>,
 		name: Token,
+		/// `object?.name`, which is `nil` when the object is `nil` instead of an error.
+		optional: bool,
 	},
+	/// Compound assignments like `a.b += 1` are desugared to `a.b = a.b + 1`, which contains
+	/// `object` twice. That's only allowed when evaluating it has no side effects, see
+	/// [NodeBuilder::is_reevaluable].
 	Set {
 		object: Box<Expr>,
 		name: Token,
@@ -81,20 +383,628 @@ pub enum Expr {
 	This {
 		keyword: Token,
 	},
-	Super {
-		keyword: Token,
-		method: Token,
+	Super(Box<SuperExpr>),
+	SuperCall(Box<SuperCallExpr>),
+	Function(Box<FunctionExpr>),
+	Match(Box<MatchExpr>),
+	Block(Box<BlockExpr>),
+	If(Box<IfExpr>),
+	/// `start..end`, or `start..=end` when inclusive.
+	Range {
+		start: Box<Expr>,
+		end: Box<Expr>,
+		inclusive: bool,
 	},
 }
 
+// The payloads of these are boxed, so that they don't inflate the size of every other `Expr`.
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct CallExpr {
+	pub callee: Expr,
+	pub closing_parenthesis: Token,
+	pub arguments: Vec<Argument>,
+}
+
+/// An argument of a call. `...args` spreads the values of `args` into separate arguments, and
+/// `name: value` passes a named argument. Named arguments come after all the positional ones.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Argument<E = Expr> {
+	pub expr: E,
+	pub spread: bool,
+	pub name: Option<Token>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct SuperExpr {
+	pub keyword: Token,
+	pub method: Token,
+}
+
+/// `super(arguments)`, which runs the initializer of the superclass on `this`.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct SuperCallExpr {
+	pub keyword: Token,
+	pub closing_parenthesis: Token,
+	pub arguments: Vec<Argument>,
+}
+
+/// `match (subject) { pattern => value, ..., _ => default }`. The value of the first arm whose
+/// pattern equals the subject, otherwise the default, or `nil` without one.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct MatchExpr {
+	pub keyword: Token,
+	pub subject: Expr,
+	/// `(pattern, value)` pairs, in source order.
+	pub arms: Vec<(Expr, Expr)>,
+	pub default: Option<Expr>,
+	pub closing_brace: Token,
+}
+
+/// `{ statements value }` in expression position, evaluating the statements in a new scope and
+/// then the trailing value.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct BlockExpr {
+	pub left_brace: Token,
+	pub statements: Vec<Stmt>,
+	pub value: Expr,
+	pub right_brace: Token,
+}
+
+/// `if (condition) then_branch else else_branch` in expression position. Unlike the statement,
+/// it always has an `else`.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct IfExpr {
+	pub keyword: Token,
+	pub condition: Expr,
+	pub then_branch: Expr,
+	pub else_branch: Expr,
+}
+
+/// `fun name(params) { body }` in expression position. The name is optional and only used when
+/// displaying the function.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct FunctionExpr {
+	pub keyword: Token,
+	pub name: Option<Token>,
+	pub params: Vec<Token>,
+	pub body: Vec<Stmt>,
+	pub closing_brace: Token,
+}
+
 impl Display for Expr {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		print_ast(self, f)
 	}
 }
 
-pub struct Parser {
-	tokens: std::iter::Peekable<std::vec::IntoIter<Token>>,
+/// Constructors for building trees by hand, e.g. in tests. Their tokens don't come from any source:
+/// they're all on line 1, with fresh universal indices.
+#[allow(dead_code)]
+impl Expr {
+	fn token(token_type: TokenType) -> Token {
+		Token {
+			lexeme: token_type.to_string(),
+			..Token::synthetic(token_type, 1)
+		}
+	}
+
+	pub fn number(n: f64) -> Expr {
+		Expr::Literal(Expr::token(TokenType::Number(n)))
+	}
+
+	pub fn integer(n: i64) -> Expr {
+		Expr::Literal(Expr::token(TokenType::Integer(n)))
+	}
+
+	pub fn string(s: &str) -> Expr {
+		Expr::Literal(Expr::token(TokenType::String(s.to_string())))
+	}
+
+	pub fn bool(b: bool) -> Expr {
+		Expr::Literal(Expr::token(if b { TokenType::True } else { TokenType::False }))
+	}
+
+	pub fn nil() -> Expr {
+		Expr::Literal(Expr::token(TokenType::Nil))
+	}
+
+	pub fn variable(name: &str) -> Expr {
+		Expr::Variable(Expr::token(TokenType::Identifier(name.to_string())))
+	}
+
+	pub fn unary(operator: TokenType, expr: Expr) -> Expr {
+		Expr::Unary {
+			operator: Expr::token(operator),
+			expr: Box::new(expr),
+		}
+	}
+
+	pub fn binary(left: Expr, operator: TokenType, right: Expr) -> Expr {
+		Expr::Binary {
+			left: Box::new(left),
+			operator: Expr::token(operator),
+			right: Box::new(right),
+		}
+	}
+
+	pub fn group(inner: Expr) -> Expr {
+		Expr::Grouping(Box::new(inner))
+	}
+}
+
+#[allow(dead_code)]
+impl Expr {
+	/// `(start_line, end_line)` of the source the expression was parsed from.
+	pub fn span(&self) -> (usize, usize) {
+		(self.first_token().line, self.last_token().line)
+	}
+
+	/// The value of a number literal, integer or not.
+	pub fn as_number(&self) -> Option<f64> {
+		match self {
+			Expr::Literal(Token {
+				token_type: TokenType::Number(n),
+				..
+			}) => Some(*n),
+			Expr::Literal(Token {
+				token_type: TokenType::Integer(n),
+				..
+			}) => Some(*n as f64),
+			_ => None,
+		}
+	}
+
+	/// The value of a string literal.
+	pub fn as_string(&self) -> Option<&str> {
+		match self {
+			Expr::Literal(Token {
+				token_type: TokenType::String(s),
+				..
+			}) => Some(s),
+			_ => None,
+		}
+	}
+
+	/// The value of a `true` or `false` literal.
+	pub fn as_bool(&self) -> Option<bool> {
+		match self {
+			Expr::Literal(Token {
+				token_type: TokenType::True,
+				..
+			}) => Some(true),
+			Expr::Literal(Token {
+				token_type: TokenType::False,
+				..
+			}) => Some(false),
+			_ => None,
+		}
+	}
+
+	pub fn is_nil(&self) -> bool {
+		matches!(
+			self,
+			Expr::Literal(Token {
+				token_type: TokenType::Nil,
+				..
+			})
+		)
+	}
+
+	/// The number of arguments of a call, as written. A spread argument counts as one, although
+	/// it may stand for any number of values; see [Expr::has_spread_argument].
+	pub fn call_arity(&self) -> Option<usize> {
+		match self {
+			Expr::Call(call) => Some(call.arguments.len()),
+			_ => None,
+		}
+	}
+
+	/// Whether this is a call with a `...spread` argument, whose arity is only known at runtime.
+	pub fn has_spread_argument(&self) -> bool {
+		match self {
+			Expr::Call(call) => call.arguments.iter().any(|argument| argument.spread),
+			_ => false,
+		}
+	}
+
+	/// The leftmost token reachable from this node.
+	pub fn first_token(&self) -> &Token {
+		match self {
+			Expr::Literal(token) | Expr::Variable(token) => token,
+			Expr::Assign { name, .. } => name,
+			Expr::Unary { operator, .. } => operator,
+			Expr::Binary { left, .. } | Expr::Logical { left, .. } => left.first_token(),
+			Expr::Grouping(expr) => expr.first_token(),
+			Expr::Call(call) => call.callee.first_token(),
+			Expr::Get { object, .. } | Expr::Set { object, .. } => object.first_token(),
+			Expr::This { keyword } => keyword,
+			Expr::Super(super_expr) => &super_expr.keyword,
+			Expr::SuperCall(super_call) => &super_call.keyword,
+			Expr::Function(function) => &function.keyword,
+			Expr::Match(match_expr) => &match_expr.keyword,
+			Expr::Block(block) => &block.left_brace,
+			Expr::If(if_expr) => &if_expr.keyword,
+			Expr::Range { start, .. } => start.first_token(),
+		}
+	}
+
+	/// The rightmost token reachable from this node.
+	pub fn last_token(&self) -> &Token {
+		match self {
+			Expr::Literal(token) | Expr::Variable(token) => token,
+			Expr::Assign { value, .. } | Expr::Set { value, .. } => value.last_token(),
+			Expr::Unary { expr, .. } | Expr::Grouping(expr) => expr.last_token(),
+			Expr::Binary { right, .. } | Expr::Logical { right, .. } => right.last_token(),
+			Expr::Call(call) => &call.closing_parenthesis,
+			Expr::Get { name, .. } => name,
+			Expr::This { keyword } => keyword,
+			Expr::Super(super_expr) => &super_expr.method,
+			Expr::SuperCall(super_call) => &super_call.closing_parenthesis,
+			Expr::Function(function) => &function.closing_brace,
+			Expr::Match(match_expr) => &match_expr.closing_brace,
+			Expr::Block(block) => &block.right_brace,
+			Expr::If(if_expr) => if_expr.else_branch.last_token(),
+			Expr::Range { end, .. } => end.last_token(),
+		}
+	}
+
+	/// The name of the variant, e.g. `"Binary"`.
+	pub fn kind_name(&self) -> &'static str {
+		match self {
+			Expr::Literal(_) => "Literal",
+			Expr::Variable(_) => "Variable",
+			Expr::Assign { .. } => "Assign",
+			Expr::Unary { .. } => "Unary",
+			Expr::Binary { .. } => "Binary",
+			Expr::Grouping(_) => "Grouping",
+			Expr::Logical { .. } => "Logical",
+			Expr::Call(_) => "Call",
+			Expr::Get { .. } => "Get",
+			Expr::Set { .. } => "Set",
+			Expr::This { .. } => "This",
+			Expr::Super(_) => "Super",
+			Expr::SuperCall(_) => "SuperCall",
+			Expr::Function(_) => "Function",
+			Expr::Match(_) => "Match",
+			Expr::Block(_) => "Block",
+			Expr::If(_) => "If",
+			Expr::Range { .. } => "Range",
+		}
+	}
+
+	/// Compares two expressions by their shape and token types only. Unlike `==`, which relies on
+	/// the tokens' `universal_index`, this ignores where (and in which parse) the nodes came from.
+	pub fn structurally_eq(&self, other: &Expr) -> bool {
+		fn token_eq(a: &Token, b: &Token) -> bool {
+			a.token_type == b.token_type
+		}
+
+		fn all_eq(a: &[Argument], b: &[Argument]) -> bool {
+			a.len() == b.len()
+				&& a.iter()
+					.zip(b)
+					.all(|(a, b)| {
+						a.spread == b.spread
+							&& a.name.as_ref().map(|t| &t.token_type)
+								== b.name.as_ref().map(|t| &t.token_type)
+							&& a.expr.structurally_eq(&b.expr)
+					})
+		}
+
+		match (self, other) {
+			(Expr::Literal(a), Expr::Literal(b)) | (Expr::Variable(a), Expr::Variable(b)) => {
+				token_eq(a, b)
+			}
+			(
+				Expr::Assign { name, value },
+				Expr::Assign {
+					name: other_name,
+					value: other_value,
+				},
+			) => token_eq(name, other_name) && value.structurally_eq(other_value),
+			(
+				Expr::Unary { operator, expr },
+				Expr::Unary {
+					operator: other_operator,
+					expr: other_expr,
+				},
+			) => token_eq(operator, other_operator) && expr.structurally_eq(other_expr),
+			(
+				Expr::Binary {
+					left,
+					operator,
+					right,
+				},
+				Expr::Binary {
+					left: other_left,
+					operator: other_operator,
+					right: other_right,
+				},
+			)
+			| (
+				Expr::Logical {
+					left,
+					operator,
+					right,
+				},
+				Expr::Logical {
+					left: other_left,
+					operator: other_operator,
+					right: other_right,
+				},
+			) => {
+				token_eq(operator, other_operator)
+					&& left.structurally_eq(other_left)
+					&& right.structurally_eq(other_right)
+			}
+			(Expr::Grouping(expr), Expr::Grouping(other_expr)) => expr.structurally_eq(other_expr),
+			(Expr::Call(call), Expr::Call(other_call)) => {
+				call.callee.structurally_eq(&other_call.callee)
+					&& all_eq(&call.arguments, &other_call.arguments)
+			}
+			(
+				Expr::Get {
+					object,
+					name,
+					optional,
+				},
+				Expr::Get {
+					object: other_object,
+					name: other_name,
+					optional: other_optional,
+				},
+			) => {
+				token_eq(name, other_name)
+					&& optional == other_optional
+					&& object.structurally_eq(other_object)
+			}
+			(
+				Expr::Set {
+					object,
+					name,
+					value,
+				},
+				Expr::Set {
+					object: other_object,
+					name: other_name,
+					value: other_value,
+				},
+			) => {
+				token_eq(name, other_name)
+					&& object.structurally_eq(other_object)
+					&& value.structurally_eq(other_value)
+			}
+			(Expr::This { .. }, Expr::This { .. }) => true,
+			(Expr::Super(super_expr), Expr::Super(other_super_expr)) => {
+				token_eq(&super_expr.method, &other_super_expr.method)
+			}
+			(Expr::SuperCall(super_call), Expr::SuperCall(other_super_call)) => {
+				all_eq(&super_call.arguments, &other_super_call.arguments)
+			}
+			(Expr::Function(function), Expr::Function(other_function)) => {
+				function.name.as_ref().map(|t| &t.token_type)
+					== other_function.name.as_ref().map(|t| &t.token_type)
+					&& function.params.len() == other_function.params.len()
+					&& function
+						.params
+						.iter()
+						.zip(&other_function.params)
+						.all(|(a, b)| token_eq(a, b))
+					&& function.body.len() == other_function.body.len()
+					&& function
+						.body
+						.iter()
+						.zip(&other_function.body)
+						.all(|(a, b)| a.structurally_eq(b))
+			}
+			(Expr::Match(match_expr), Expr::Match(other_match_expr)) => {
+				match_expr.subject.structurally_eq(&other_match_expr.subject)
+					&& match_expr.arms.len() == other_match_expr.arms.len()
+					&& match_expr.arms.iter().zip(&other_match_expr.arms).all(
+						|((pattern, value), (other_pattern, other_value))| {
+							pattern.structurally_eq(other_pattern)
+								&& value.structurally_eq(other_value)
+						},
+					) && match (&match_expr.default, &other_match_expr.default) {
+					(Some(a), Some(b)) => a.structurally_eq(b),
+					(None, None) => true,
+					_ => false,
+				}
+			}
+			(Expr::Block(block), Expr::Block(other_block)) => {
+				block.statements.len() == other_block.statements.len()
+					&& block
+						.statements
+						.iter()
+						.zip(&other_block.statements)
+						.all(|(a, b)| a.structurally_eq(b))
+					&& block.value.structurally_eq(&other_block.value)
+			}
+			(Expr::If(if_expr), Expr::If(other_if_expr)) => {
+				if_expr.condition.structurally_eq(&other_if_expr.condition)
+					&& if_expr.then_branch.structurally_eq(&other_if_expr.then_branch)
+					&& if_expr.else_branch.structurally_eq(&other_if_expr.else_branch)
+			}
+			(
+				Expr::Range {
+					start,
+					end,
+					inclusive,
+				},
+				Expr::Range {
+					start: other_start,
+					end: other_end,
+					inclusive: other_inclusive,
+				},
+			) => {
+				inclusive == other_inclusive
+					&& start.structurally_eq(other_start)
+					&& end.structurally_eq(other_end)
+			}
+			_ => false,
+		}
+	}
+
+	/// Like [Expr::structurally_eq], but treats `(a + b)` and `a + b` as equal. The nesting the
+	/// parentheses produced is still compared, so `(a + b) * c` differs from `a + b * c`.
+	/// As with [transform::map_expr], groupings inside function bodies are kept.
+	pub fn eq_ignoring_grouping(&self, other: &Expr) -> bool {
+		let strip = |expr: &Expr| {
+			transform::map_expr(expr.clone(), &mut |expr| match expr {
+				Expr::Grouping(inner) => *inner,
+				expr => expr,
+			})
+		};
+		strip(self).structurally_eq(&strip(other))
+	}
+}
+
+/// The levels of the expression grammar, from the loosest binding to the tightest. Each level
+/// parses its operands at the next one.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Precedence {
+	Assignment,
+	Coalesce,
+	Or,
+	And,
+	Equality,
+	Range,
+	Comparison,
+	Term,
+	Factor,
+	Unary,
+	Call,
+	Primary,
+}
+
+impl Precedence {
+	/// The level one step tighter than this one. [Precedence::Primary] is the tightest.
+	pub fn next(self) -> Precedence {
+		match self {
+			Precedence::Assignment => Precedence::Coalesce,
+			Precedence::Coalesce => Precedence::Or,
+			Precedence::Or => Precedence::And,
+			Precedence::And => Precedence::Equality,
+			Precedence::Equality => Precedence::Range,
+			Precedence::Range => Precedence::Comparison,
+			Precedence::Comparison => Precedence::Term,
+			Precedence::Term => Precedence::Factor,
+			Precedence::Factor => Precedence::Unary,
+			Precedence::Unary => Precedence::Call,
+			Precedence::Call | Precedence::Primary => Precedence::Primary,
+		}
+	}
+}
+
+/// The level at which `token_type` binds as an infix or postfix operator, or `None` if it isn't
+/// one. `-` is a [Precedence::Term], even though it can be a prefix operator as well.
+pub fn precedence_of(token_type: &TokenType) -> Option<Precedence> {
+	Some(match token_type {
+		TokenType::Equal => Precedence::Assignment,
+		TokenType::QuestionQuestion => Precedence::Coalesce,
+		TokenType::Or => Precedence::Or,
+		TokenType::And => Precedence::And,
+		TokenType::EqualEqual | TokenType::BangEqual => Precedence::Equality,
+		TokenType::DotDot | TokenType::DotDotEqual => Precedence::Range,
+		TokenType::Greater
+		| TokenType::GreaterEqual
+		| TokenType::Less
+		| TokenType::LessEqual
+		| TokenType::Is => Precedence::Comparison,
+		TokenType::Minus | TokenType::Plus => Precedence::Term,
+		TokenType::Slash | TokenType::Star => Precedence::Factor,
+		TokenType::LeftParen | TokenType::Dot | TokenType::QuestionDot => Precedence::Call,
+		_ => return None,
+	})
+}
+
+pub struct Parser<'a, B = Tree> {
+	/// Either an owned or a borrowed token buffer. An owned buffer hands its tokens over as
+	/// they're consumed, while a borrowed one is cloned from, which leaves it intact for the next
+	/// parse.
+	tokens: Cow<'a, [Token]>,
+	/// Index of the next token to consume.
+	pos: usize,
+	/// Errors the parser has recovered from, e.g. malformed class members.
+	errors: Vec<Error>,
+	options: ParserOptions,
+	/// Current nesting level of the recursive expression rules.
+	depth: usize,
+	/// Number of function bodies the parser is currently in.
+	function_depth: usize,
+	/// [TokenType::Comment] tokens, taken out of [Parser::tokens] so that the grammar never sees
+	/// them. In source order.
+	comments: Vec<Token>,
+	/// `(start, end)` positions of the statements parsed so far, for [Parser::parse_events]. Only
+	/// recorded while it's `Some`.
+	stmt_spans: Option<Vec<(usize, usize)>>,
+	/// Whether the top level of a match arm's pattern is being parsed. A `(x)` pattern is a
+	/// grouping followed by the arm's `=>` there, rather than the start of an arrow function.
+	/// Inside brackets, a `=>` can't belong to the arm, so [Parser::bracketed] clears it.
+	in_pattern: bool,
+	/// Where the parsed nodes go.
+	builder: B,
+}
+
+/// Opt-in extensions to the canonical Lox grammar. The default is the strict grammar.
+#[derive(Debug, Clone)]
+pub struct ParserOptions {
+	/// Accept a `,` after the last argument of a call or the last parameter of a function.
+	pub trailing_commas: bool,
+	/// How deep the code can nest before parsing fails with [ErrorKind::MaxDepthExceeded].
+	/// Expressions, blocks, prefix operators, and `if`, `while` and `for` bodies that aren't blocks
+	/// each take a level, so `(1);` is two levels deep. The default is low enough for the parser
+	/// to fit the 8 MiB stack of the main thread in debug builds.
+	pub max_depth: usize,
+	/// Reject `return` outside of a function body with [ErrorKind::ReturnOutsideFunction], instead
+	/// of leaving it to the resolver.
+	pub reject_return_outside_function: bool,
+	/// Reject functions with two parameters of the same name with
+	/// [ErrorKind::DuplicateParameter], instead of leaving it to the resolver.
+	pub reject_duplicate_parameters: bool,
+	/// How many arguments a call, or parameters a function, can have. `None` means no limit.
+	pub max_arguments: Option<usize>,
+	/// Reject bodies of `if`, `while` and `for` that aren't blocks with
+	/// [ErrorKind::ExpectedLeftBrace]. `else if` is still allowed.
+	pub require_braces: bool,
+}
+
+impl Default for ParserOptions {
+	fn default() -> Self {
+		ParserOptions {
+			trailing_commas: false,
+			max_depth: 200,
+			reject_return_outside_function: false,
+			reject_duplicate_parameters: false,
+			max_arguments: Some(255),
+			require_braces: false,
+		}
+	}
+}
+
+/// Everything [Parser::parse_all] found in a program.
+#[derive(Debug)]
+pub struct ParseResult {
+	pub statements: Vec<Stmt>,
+	/// In the order they were found.
+	pub errors: Vec<Error>,
+	/// In source order, as in [Parser::parse_with_comments].
+	pub comments: Vec<Token>,
+}
+
+/// One step of [Parser::parse_events]. Every `Enter*` is matched by an `Exit*`, with the tokens
+/// and nested nodes of the statement or expression in between.
+#[allow(dead_code)]
+#[derive(Debug)]
+pub enum ParseEvent {
+	EnterStmt,
+	ExitStmt,
+	/// The name of the [Expr] variant, e.g. `"Binary"`.
+	EnterExpr(&'static str),
+	ExitExpr,
+	Token(Token),
+	Error(Error),
 }
 
 #[derive(Debug)]
@@ -114,9 +1024,23 @@ pub enum ErrorKind {
 	ExpectedSemicolon,
 	ExpectedIdentifier { place: &'static str },
 	InvalidAssignmentTarget,
-	ExceededArgumentsLimit,
+	ExceededArgumentsLimit { limit: usize },
+	ExceededParametersLimit { limit: usize },
 	ExpectedComma,
 	ExpectedDot,
+	ExpectedEndOfInput,
+	MaxDepthExceeded,
+	ExpectedCatch,
+	CatchWithoutTry,
+	DanglingElse,
+	ReturnOutsideFunction,
+	ExpectedLoopAfterLabel,
+	PositionalAfterNamed,
+	ExpectedFatArrow,
+	ExpectedElse,
+	DuplicateParameter,
+	ExpectedStringLiteral,
+	ExpectedVar,
 }
 
 impl Display for Error {
@@ -134,9 +1058,29 @@ impl Display for Error {
 			ErrorKind::ExpectedLeftBrace => write!(f, "expected `{{` at the end of a block")?,
 			ErrorKind::ExpectedRightBrace => write!(f, "expected `}}` at the end of a block")?,
 			ErrorKind::ExpectedLeftParenthesis => write!(f, "expected `(`")?,
-			ErrorKind::ExceededArgumentsLimit => write!(f, "can't have more than 255 arguments")?,
+			ErrorKind::ExceededArgumentsLimit { limit } => {
+				write!(f, "can't have more than {limit} arguments")?
+			}
+			ErrorKind::ExceededParametersLimit { limit } => {
+				write!(f, "can't have more than {limit} parameters")?
+			}
 			ErrorKind::ExpectedComma => write!(f, "expected `,`")?,
 			ErrorKind::ExpectedDot => write!(f, "expected `.`")?,
+			ErrorKind::ExpectedEndOfInput => write!(f, "expected end of input")?,
+			ErrorKind::MaxDepthExceeded => write!(f, "code nested too deeply")?,
+			ErrorKind::ExpectedCatch => write!(f, "expected `catch` after the `try` block")?,
+			ErrorKind::CatchWithoutTry => write!(f, "`catch` without a preceding `try`")?,
+			ErrorKind::DanglingElse => write!(f, "`else` without a preceding `if`")?,
+			ErrorKind::ReturnOutsideFunction => write!(f, "can't return from top-level code")?,
+			ErrorKind::ExpectedLoopAfterLabel => write!(f, "expected a loop after the label")?,
+			ErrorKind::ExpectedFatArrow => write!(f, "expected `=>` after the pattern")?,
+			ErrorKind::ExpectedElse => write!(f, "expected `else` in an `if` expression")?,
+			ErrorKind::DuplicateParameter => write!(f, "duplicate parameter name")?,
+			ErrorKind::ExpectedStringLiteral => write!(f, "expected a string literal")?,
+			ErrorKind::ExpectedVar => write!(f, "expected `var` after the scope qualifier")?,
+			ErrorKind::PositionalAfterNamed => {
+				write!(f, "positional arguments can't follow named ones")?
+			}
 		}
 		match &self.token {
 			None
@@ -144,6 +1088,12 @@ impl Display for Error {
 				token_type: TokenType::Eof,
 				..
 			}) => write!(f, " at the end")?,
+			// Synthesized tokens have no lexeme, so their type stands in for it.
+			Some(Token {
+				token_type,
+				lexeme,
+				..
+			}) if lexeme.is_empty() => write!(f, " at `{token_type}`")?,
 			Some(Token { lexeme, .. }) => write!(f, " at `{lexeme}`")?,
 		}
 		Ok(())
@@ -152,9 +1102,147 @@ impl Display for Error {
 
 impl std::error::Error for Error {}
 
-macro_rules! expect_token_type {
-	($parser:ident, $pattern:pat) => {{
-		match $parser.tokens.next() {
+/// Renders `errors` as a single report, in source order and separated by blank lines. Each error
+/// is followed by the line of `source` it points at, with a caret under the offending token. The
+/// same error reported more than once at the same position is only included once.
+#[allow(dead_code)]
+pub fn format_errors(errors: &[Error], source: &str) -> String {
+	let lines: Vec<&str> = source.lines().collect();
+	let mut errors: Vec<_> = errors
+		.iter()
+		.map(|error| {
+			let position = match &error.token {
+				Some(token) => (token.line, token.column),
+				// Errors without a position go last.
+				None => (usize::MAX, usize::MAX),
+			};
+			(position, error.to_string(), error.token.as_ref())
+		})
+		.collect();
+	errors.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+	errors.dedup_by(|a, b| a.0 == b.0 && a.1 == b.1);
+
+	let reports: Vec<String> = errors
+		.into_iter()
+		.map(|(_, message, token)| {
+			let Some((token, text)) =
+				token.and_then(|token| Some((token, *lines.get(token.line.checked_sub(1)?)?)))
+			else {
+				return message;
+			};
+			let gutter = " ".repeat(token.line.to_string().len());
+			let mut report = format!("{message}\n{gutter} |\n{} | {text}", token.line);
+			// Synthetic tokens have no column to point at.
+			if token.column > 0 {
+				// Tabs are kept, so that the caret lines up with the text above it.
+				let indent: String = text
+					.chars()
+					.take(token.column - 1)
+					.map(|c| if c == '\t' { '\t' } else { ' ' })
+					.collect();
+				let width = token.lexeme.chars().count().max(1);
+				report += &format!("\n{gutter} | {indent}{}", "^".repeat(width));
+			}
+			report
+		})
+		.collect();
+	reports.join("\n\n")
+}
+
+/// `(first_line, last_line)` of `stmts`, e.g. for a debugger to highlight a block. Only the
+/// tokens kept in the tree count, so the braces of a block, say, are left out. `None` if there
+/// are no such tokens, as in an empty slice.
+#[allow(dead_code)]
+pub fn block_span(stmts: &[Stmt]) -> Option<(usize, usize)> {
+	let (first_line, _) = stmts.iter().find_map(stmt_lines)?;
+	let (_, last_line) = stmts.iter().rev().find_map(stmt_lines)?;
+	Some((first_line, last_line))
+}
+
+/// The lowest and highest line of the tokens reachable from `stmt`.
+fn stmt_lines(stmt: &Stmt) -> Option<(usize, usize)> {
+	let tokens: Vec<&Token> = match stmt {
+		Stmt::Expr(_) | Stmt::Print(_) | Stmt::Block(_) | Stmt::Empty | Stmt::If { .. } => {
+			Vec::new()
+		}
+		Stmt::Var { name, .. } => vec![name],
+		Stmt::While { label, .. } => label.iter().collect(),
+		Stmt::Break { keyword, label } | Stmt::Continue { keyword, label } => {
+			std::iter::once(keyword).chain(label).collect()
+		}
+		Stmt::Function(function) => std::iter::once(&function.name)
+			.chain(&function.params)
+			.collect(),
+		Stmt::Return { keyword, .. }
+		| Stmt::Throw { keyword, .. }
+		| Stmt::Assert { keyword, .. } => vec![keyword],
+		Stmt::Class {
+			name, superclass, ..
+		} => std::iter::once(name).chain(superclass).collect(),
+		Stmt::Try {
+			keyword,
+			catch_name,
+			..
+		} => std::iter::once(keyword).chain(catch_name).collect(),
+		Stmt::Import {
+			keyword,
+			path,
+			alias,
+		} => [keyword, path].into_iter().chain(alias).collect(),
+	};
+	let children = visitor::stmt_children(stmt)
+		.into_iter()
+		.filter_map(|node| match node {
+			visitor::Node::Stmt(stmt) => stmt_lines(stmt),
+			visitor::Node::Expr(expr) => Some(expr.span()),
+		});
+	tokens
+		.into_iter()
+		.map(|token| (token.line, token.line))
+		.chain(children)
+		.reduce(|(first, last), (line, end)| (first.min(line), last.max(end)))
+}
+
+/// Adds the `(start, end)` token positions of every expression under `node` to `spans`, and
+/// returns the one of `node` itself if it's an expression. Unlike [Expr::first_token], this
+/// counts the parentheses of groupings. Synthetic tokens aren't in `positions`, so they're left
+/// out.
+fn expr_spans(
+	node: visitor::Node,
+	positions: &HashMap<u64, usize>,
+	spans: &mut Vec<(usize, usize, ParseEvent)>,
+) -> Option<(usize, usize)> {
+	let (children, expr) = match node {
+		visitor::Node::Stmt(stmt) => (visitor::stmt_children(stmt), None),
+		visitor::Node::Expr(expr) => (visitor::expr_children(expr), Some(expr)),
+	};
+	let mut span: Option<(usize, usize)> = None;
+	let mut extend = |(start, end): (usize, usize)| {
+		span = Some(span.map_or((start, end), |(s, e)| (s.min(start), e.max(end))));
+	};
+	for child in children {
+		if let Some(child_span) = expr_spans(child, positions, spans) {
+			extend(child_span);
+		}
+	}
+	let expr = expr?;
+	for token in [expr.first_token(), expr.last_token()] {
+		if let Some(&pos) = positions.get(&token.universal_index) {
+			extend((pos, pos + 1));
+		}
+	}
+	let (mut start, mut end) = span?;
+	if let Expr::Grouping(_) = expr {
+		start = start.saturating_sub(1);
+		end += 1;
+	}
+	spans.push((start, end, ParseEvent::EnterExpr(expr.kind_name())));
+	Some((start, end))
+}
+
+macro_rules! expect_token_type {
+	($parser:ident, $pattern:pat) => {{
+		match $parser.advance() {
 			Some(token)
 				if matches!(
 					token,
@@ -171,57 +1259,396 @@ macro_rules! expect_token_type {
 	}};
 }
 
-impl Parser {
+/// Moves `token` out of an owned buffer. Only its position and type stay behind, without any
+/// text, which is enough to point an error at it.
+fn take_token(token: &mut Token) -> Token {
+	let token_type = match &token.token_type {
+		TokenType::Identifier(_) => TokenType::Identifier(String::new()),
+		TokenType::String(_) => TokenType::String(String::new()),
+		TokenType::Comment(_) => TokenType::Comment(String::new()),
+		token_type => token_type.clone(),
+	};
+	let consumed = Token {
+		token_type,
+		lexeme: String::new(),
+		line: token.line,
+		column: token.column,
+		universal_index: token.universal_index,
+	};
+	std::mem::replace(token, consumed)
+}
+
+impl<'a> Parser<'a> {
 	pub fn new(tokens: Vec<Token>) -> Self {
+		Parser::from_tokens(tokens)
+	}
+
+	/// Parses tokens from any source, e.g. a lazy scanner. They're still buffered up front, as the
+	/// parser needs to look ahead.
+	pub fn from_tokens<I: IntoIterator<Item = Token>>(tokens: I) -> Self {
+		Parser::with_options(tokens.into_iter().collect(), ParserOptions::default())
+	}
+
+	pub fn with_options(tokens: Vec<Token>, options: ParserOptions) -> Self {
+		Parser::with_buffer(Cow::Owned(tokens), options)
+	}
+
+	/// Parses a borrowed token buffer, e.g. to parse the same tokens more than once. The buffer
+	/// is only copied if it has comments to take out.
+	#[allow(dead_code)]
+	pub fn from_slice(tokens: &'a [Token]) -> Self {
+		Parser::with_buffer(Cow::Borrowed(tokens), ParserOptions::default())
+	}
+
+	fn with_buffer(tokens: Cow<'a, [Token]>, options: ParserOptions) -> Self {
+		let is_comment = |token: &Token| matches!(token.token_type, TokenType::Comment(_));
+		let (tokens, comments) = if tokens.iter().any(is_comment) {
+			let (comments, tokens) = tokens.into_owned().into_iter().partition(is_comment);
+			(Cow::Owned(tokens), comments)
+		} else {
+			(tokens, Vec::new())
+		};
+		Parser {
+			tokens,
+			pos: 0,
+			errors: Vec::new(),
+			options,
+			depth: 0,
+			function_depth: 0,
+			comments,
+			stmt_spans: None,
+			in_pattern: false,
+			builder: Tree,
+		}
+	}
+
+	/// The same parser, but putting the nodes it parses into `builder` instead.
+	fn with_builder<C: NodeBuilder>(self, builder: C) -> Parser<'a, C> {
 		Parser {
-			tokens: tokens.into_iter().peekable(),
+			tokens: self.tokens,
+			pos: self.pos,
+			errors: self.errors,
+			options: self.options,
+			depth: self.depth,
+			function_depth: self.function_depth,
+			comments: self.comments,
+			stmt_spans: self.stmt_spans,
+			in_pattern: self.in_pattern,
+			builder,
+		}
+	}
+
+	/// Parses the whole program. Fails with the first error in it; [Parser::parse_all] reports
+	/// every one.
+	pub fn parse(self) -> Result<Vec<Stmt>, Error> {
+		let result = self.parse_all();
+		match result.errors.into_iter().next() {
+			Some(err) => Err(err),
+			None => Ok(result.statements),
+		}
+	}
+
+	/// Parses the whole program, however broken, e.g. for an editor. After an error, the parser
+	/// synchronizes to the next statement and carries on.
+	pub fn parse_all(mut self) -> ParseResult {
+		let (statements, errors) = self.program();
+		ParseResult {
+			statements,
+			errors,
+			comments: self.comments,
+		}
+	}
+
+	/// Like [Parser::parse], but also hands back the comments of the program, e.g. for a
+	/// formatter. Their line and column tell where they go.
+	#[allow(dead_code)]
+	pub fn parse_with_comments(self) -> Result<(Vec<Stmt>, Vec<Token>), Error> {
+		let result = self.parse_all();
+		match result.errors.into_iter().next() {
+			Some(err) => Err(err),
+			None => Ok((result.statements, result.comments)),
+		}
+	}
+
+	/// Like [Parser::parse], but stores the program in a flat [arena::Ast] instead of a tree of
+	/// boxes. The nodes go straight into the arena, without building the tree first.
+	#[allow(dead_code)]
+	pub fn parse_arena(self) -> Result<arena::Ast, Error> {
+		let mut parser = self.with_builder(arena::Ast::default());
+		let (roots, errors) = parser.program();
+		if let Some(err) = errors.into_iter().next() {
+			return Err(err);
+		}
+		let mut ast = parser.builder;
+		ast.roots = roots;
+		Ok(ast)
+	}
+
+	/// Lazily parses one top-level declaration per [Iterator::next]. After an error, the parser
+	/// synchronizes to the next statement, so iteration can continue past it.
+	#[allow(dead_code)]
+	pub fn statements(mut self) -> impl Iterator<Item = Result<Stmt, Error>> + 'a {
+		let mut pending = Vec::new().into_iter();
+		std::iter::from_fn(move || {
+			if let Some(err) = pending.next() {
+				return Some(Err(err));
+			}
+			if self.is_at_end() {
+				return None;
+			}
+			let declaration = self.declaration();
+			if declaration.is_err() {
+				self.synchronize();
+			}
+			if self.errors.is_empty() {
+				return Some(declaration);
+			}
+			// A declaration with recovered errors is yielded as those errors instead.
+			let mut errors = std::mem::take(&mut self.errors);
+			errors.extend(declaration.err());
+			pending = errors.into_iter();
+			pending.next().map(Err)
+		})
+	}
+
+	/// Like [Parser::statements], but flattens each declaration into [ParseEvent]s, e.g. for
+	/// tools that keep their own tree up to date. Every consumed token is reported, inside the
+	/// statements and expressions it belongs to. A declaration that fails to parse is reported as
+	/// its bare tokens, followed by its errors.
+	#[allow(dead_code)]
+	pub fn parse_events(mut self) -> impl Iterator<Item = ParseEvent> + 'a {
+		// The parser gives up the tokens of an owned buffer as it consumes them, so the events
+		// are made from a copy. A borrowed buffer is just borrowed again.
+		let tokens = self.tokens.clone();
+		let positions: HashMap<u64, usize> = tokens
+			.iter()
+			.enumerate()
+			.map(|(pos, token)| (token.universal_index, pos))
+			.collect();
+		self.stmt_spans = Some(Vec::new());
+		let mut pending = Vec::new().into_iter();
+		std::iter::from_fn(move || loop {
+			if let Some(event) = pending.next() {
+				return Some(event);
+			}
+			if self.is_at_end() {
+				return None;
+			}
+			let start = self.pos;
+			let declaration = self.declaration();
+			if declaration.is_err() {
+				self.synchronize();
+			}
+
+			let stmt_spans = self.stmt_spans.replace(Vec::new()).unwrap_or_default();
+			let mut spans = Vec::new();
+			if let Ok(stmt) = &declaration {
+				spans.extend(stmt_spans.into_iter().map(|(s, e)| (s, e, ParseEvent::EnterStmt)));
+				// A statement that falls through from `declaration` to `statement` is recorded by
+				// both.
+				spans.dedup_by_key(|(s, e, _)| (*s, *e));
+				expr_spans(visitor::Node::Stmt(stmt), &positions, &mut spans);
+			}
+			// Outer nodes first.
+			spans.sort_by_key(|(s, e, _)| (*s, std::cmp::Reverse(*e)));
+
+			let mut events = Vec::new();
+			let mut open: Vec<(usize, ParseEvent)> = Vec::new();
+			let mut spans = spans.into_iter().peekable();
+			for pos in start..self.pos {
+				while open.last().is_some_and(|(end, _)| *end <= pos) {
+					events.extend(open.pop().map(|(_, exit)| exit));
+				}
+				while let Some((_, end, enter)) = spans.next_if(|(s, _, _)| *s == pos) {
+					let exit = match enter {
+						ParseEvent::EnterStmt => ParseEvent::ExitStmt,
+						_ => ParseEvent::ExitExpr,
+					};
+					events.push(enter);
+					open.push((end, exit));
+				}
+				events.push(ParseEvent::Token(tokens[pos].clone()));
+			}
+			events.extend(open.into_iter().rev().map(|(_, exit)| exit));
+			events.extend(std::mem::take(&mut self.errors).into_iter().map(ParseEvent::Error));
+			events.extend(declaration.err().map(ParseEvent::Error));
+			pending = events.into_iter();
+		})
+	}
+
+	/// Parses the whole input as a single expression, with no trailing `;`.
+	#[allow(dead_code)]
+	pub fn parse_expression(mut self) -> Result<Expr, Error> {
+		let expr = self.expression()?;
+		if !self.errors.is_empty() {
+			return Err(self.errors.remove(0));
+		}
+		match self.advance() {
+			Some(Token {
+				token_type: TokenType::Eof,
+				..
+			})
+			| None => Ok(expr),
+			token => Err(Error {
+				kind: ErrorKind::ExpectedEndOfInput,
+				token,
+			}),
 		}
 	}
+}
+
+/// A function's parameters, body and closing brace.
+type FunctionParts<S> = (Vec<Token>, Vec<S>, Token);
+
+/// A call's arguments and closing parenthesis.
+type ArgumentList<E> = (Vec<Argument<E>>, Token);
 
-	pub fn parse(mut self) -> Result<Vec<Stmt>, Error> {
+impl<'a, B: NodeBuilder> Parser<'a, B> {
+	/// The declarations that parsed, and every error. A declaration is left out if it fails to
+	/// parse, but kept if it only has errors the parser recovered from.
+	fn program(&mut self) -> (Vec<B::Stmt>, Vec<Error>) {
 		let mut statements = Vec::new();
-		while self
-			.tokens
-			.peek()
-			.map(|t| !matches!(t.token_type, TokenType::Eof))
-			.unwrap_or_default()
-		{
-			//TODO(aqatl): if this fails, we should call [self.synchronize]
-			let declaration = self.declaration()?;
-			statements.push(declaration);
+		let mut errors = Vec::new();
+		while !self.is_at_end() {
+			let declaration = self.declaration();
+			errors.append(&mut self.errors);
+			match declaration {
+				Ok(declaration) => statements.push(declaration),
+				Err(err) => {
+					errors.push(err);
+					self.synchronize();
+				}
+			}
+		}
+		(statements, errors)
+	}
+
+	/// Whether all the tokens, up to [TokenType::Eof], have been consumed.
+	pub fn is_at_end(&self) -> bool {
+		self.peek()
+			.map(|t| matches!(t.token_type, TokenType::Eof))
+			.unwrap_or(true)
+	}
+
+	/// Type of the next token, without consuming it.
+	#[allow(dead_code)]
+	pub fn peek_type(&self) -> Option<&TokenType> {
+		self.peek().map(|t| &t.token_type)
+	}
+
+	/// The next token, without consuming it.
+	fn peek(&self) -> Option<&Token> {
+		self.tokens.get(self.pos)
+	}
+
+	/// The token after [Parser::peek], without consuming either. Rules like labels (`name:`) need
+	/// this second token of lookahead to tell themselves apart from expressions.
+	fn peek_next(&self) -> Option<&Token> {
+		self.tokens.get(self.pos + 1)
+	}
+
+	/// The most recently consumed token. From an owned buffer, that's only what [take_token]
+	/// left behind.
+	fn previous(&self) -> Option<&Token> {
+		self.pos.checked_sub(1).and_then(|pos| self.tokens.get(pos))
+	}
+
+	/// Consumes the next token.
+	fn advance(&mut self) -> Option<Token> {
+		let token = match &mut self.tokens {
+			Cow::Owned(tokens) => tokens.get_mut(self.pos).map(take_token),
+			Cow::Borrowed(tokens) => tokens.get(self.pos).cloned(),
+		};
+		if token.is_some() {
+			self.pos += 1;
 		}
-		Ok(statements)
+		token
+	}
+
+	/// Whether the next token is of the same kind as `token_type`. Payloads of identifiers and
+	/// literals aren't compared.
+	fn check(&self, token_type: &TokenType) -> bool {
+		self.peek().is_some_and(|t| {
+			std::mem::discriminant(&t.token_type) == std::mem::discriminant(token_type)
+		})
 	}
 
-	fn declaration(&mut self) -> Result<Stmt, Error> {
-		match self.tokens.peek() {
+	fn declaration(&mut self) -> Result<B::Stmt, Error> {
+		let start = self.pos;
+		let declaration = match self.peek() {
+			// Without a name, it's a function expression.
 			Some(Token {
 				token_type: TokenType::Fun,
 				..
-			}) => {
-				let _ = self.tokens.next().unwrap();
+			}) if matches!(
+				self.peek_next(),
+				Some(Token {
+					token_type: TokenType::Identifier(_),
+					..
+				})
+			) =>
+			{
+				let _ = self.advance().unwrap();
 				self.function("function")
+					.map(|function| self.builder.build_stmt(StmtKind::Function(function)))
 			}
 			Some(Token {
 				token_type: TokenType::Var,
 				..
 			}) => {
-				let _ = self.tokens.next().unwrap();
-				self.var_declaration()
+				let _ = self.advance().unwrap();
+				self.var_declaration(VarScope::Unqualified)
+			}
+			Some(Token {
+				token_type: TokenType::Global | TokenType::Local,
+				..
+			}) => {
+				let qualifier = self.advance().unwrap();
+				let scope = match qualifier.token_type {
+					TokenType::Global => VarScope::Global,
+					_ => VarScope::Local,
+				};
+				expect_token_type!(self, TokenType::Var).map_err(|token| Error {
+					kind: ErrorKind::ExpectedVar,
+					token,
+				})?;
+				self.var_declaration(scope)
 			}
 			Some(Token {
 				token_type: TokenType::Class,
 				..
 			}) => {
-				let _ = self.tokens.next().unwrap();
+				let _ = self.advance().unwrap();
 				self.class_declaration()
 			}
 			_ => self.statement(),
+		};
+		if declaration.is_ok() {
+			self.record_stmt(start);
+		}
+		declaration
+	}
+
+	/// Records that a statement spans from `start` to the current position, for
+	/// [Parser::parse_events].
+	fn record_stmt(&mut self, start: usize) {
+		if let Some(spans) = &mut self.stmt_spans {
+			spans.push((start, self.pos));
 		}
 	}
 
-	fn var_declaration(&mut self) -> Result<Stmt, Error> {
-		let name = match self.tokens.next() {
+	fn var_declaration(&mut self, scope: VarScope) -> Result<B::Stmt, Error> {
+		let (name, initializer) = self.variable("variable")?;
+		Ok(self.builder.build_stmt(StmtKind::Var {
+			name,
+			initializer,
+			scope,
+		}))
+	}
+
+	/// The part of a variable or field declaration after `var`.
+	fn variable(&mut self, place: &'static str) -> Result<(Token, Option<B::Expr>), Error> {
+		let name = match self.advance() {
 			Some(
 				t @ Token {
 					token_type: TokenType::Identifier(_),
@@ -230,13 +1657,13 @@ impl Parser {
 			) => t,
 			t => {
 				return Err(Error {
-					kind: ErrorKind::ExpectedIdentifier { place: "variable" },
+					kind: ErrorKind::ExpectedIdentifier { place },
 					token: t,
 				})
 			}
 		};
 
-		let initializer = match self.tokens.next() {
+		let initializer = match self.advance() {
 			Some(Token {
 				token_type: TokenType::Equal,
 				..
@@ -254,7 +1681,7 @@ impl Parser {
 		};
 
 		if initializer.is_some() {
-			match self.tokens.next() {
+			match self.advance() {
 				Some(Token {
 					token_type: TokenType::Semicolon,
 					..
@@ -268,21 +1695,21 @@ impl Parser {
 			}
 		}
 
-		Ok(Stmt::Var { name, initializer })
+		Ok((name, initializer))
 	}
 
-	fn class_declaration(&mut self) -> Result<Stmt, Error> {
+	fn class_declaration(&mut self) -> Result<B::Stmt, Error> {
 		let name = expect_token_type!(self, TokenType::Identifier(_)).map_err(|token| Error {
 			kind: ErrorKind::ExpectedIdentifier { place: "class" },
 			token,
 		})?;
 
-		let superclass = match self.tokens.peek() {
+		let superclass = match self.peek() {
 			Some(Token {
 				token_type: TokenType::Less,
 				..
 			}) => {
-				let _ = self.tokens.next();
+				let _ = self.advance();
 				Some(
 					expect_token_type!(self, TokenType::Identifier(_)).map_err(|token| Error {
 						kind: ErrorKind::ExpectedIdentifier {
@@ -301,21 +1728,26 @@ impl Parser {
 		})?;
 
 		let mut methods = Vec::new();
+		let mut static_methods = Vec::new();
+		let mut fields = Vec::new();
 
-		loop {
-			if let Some(Token {
-				token_type: TokenType::RightBrace | TokenType::Eof,
-				..
-			})
-			| None = self.tokens.peek()
-			{
-				break;
-			}
-			let method = match self.function("method")? {
-				Stmt::Function(v) => v,
-				_ => panic!(),
+		while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+			let start = self.pos;
+			let member = if self.check(&TokenType::Var) {
+				let _ = self.advance();
+				self.variable("field").map(|field| fields.push(field))
+			} else if self.check(&TokenType::Class) {
+				let _ = self.advance();
+				self.function("method").map(|method| static_methods.push(method))
+			} else {
+				self.function("method").map(|method| methods.push(method))
 			};
-			methods.push(method);
+			if let Err(err) = member {
+				// Keep going, so that every malformed member gets reported.
+				self.errors.push(err);
+				self.pos = start;
+				self.synchronize_member();
+			}
 		}
 
 		expect_token_type!(self, TokenType::RightBrace).map_err(|token| Error {
@@ -323,46 +1755,80 @@ impl Parser {
 			token,
 		})?;
 
-		Ok(Stmt::Class {
+		Ok(self.builder.build_stmt(StmtKind::Class {
 			name,
 			superclass,
 			methods,
-		})
+			static_methods,
+			fields,
+		}))
 	}
 
-	fn function(&mut self, place: &'static str) -> Result<Stmt, Error> {
+	fn function(&mut self, place: &'static str) -> Result<FunctionStatement<B::Stmt>, Error> {
 		let name = expect_token_type!(self, TokenType::Identifier(_)).map_err(|token| Error {
 			kind: ErrorKind::ExpectedIdentifier { place },
 			token,
 		})?;
+		let (params, body, _) = self.parameters_and_body()?;
+		Ok(FunctionStatement { name, params, body })
+	}
 
+	/// Everything after the function name: `(params) { body }`. Also returns the closing brace.
+	fn parameters_and_body(&mut self) -> Result<FunctionParts<B::Stmt>, Error> {
 		expect_token_type!(self, TokenType::LeftParen).map_err(|token| Error {
 			kind: ErrorKind::ExpectedLeftParenthesis,
 			token,
 		})?;
+		let params = self.parameters()?;
+
+		expect_token_type!(self, TokenType::LeftBrace).map_err(|token| Error {
+			kind: ErrorKind::ExpectedLeftBrace,
+			token,
+		})?;
+
+		self.function_depth += 1;
+		let body = self.block_with_end();
+		// Restored even on errors, which the parser may recover from.
+		self.function_depth -= 1;
+		let (body, closing_brace) = body?;
+
+		Ok((params, body, closing_brace))
+	}
 
+	/// A parameter list, after the `(`, up to and including the `)`.
+	fn parameters(&mut self) -> Result<Vec<Token>, Error> {
 		let mut params = Vec::new();
 
 		loop {
-			if params.len() >= 255 {
-				return Err(Error {
-					kind: ErrorKind::ExceededArgumentsLimit,
-					token: params.pop(),
-				});
-			}
-			match self.tokens.next() {
+			match self.advance() {
 				Some(Token {
 					token_type: TokenType::RightParen,
 					..
-				}) => break,
+				}) if params.is_empty() || self.options.trailing_commas => break,
 				Some(
 					token @ Token {
 						token_type: TokenType::Identifier(_),
 						..
 					},
 				) => {
+					let limit = self.options.max_arguments;
+					if let Some(limit) = limit.filter(|&limit| params.len() >= limit) {
+						return Err(Error {
+							kind: ErrorKind::ExceededParametersLimit { limit },
+							token: Some(token),
+						});
+					}
+					if self.options.reject_duplicate_parameters
+						&& params.iter().any(|param: &Token| param.lexeme == token.lexeme)
+					{
+						// The parameter list itself is well-formed, so parsing can carry on.
+						self.errors.push(Error {
+							kind: ErrorKind::DuplicateParameter,
+							token: Some(token.clone()),
+						});
+					}
 					params.push(token);
-					match self.tokens.next() {
+					match self.advance() {
 						Some(Token {
 							token_type: TokenType::Comma,
 							..
@@ -387,48 +1853,102 @@ impl Parser {
 				}
 			}
 		}
-
-		expect_token_type!(self, TokenType::LeftBrace).map_err(|token| Error {
-			kind: ErrorKind::ExpectedLeftBrace,
-			token,
-		})?;
-
-		let body = self.block()?;
-
-		Ok(Stmt::Function(FunctionStatement { name, params, body }))
+		Ok(params)
 	}
 
-	fn statement(&mut self) -> Result<Stmt, Error> {
-		match self.tokens.peek().map(|t| &t.token_type) {
+	fn statement(&mut self) -> Result<B::Stmt, Error> {
+		let start = self.pos;
+		let statement = match self.peek().map(|t| &t.token_type) {
 			Some(TokenType::If) => {
-				let _ = self.tokens.next().unwrap();
+				let _ = self.advance().unwrap();
 				self.if_statement()
 			}
 			Some(TokenType::Print) => {
-				let _ = self.tokens.next().unwrap();
+				let _ = self.advance().unwrap();
 				self.print_statement()
 			}
 			Some(TokenType::While) => {
-				let _ = self.tokens.next().unwrap();
-				self.while_statement()
+				let _ = self.advance().unwrap();
+				self.while_statement(None)
 			}
 			Some(TokenType::For) => {
-				let _ = self.tokens.next().unwrap();
-				self.for_statement()
+				let keyword = self.advance().unwrap();
+				self.for_statement(keyword, None)
+			}
+			Some(TokenType::Identifier(_))
+				if self.peek_next().is_some_and(|t| t.token_type == TokenType::Colon) =>
+			{
+				let label = self.advance().unwrap();
+				let _ = self.advance();
+				self.labeled_statement(label)
+			}
+			Some(TokenType::Break | TokenType::Continue) => {
+				let keyword = self.advance().unwrap();
+				self.loop_control_statement(keyword)
 			}
 			Some(TokenType::LeftBrace) => {
-				let _ = self.tokens.next().unwrap();
-				self.block().map(Stmt::Block)
+				let _ = self.advance().unwrap();
+				self.block()
+					.map(|statements| self.builder.build_stmt(StmtKind::Block(statements)))
+			}
+			Some(TokenType::Semicolon) => {
+				let _ = self.advance().unwrap();
+				Ok(self.builder.build_stmt(StmtKind::Empty))
 			}
 			Some(TokenType::Return) => {
-				let keyword = self.tokens.next().unwrap();
+				let keyword = self.advance().unwrap();
 				self.return_statement(keyword)
 			}
+			Some(TokenType::Throw) => {
+				let keyword = self.advance().unwrap();
+				self.throw_statement(keyword)
+			}
+			Some(TokenType::Try) => {
+				let keyword = self.advance().unwrap();
+				self.try_statement(keyword)
+			}
+			Some(TokenType::Assert) => {
+				let keyword = self.advance().unwrap();
+				self.assert_statement(keyword)
+			}
+			Some(TokenType::Import) => {
+				let keyword = self.advance().unwrap();
+				self.import_statement(keyword)
+			}
+			Some(TokenType::Catch) => Err(Error {
+				kind: ErrorKind::CatchWithoutTry,
+				token: self.advance(),
+			}),
+			Some(TokenType::Else) => Err(Error {
+				kind: ErrorKind::DanglingElse,
+				token: self.advance(),
+			}),
 			_ => self.expression_statement(),
+		};
+		if statement.is_ok() {
+			self.record_stmt(start);
 		}
+		statement
 	}
 
-	fn if_statement(&mut self) -> Result<Stmt, Error> {
+	/// The body of an `if`, `while` or `for`. A block with [ParserOptions::require_braces], or any
+	/// statement otherwise.
+	fn body(&mut self) -> Result<B::Stmt, Error> {
+		if self.options.require_braces && !self.check(&TokenType::LeftBrace) {
+			return Err(Error {
+				kind: ErrorKind::ExpectedLeftBrace,
+				token: self.peek().cloned(),
+			});
+		}
+		// A block counts as a nesting level by itself.
+		if self.check(&TokenType::LeftBrace) {
+			self.statement()
+		} else {
+			self.nested(Self::statement)
+		}
+	}
+
+	fn if_statement(&mut self) -> Result<B::Stmt, Error> {
 		expect_token_type!(self, TokenType::LeftParen).map_err(|token| Error {
 			kind: ErrorKind::ExpectedLeftParenthesis,
 			token,
@@ -439,42 +1959,106 @@ impl Parser {
 			token,
 		})?;
 
-		let then_branch = Box::new(self.statement()?);
-		let else_branch = match self.tokens.peek() {
-			Some(Token {
-				token_type: TokenType::Else,
-				..
-			}) => {
-				let _ = self.tokens.next();
-				Some(Box::new(self.statement()?))
-			}
-			_ => None,
+		let then_branch = self.body()?;
+		let else_branch = if self.check(&TokenType::Else) {
+			let _ = self.advance();
+			// `else if` is fine without braces, like any other `if` statement.
+			let else_branch = if self.check(&TokenType::If) {
+				self.nested(Self::statement)?
+			} else {
+				self.body()?
+			};
+			Some(else_branch)
+		} else {
+			None
 		};
 
-		Ok(Stmt::If {
+		Ok(self.builder.build_stmt(StmtKind::If {
 			condition,
 			then_branch,
 			else_branch,
-		})
+		}))
 	}
 
-	fn print_statement(&mut self) -> Result<Stmt, Error> {
-		let value = self.expression()?;
-		//TODO(aqatl): report token
-		let token = self.tokens.next().ok_or(Error {
+	fn print_statement(&mut self) -> Result<B::Stmt, Error> {
+		let mut values = vec![self.expression()?];
+		loop {
+			match self.advance() {
+				Some(Token {
+					token_type: TokenType::Comma,
+					..
+				}) => values.push(self.expression()?),
+				Some(Token {
+					token_type: TokenType::Semicolon,
+					..
+				}) => break,
+				// Another value right after the previous one is most likely a missing `,`.
+				token @ Some(Token {
+					token_type:
+						TokenType::Identifier(_)
+						| TokenType::Number(_)
+						| TokenType::Integer(_)
+						| TokenType::String(_)
+						| TokenType::True
+						| TokenType::False
+						| TokenType::Nil
+						| TokenType::Undefined
+						| TokenType::This
+						| TokenType::Super,
+					..
+				}) => {
+					return Err(Error {
+						kind: ErrorKind::ExpectedComma,
+						token,
+					})
+				}
+				token => {
+					return Err(Error {
+						kind: ErrorKind::ExpectedSemicolon,
+						token,
+					})
+				}
+			}
+		}
+		Ok(self.builder.build_stmt(StmtKind::Print(values)))
+	}
+
+	/// The loop following `label:`.
+	fn labeled_statement(&mut self, label: Token) -> Result<B::Stmt, Error> {
+		match self.peek().map(|t| &t.token_type) {
+			Some(TokenType::While) => {
+				let _ = self.advance().unwrap();
+				self.while_statement(Some(label))
+			}
+			Some(TokenType::For) => {
+				let keyword = self.advance().unwrap();
+				self.for_statement(keyword, Some(label))
+			}
+			_ => Err(Error {
+				kind: ErrorKind::ExpectedLoopAfterLabel,
+				token: self.advance(),
+			}),
+		}
+	}
+
+	/// `break` and `continue`. Labels aren't checked against the enclosing loops here.
+	fn loop_control_statement(&mut self, keyword: Token) -> Result<B::Stmt, Error> {
+		let label = match self.peek().map(|t| &t.token_type) {
+			Some(TokenType::Identifier(_)) => self.advance(),
+			_ => None,
+		};
+		expect_token_type!(self, TokenType::Semicolon).map_err(|token| Error {
 			kind: ErrorKind::ExpectedSemicolon,
-			token: None,
+			token,
 		})?;
-		if !matches!(token.token_type, TokenType::Semicolon) {
-			return Err(Error {
-				kind: ErrorKind::ExpectedSemicolon,
-				token: None,
-			});
-		}
-		Ok(Stmt::Print(value))
+		let statement = match keyword.token_type {
+			TokenType::Break => StmtKind::Break { keyword, label },
+			_ => StmtKind::Continue { keyword, label },
+		};
+		Ok(self.builder.build_stmt(statement))
 	}
 
-	fn while_statement(&mut self) -> Result<Stmt, Error> {
+	fn while_statement(&mut self, label: Option<Token>) -> Result<B::Stmt, Error> {
 		expect_token_type!(self, TokenType::LeftParen).map_err(|token| Error {
 			kind: ErrorKind::ExpectedLeftParenthesis,
 			token,
@@ -484,38 +2068,39 @@ impl Parser {
 			kind: ErrorKind::ExpectedRightParenthesis,
 			token,
 		})?;
-		let body = self.statement()?;
-		Ok(Stmt::While {
+		let body = self.body()?;
+		Ok(self.builder.build_stmt(StmtKind::While {
 			condition,
-			body: Box::new(body),
-		})
+			body,
+			label,
+		}))
 	}
 
-	fn for_statement(&mut self) -> Result<Stmt, Error> {
+	fn for_statement(&mut self, keyword: Token, label: Option<Token>) -> Result<B::Stmt, Error> {
 		expect_token_type!(self, TokenType::LeftParen).map_err(|token| Error {
 			kind: ErrorKind::ExpectedLeftParenthesis,
 			token,
 		})?;
 
-		let initializer = match self.tokens.peek() {
+		let initializer = match self.peek() {
 			Some(Token {
 				token_type: TokenType::Semicolon,
 				..
 			}) => {
-				let _ = self.tokens.next();
+				let _ = self.advance();
 				None
 			}
 			Some(Token {
 				token_type: TokenType::Var,
 				..
 			}) => {
-				let _ = self.tokens.next();
-				Some(self.var_declaration()?)
+				let _ = self.advance();
+				Some(self.var_declaration(VarScope::Unqualified)?)
 			}
 			_ => Some(self.expression_statement()?),
 		};
 
-		let condition = match self.tokens.peek() {
+		let condition = match self.peek() {
 			Some(Token {
 				token_type: TokenType::Semicolon,
 				..
@@ -528,7 +2113,7 @@ impl Parser {
 			token,
 		})?;
 
-		let increment = match self.tokens.peek() {
+		let increment = match self.peek() {
 			Some(Token {
 				token_type: TokenType::RightParen,
 				..
@@ -541,83 +2126,77 @@ impl Parser {
 			token,
 		})?;
 
-		let mut body = self.statement()?;
+		let mut body = self.body()?;
 
 		// desugar into while loop
 
 		if let Some(increment) = increment {
-			body = Stmt::Block(vec![body, Stmt::Expr(increment)]);
+			let increment = self.builder.build_stmt(StmtKind::Expr(increment));
+			body = self.builder.build_stmt(StmtKind::Block(vec![body, increment]));
 		}
 
-		let condition = condition.unwrap_or_else(|| {
-			Expr::Literal(Token {
-				token_type: TokenType::True,
-				lexeme: "".to_string(),
-				line: 1,
-				universal_index: next_universal_index(),
-			})
+		let condition = match condition {
+			Some(condition) => condition,
+			// Without a condition the loop runs until something breaks out of it. The `true`
+			// standing in for the condition is reported at the `for`.
+			None => self.builder.build_expr(ExprKind::Literal(
+				Token::synthetic(TokenType::True, 0).with_line(keyword.line),
+			)),
+		};
+
+		body = self.builder.build_stmt(StmtKind::While {
+			condition,
+			body,
+			label,
 		});
 
 		if let Some(initializer) = initializer {
-			body = Stmt::Block(vec![
-				initializer,
-				Stmt::While {
-					condition,
-					body: Box::new(body),
-				},
-			]);
+			body = self.builder.build_stmt(StmtKind::Block(vec![initializer, body]));
 		}
 
 		Ok(body)
 	}
 
-	fn block(&mut self) -> Result<Vec<Stmt>, Error> {
-		let mut statements = Vec::new();
+	fn block(&mut self) -> Result<Vec<B::Stmt>, Error> {
+		self.block_with_end().map(|(statements, _)| statements)
+	}
 
-		loop {
-			match self.tokens.peek() {
-				Some(Token {
-					token_type: TokenType::RightBrace,
-					..
-				})
-				| None => {
-					break;
-				}
-				_ => (),
+	/// [Parser::block] that also returns the closing brace.
+	fn block_with_end(&mut self) -> Result<(Vec<B::Stmt>, Token), Error> {
+		self.nested(|parser| {
+			let mut statements = Vec::new();
+
+			while !parser.check(&TokenType::RightBrace) && !parser.is_at_end() {
+				let statement = parser.declaration()?;
+				statements.push(statement);
 			}
-			let statement = self.declaration()?;
-			statements.push(statement);
-		}
-
-		let token = self.tokens.next();
-		if !matches!(
-			token,
-			Some(Token {
-				token_type: TokenType::RightBrace,
-				..
-			})
-		) {
-			return Err(Error {
-				kind: ErrorKind::ExpectedRightBrace,
-				token,
-			});
-		}
 
-		Ok(statements)
+			if parser.is_at_end() {
+				// Point at the last token of the block rather than at the end of the input, which
+				// would lose the line.
+				return Err(Error {
+					kind: ErrorKind::ExpectedRightBrace,
+					token: parser.previous().cloned(),
+				});
+			}
+			Ok((statements, parser.advance().unwrap()))
+		})
 	}
 
-	fn return_statement(&mut self, keyword: Token) -> Result<Stmt, Error> {
-		let value = match self.tokens.peek() {
+	fn return_statement(&mut self, keyword: Token) -> Result<B::Stmt, Error> {
+		if self.options.reject_return_outside_function && self.function_depth == 0 {
+			// The statement itself is well-formed, so parsing can carry on past it.
+			self.errors.push(Error {
+				kind: ErrorKind::ReturnOutsideFunction,
+				token: Some(keyword.clone()),
+			});
+		}
+		let value = match self.peek() {
 			Some(Token {
 				token_type: TokenType::Semicolon,
 				..
-			}) => Expr::Literal(Token {
-				token_type: TokenType::Nil,
-				lexeme: keyword.lexeme.clone(),
-				line: keyword.line,
-				universal_index: next_universal_index(),
-			}),
-			_ => self.expression()?,
+			}) => None,
+			_ => Some(self.expression()?),
 		};
 
 		expect_token_type!(self, TokenType::Semicolon).map_err(|token| Error {
@@ -625,221 +2204,311 @@ impl Parser {
 			token,
 		})?;
 
-		Ok(Stmt::Return { keyword, value })
+		Ok(self.builder.build_stmt(StmtKind::Return { keyword, value }))
 	}
 
-	fn expression_statement(&mut self) -> Result<Stmt, Error> {
-		let expr = self.expression()?;
-		let token = self.tokens.next().ok_or(Error {
+	fn throw_statement(&mut self, keyword: Token) -> Result<B::Stmt, Error> {
+		let value = self.expression()?;
+		expect_token_type!(self, TokenType::Semicolon).map_err(|token| Error {
 			kind: ErrorKind::ExpectedSemicolon,
-			token: None,
+			token,
 		})?;
-		if !matches!(token.token_type, TokenType::Semicolon) {
-			return Err(Error {
-				kind: ErrorKind::ExpectedSemicolon,
-				token: None,
-			});
-		}
-		Ok(Stmt::Expr(expr))
+		Ok(self.builder.build_stmt(StmtKind::Throw { keyword, value }))
 	}
 
-	fn expression(&mut self) -> Result<Expr, Error> {
-		self.assignment()
+	fn import_statement(&mut self, keyword: Token) -> Result<B::Stmt, Error> {
+		let path = expect_token_type!(self, TokenType::String(_)).map_err(|token| Error {
+			kind: ErrorKind::ExpectedStringLiteral,
+			token,
+		})?;
+		let alias = if self.check(&TokenType::As) {
+			let _ = self.advance();
+			Some(
+				expect_token_type!(self, TokenType::Identifier(_)).map_err(|token| Error {
+					kind: ErrorKind::ExpectedIdentifier { place: "alias" },
+					token,
+				})?,
+			)
+		} else {
+			None
+		};
+		expect_token_type!(self, TokenType::Semicolon).map_err(|token| Error {
+			kind: ErrorKind::ExpectedSemicolon,
+			token,
+		})?;
+		Ok(self.builder.build_stmt(StmtKind::Import {
+			keyword,
+			path,
+			alias,
+		}))
 	}
 
-	fn assignment(&mut self) -> Result<Expr, Error> {
-		let expr = self.or()?;
-
-		match self.tokens.peek() {
-			Some(Token {
-				token_type: TokenType::Equal,
-				..
-			}) => {
-				let equals = self.tokens.next();
-				let value = self.assignment()?;
-				match expr {
-					Expr::Variable(name) => Ok(Expr::Assign {
-						name,
-						value: Box::new(value),
-					}),
-					Expr::Get { object, name } => Ok(Expr::Set {
-						object,
-						name,
-						value: Box::new(value),
-					}),
-					_ => Err(Error {
-						kind: ErrorKind::InvalidAssignmentTarget,
-						token: equals,
-					}),
-				}
-			}
-			_ => Ok(expr),
-		}
+	fn assert_statement(&mut self, keyword: Token) -> Result<B::Stmt, Error> {
+		let condition = self.expression()?;
+		let message = if self.check(&TokenType::Comma) {
+			let _ = self.advance();
+			Some(self.expression()?)
+		} else {
+			None
+		};
+		expect_token_type!(self, TokenType::Semicolon).map_err(|token| Error {
+			kind: ErrorKind::ExpectedSemicolon,
+			token,
+		})?;
+		Ok(self.builder.build_stmt(StmtKind::Assert {
+			keyword,
+			condition,
+			message,
+		}))
 	}
 
-	fn or(&mut self) -> Result<Expr, Error> {
-		let mut expr = self.and()?;
-
-		loop {
-			match self.tokens.peek() {
-				Some(Token {
-					token_type: TokenType::Or,
-					..
-				}) => {
-					let operator = self.tokens.next().unwrap();
-					let right = self.and()?;
-					expr = Expr::Logical {
-						left: Box::new(expr),
-						operator,
-						right: Box::new(right),
-					};
-				}
-				_ => break,
-			}
-		}
+	fn try_statement(&mut self, keyword: Token) -> Result<B::Stmt, Error> {
+		expect_token_type!(self, TokenType::LeftBrace).map_err(|token| Error {
+			kind: ErrorKind::ExpectedLeftBrace,
+			token,
+		})?;
+		let body = self.block()?;
+		let body = self.builder.build_stmt(StmtKind::Block(body));
 
-		Ok(expr)
-	}
+		expect_token_type!(self, TokenType::Catch).map_err(|token| Error {
+			kind: ErrorKind::ExpectedCatch,
+			token,
+		})?;
 
-	fn and(&mut self) -> Result<Expr, Error> {
-		let mut expr = self.equality()?;
+		let catch_name = if self.check(&TokenType::LeftParen) {
+			let _ = self.advance();
+			let name = expect_token_type!(self, TokenType::Identifier(_)).map_err(|token| Error {
+				kind: ErrorKind::ExpectedIdentifier { place: "exception" },
+				token,
+			})?;
+			expect_token_type!(self, TokenType::RightParen).map_err(|token| Error {
+				kind: ErrorKind::ExpectedRightParenthesis,
+				token,
+			})?;
+			Some(name)
+		} else {
+			None
+		};
 
-		loop {
-			match self.tokens.peek() {
-				Some(Token {
-					token_type: TokenType::And,
-					..
-				}) => {
-					let operator = self.tokens.next().unwrap();
-					let right = self.equality()?;
-					expr = Expr::Logical {
-						left: Box::new(expr),
-						operator,
-						right: Box::new(right),
-					};
-				}
-				_ => break,
-			}
-		}
+		expect_token_type!(self, TokenType::LeftBrace).map_err(|token| Error {
+			kind: ErrorKind::ExpectedLeftBrace,
+			token,
+		})?;
+		let catch_body = self.block()?;
+		let catch_body = self.builder.build_stmt(StmtKind::Block(catch_body));
 
-		Ok(expr)
+		Ok(self.builder.build_stmt(StmtKind::Try {
+			keyword,
+			body,
+			catch_name,
+			catch_body,
+		}))
 	}
 
-	fn equality(&mut self) -> Result<Expr, Error> {
-		let mut expr = self.comparison()?;
-		loop {
-			let operator = match self.tokens.peek().map(|t| &t.token_type) {
-				Some(TokenType::BangEqual | TokenType::EqualEqual) => self.tokens.next().unwrap(),
-				_ => break,
-			};
-
-			expr = Expr::Binary {
-				left: Box::new(expr),
-				operator,
-				right: Box::new(self.comparison()?),
-			};
+	fn expression_statement(&mut self) -> Result<B::Stmt, Error> {
+		let expr = self.expression()?;
+		let token = self.advance().ok_or(Error {
+			kind: ErrorKind::ExpectedSemicolon,
+			token: None,
+		})?;
+		if !matches!(token.token_type, TokenType::Semicolon) {
+			return Err(Error {
+				kind: ErrorKind::ExpectedSemicolon,
+				token: None,
+			});
 		}
-
-		Ok(expr)
+		Ok(self.builder.build_stmt(StmtKind::Expr(expr)))
 	}
 
-	fn comparison(&mut self) -> Result<Expr, Error> {
-		let mut expr = self.term()?;
+	fn expression(&mut self) -> Result<B::Expr, Error> {
+		self.nested(Self::assignment)
+	}
 
-		loop {
-			let operator = match self.tokens.peek().map(|t| &t.token_type) {
-				Some(
-					TokenType::Greater
-					| TokenType::GreaterEqual
-					| TokenType::Less
-					| TokenType::LessEqual,
-				) => self.tokens.next().unwrap(),
-				_ => break,
-			};
+	/// Runs `rule` on code inside brackets, where it's no longer at the top level of a pattern.
+	fn bracketed<T>(
+		&mut self,
+		rule: impl FnOnce(&mut Self) -> Result<T, Error>,
+	) -> Result<T, Error> {
+		let in_pattern = std::mem::replace(&mut self.in_pattern, false);
+		let result = rule(self);
+		self.in_pattern = in_pattern;
+		result
+	}
 
-			expr = Expr::Binary {
-				left: Box::new(expr),
-				operator,
-				right: Box::new(self.term()?),
-			};
+	/// Runs a recursive rule one nesting level deeper, bailing out once
+	/// [ParserOptions::max_depth] is exceeded instead of overflowing the stack.
+	fn nested<T>(&mut self, rule: impl FnOnce(&mut Self) -> Result<T, Error>) -> Result<T, Error> {
+		if self.depth >= self.options.max_depth {
+			return Err(Error {
+				kind: ErrorKind::MaxDepthExceeded,
+				token: self.peek().cloned(),
+			});
 		}
-
-		Ok(expr)
+		self.depth += 1;
+		let result = rule(self);
+		self.depth -= 1;
+		result
 	}
 
-	fn term(&mut self) -> Result<Expr, Error> {
-		let mut expr = self.factor()?;
-
-		loop {
-			let operator = match self.tokens.peek().map(|t| &t.token_type) {
-				Some(TokenType::Minus | TokenType::Plus) => self.tokens.next().unwrap(),
-				_ => break,
-			};
-
-			expr = Expr::Binary {
-				left: Box::new(expr),
-				operator,
-				right: Box::new(self.factor()?),
-			};
+	/// Assignment is right-associative, so `a = b = c` is parsed as a list of targets followed
+	/// by the value, which is then folded from the right, rather than by recursing once per `=`.
+	/// Compound assignments are desugared, e.g. `a += b` into `a = a + b`, so the target is
+	/// evaluated twice and must not have side effects, ruling out e.g. `f().x += 1`.
+	/// The rules that still recurse (prefix operators, groupings, and anything nested inside
+	/// brackets or braces) go through [Parser::nested], so they're bounded by
+	/// [ParserOptions::max_depth]. Binary operators are parsed in a loop by [Parser::binary].
+	fn assignment(&mut self) -> Result<B::Expr, Error> {
+		let mut targets = Vec::new();
+		let mut value = self.binary(Precedence::Coalesce)?;
+		while let Some(Token {
+			token_type:
+				TokenType::Equal
+				| TokenType::PlusEqual
+				| TokenType::MinusEqual
+				| TokenType::StarEqual
+				| TokenType::SlashEqual,
+			..
+		}) = self.peek()
+		{
+			let equals = self.advance();
+			targets.push((value, equals));
+			value = self.binary(Precedence::Coalesce)?;
 		}
 
-		Ok(expr)
+		targets
+			.into_iter()
+			.rev()
+			.try_fold(value, |value, (target, equals)| {
+				let operator = match equals.as_ref().map(|t| &t.token_type) {
+					Some(TokenType::PlusEqual) => Some(TokenType::Plus),
+					Some(TokenType::MinusEqual) => Some(TokenType::Minus),
+					Some(TokenType::StarEqual) => Some(TokenType::Star),
+					Some(TokenType::SlashEqual) => Some(TokenType::Slash),
+					_ => None,
+				};
+				let value = match (operator, &equals) {
+					(Some(_), Some(_)) if !self.builder.is_reevaluable(&target) => {
+						return Err(Error {
+							kind: ErrorKind::InvalidAssignmentTarget,
+							token: equals,
+						});
+					}
+					(Some(operator), Some(equals)) => self.builder.build_expr(ExprKind::Binary {
+						left: target.clone(),
+						operator: Token {
+							lexeme: operator.to_string(),
+							column: equals.column,
+							..Token::synthetic(operator, equals.line)
+						},
+						right: value,
+					}),
+					_ => value,
+				};
+				self.builder.assign(target, value).ok_or(Error {
+					kind: ErrorKind::InvalidAssignmentTarget,
+					token: equals,
+				})
+			})
 	}
 
-	fn factor(&mut self) -> Result<Expr, Error> {
+	/// Parses a unary expression followed by any binary operators binding at least as tightly as
+	/// `precedence`, where [precedence_of] decides how tightly each operator binds. All of them
+	/// are left-associative, apart from ranges.
+	fn binary(&mut self, precedence: Precedence) -> Result<B::Expr, Error> {
 		let mut expr = self.unary()?;
-
+		// The loosest operator applied to `expr` so far.
+		let mut loosest = Precedence::Primary;
 		loop {
-			let operator = match self.tokens.peek().map(|t| &t.token_type) {
-				Some(TokenType::Slash | TokenType::Star) => self.tokens.next().unwrap(),
+			let operator_precedence = match self.peek().and_then(|t| precedence_of(&t.token_type)) {
+				Some(operator_precedence)
+					if operator_precedence >= precedence
+						&& operator_precedence < Precedence::Unary =>
+				{
+					operator_precedence
+				}
 				_ => break,
 			};
+			// Ranges don't chain, so `a..b..c` stops after `a..b`, and neither can the start of a
+			// range be a looser expression, as in `a == b..c..d`.
+			if operator_precedence == Precedence::Range && loosest <= Precedence::Range {
+				break;
+			}
+			loosest = loosest.min(operator_precedence);
+
+			let operator = self.advance().unwrap();
+			// The right side of `is` can only name a class.
+			let right = if operator.token_type == TokenType::Is {
+				let class = expect_token_type!(self, TokenType::Identifier(_)).map_err(|token| {
+					Error {
+						kind: ErrorKind::ExpectedIdentifier { place: "class" },
+						token,
+					}
+				})?;
+				self.builder.build_expr(ExprKind::Variable(class))
+			} else {
+				self.binary(operator_precedence.next())?
+			};
 
-			expr = Expr::Binary {
-				left: Box::new(expr),
-				operator,
-				right: Box::new(self.unary()?),
+			let node = match operator.token_type {
+				TokenType::DotDot | TokenType::DotDotEqual => ExprKind::Range {
+					start: expr,
+					end: right,
+					inclusive: operator.token_type == TokenType::DotDotEqual,
+				},
+				TokenType::QuestionQuestion | TokenType::Or | TokenType::And => ExprKind::Logical {
+					left: expr,
+					operator,
+					right,
+				},
+				_ => ExprKind::Binary {
+					left: expr,
+					operator,
+					right,
+				},
 			};
+			expr = self.builder.build_expr(node);
 		}
 
 		Ok(expr)
 	}
 
-	fn unary(&mut self) -> Result<Expr, Error> {
-		let token = self.tokens.peek().ok_or(Error {
+	fn unary(&mut self) -> Result<B::Expr, Error> {
+		let token = self.peek().ok_or(Error {
 			kind: ErrorKind::ExpectedExpression,
 			token: None,
 		})?;
 
 		match token.token_type {
 			TokenType::Bang | TokenType::Minus => {
-				let token = self.tokens.next().unwrap();
-				Ok(Expr::Unary {
+				let token = self.advance().unwrap();
+				let expr = self.nested(Self::unary)?;
+				Ok(self.builder.build_expr(ExprKind::Unary {
 					operator: token,
-					expr: Box::new(self.unary()?),
-				})
+					expr,
+				}))
 			}
 			_ => self.call(),
 		}
 	}
 
-	fn call(&mut self) -> Result<Expr, Error> {
+	fn call(&mut self) -> Result<B::Expr, Error> {
 		let mut expr = self.primary()?;
 
 		loop {
-			match self.tokens.peek() {
+			match self.peek() {
 				Some(Token {
 					token_type: TokenType::LeftParen,
 					..
 				}) => {
-					let _ = self.tokens.next();
+					let _ = self.advance();
 					expr = self.finish_call(expr)?;
 				}
 				Some(Token {
-					token_type: TokenType::Dot,
+					token_type: token_type @ (TokenType::Dot | TokenType::QuestionDot),
 					..
 				}) => {
-					let _ = self.tokens.next();
+					let optional = *token_type == TokenType::QuestionDot;
+					let _ = self.advance();
 					let name =
 						expect_token_type!(self, TokenType::Identifier(_)).map_err(|token| {
 							Error {
@@ -850,10 +2519,11 @@ impl Parser {
 							}
 						})?;
 
-					expr = Expr::Get {
-						object: Box::new(expr),
+					expr = self.builder.build_expr(ExprKind::Get {
+						object: expr,
 						name,
-					};
+						optional,
+					});
 				}
 				_ => break,
 			}
@@ -862,33 +2532,58 @@ impl Parser {
 		Ok(expr)
 	}
 
-	fn finish_call(&mut self, callee: Expr) -> Result<Expr, Error> {
-		let mut arguments = Vec::new();
+	fn finish_call(&mut self, callee: B::Expr) -> Result<B::Expr, Error> {
+		let (arguments, closing_parenthesis) = self.bracketed(Self::arguments)?;
+		Ok(self.builder.build_expr(ExprKind::Call {
+			callee,
+			closing_parenthesis,
+			arguments,
+		}))
+	}
 
-		if !matches!(
-			self.tokens.peek(),
-			Some(Token {
-				token_type: TokenType::RightParen,
-				..
-			})
-		) {
+	/// Everything after the `(` of a call. Also returns the closing parenthesis.
+	fn arguments(&mut self) -> Result<ArgumentList<B::Expr>, Error> {
+		let mut arguments: Vec<Argument<B::Expr>> = Vec::new();
+
+		if !self.check(&TokenType::RightParen) {
 			loop {
-				if arguments.len() >= 255 {
-					// In the book, here we only report the error, not throw it
+				let limit = self.options.max_arguments;
+				if let Some(limit) = limit.filter(|&limit| arguments.len() >= limit) {
+					// In the book, here we only report the error, not throw it. Only the error
+					// copies the token, so checking the limit costs nothing for calls within it.
 					return Err(Error {
-						kind: ErrorKind::ExceededArgumentsLimit,
-						token: self.tokens.peek().cloned(),
+						kind: ErrorKind::ExceededArgumentsLimit { limit },
+						token: self.peek().cloned(),
 					});
 				}
-				arguments.push(self.expression()?);
-				match self.tokens.peek() {
-					Some(Token {
-						token_type: TokenType::Comma,
-						..
-					}) => {
-						let _ = self.tokens.next();
-					}
-					_ => break,
+				let spread = self.check(&TokenType::DotDotDot);
+				if spread {
+					let _ = self.advance();
+				}
+				let named = !spread
+					&& matches!(self.peek().map(|t| &t.token_type), Some(TokenType::Identifier(_)))
+					&& self.peek_next().is_some_and(|t| t.token_type == TokenType::Colon);
+				let name = if named {
+					let name = self.advance();
+					let _ = self.advance();
+					name
+				} else {
+					None
+				};
+				let expr = self.expression()?;
+				if name.is_none() && arguments.last().is_some_and(|a| a.name.is_some()) {
+					return Err(Error {
+						kind: ErrorKind::PositionalAfterNamed,
+						token: Some(self.builder.first_token(&expr).clone()),
+					});
+				}
+				arguments.push(Argument { expr, spread, name });
+				if !self.check(&TokenType::Comma) {
+					break;
+				}
+				let _ = self.advance();
+				if self.options.trailing_commas && self.check(&TokenType::RightParen) {
+					break;
 				}
 			}
 		}
@@ -898,80 +2593,333 @@ impl Parser {
 				kind: ErrorKind::ExpectedRightParenthesis,
 				token,
 			})?;
-		Ok(Expr::Call {
-			callee: Box::new(callee),
-			closing_parenthesis,
-			arguments,
-		})
+		Ok((arguments, closing_parenthesis))
 	}
 
-	fn primary(&mut self) -> Result<Expr, Error> {
-		let token = self.tokens.next().ok_or(Error {
-			kind: ErrorKind::ExpectedExpression,
-			token: None,
+	/// Whether the tokens after a `(` are a parameter list followed by `=>`, which makes the `(`
+	/// the start of an arrow function rather than of a grouping. The list is checked the way
+	/// [Parser::parameters] parses it, trailing comma included.
+	fn at_arrow_function(&self) -> bool {
+		let mut tokens = self.tokens[self.pos..].iter().map(|t| &t.token_type);
+		let mut first = true;
+		loop {
+			match tokens.next() {
+				Some(TokenType::RightParen) if first || self.options.trailing_commas => break,
+				Some(TokenType::Identifier(_)) => match tokens.next() {
+					Some(TokenType::Comma) => first = false,
+					Some(TokenType::RightParen) => break,
+					_ => return false,
+				},
+				_ => return false,
+			}
+		}
+		matches!(tokens.next(), Some(TokenType::FatArrow))
+	}
+
+	/// `(params) => value`, after the `(`. It's sugar for a function expression whose body
+	/// returns `value`. The `(` stands in for the `fun` keyword, the `=>` for the `return` and
+	/// the last token of `value` for the closing brace.
+	fn arrow_function(&mut self, left_paren: Token) -> Result<B::Expr, Error> {
+		let params = self.parameters()?;
+		let arrow = expect_token_type!(self, TokenType::FatArrow).map_err(|token| Error {
+			kind: ErrorKind::ExpectedFatArrow,
+			token,
 		})?;
+		self.function_depth += 1;
+		let value = self.expression();
+		self.function_depth -= 1;
+		let value = value?;
+		let closing_brace = self.builder.last_token(&value).clone();
+		let body = self.builder.build_stmt(StmtKind::Return {
+			keyword: arrow,
+			value: Some(value),
+		});
+		Ok(self.builder.build_expr(ExprKind::Function {
+			keyword: left_paren,
+			name: None,
+			params,
+			body: vec![body],
+			closing_brace,
+		}))
+	}
 
-		match token.token_type {
-			TokenType::Identifier(_) => Ok(Expr::Variable(token)),
-			TokenType::Number(_)
-			| TokenType::String(_)
-			| TokenType::True
-			| TokenType::False
-			| TokenType::Nil => Ok(Expr::Literal(token)),
-			TokenType::This => Ok(Expr::This { keyword: token }),
-			TokenType::Super => {
-				expect_token_type!(self, TokenType::Dot).map_err(|token| Error {
-					kind: ErrorKind::ExpectedDot,
-					token,
-				})?;
+	/// Everything after the `match` keyword. The `_` arm, if any, has to come last.
+	fn match_expression(&mut self, keyword: Token) -> Result<B::Expr, Error> {
+		expect_token_type!(self, TokenType::LeftParen).map_err(|token| Error {
+			kind: ErrorKind::ExpectedLeftParenthesis,
+			token,
+		})?;
+		let subject = self.expression()?;
+		expect_token_type!(self, TokenType::RightParen).map_err(|token| Error {
+			kind: ErrorKind::ExpectedRightParenthesis,
+			token,
+		})?;
+		expect_token_type!(self, TokenType::LeftBrace).map_err(|token| Error {
+			kind: ErrorKind::ExpectedLeftBrace,
+			token,
+		})?;
 
-				let method =
-					expect_token_type!(self, TokenType::Identifier(_)).map_err(|token| Error {
-						kind: ErrorKind::ExpectedIdentifier { place: "super" },
-						token,
-					})?;
-				Ok(Expr::Super {
-					keyword: token,
-					method,
-				})
+		let mut arms = Vec::new();
+		let mut default = None;
+		while !self.check(&TokenType::RightBrace) {
+			// `_` is an identifier like any other, apart from standing for the default arm here.
+			let is_default = self.peek().is_some_and(|t| t.lexeme == "_")
+				&& self.peek_next().is_some_and(|t| t.token_type == TokenType::FatArrow);
+			let pattern = if is_default {
+				let _ = self.advance();
+				None
+			} else {
+				let in_pattern = std::mem::replace(&mut self.in_pattern, true);
+				let pattern = self.expression();
+				self.in_pattern = in_pattern;
+				Some(pattern?)
+			};
+			expect_token_type!(self, TokenType::FatArrow).map_err(|token| Error {
+				kind: ErrorKind::ExpectedFatArrow,
+				token,
+			})?;
+			let value = self.expression()?;
+			match pattern {
+				Some(pattern) => arms.push((pattern, value)),
+				None => default = Some(value),
 			}
-			TokenType::LeftParen => {
-				let expr = self.expression()?;
-				match self.tokens.next() {
-					Some(Token {
-						token_type: TokenType::RightParen,
-						..
-					}) => (),
-					Some(token) => {
-						return Err(Error {
-							kind: ErrorKind::ExpectedRightParenthesis,
-							token: Some(token),
-						})
-					}
-					None => {
-						return Err(Error {
-							kind: ErrorKind::ExpectedRightParenthesis,
-							token: None,
-						})
-					}
-				}
-				Ok(Expr::Grouping(Box::new(expr)))
+
+			if !self.check(&TokenType::Comma) {
+				break;
+			}
+			let _ = self.advance();
+			if is_default {
+				break;
 			}
-			_ => Err(Error {
-				kind: ErrorKind::ExpectedExpression,
-				token: Some(token),
-			}),
 		}
-	}
 
+		let closing_brace =
+			expect_token_type!(self, TokenType::RightBrace).map_err(|token| Error {
+				kind: ErrorKind::ExpectedRightBrace,
+				token,
+			})?;
+		Ok(self.builder.build_expr(ExprKind::Match {
+			keyword,
+			subject,
+			arms,
+			default,
+			closing_brace,
+		}))
+	}
+
+	/// `if (condition) then_branch else else_branch`, after the `if`. At the start of a statement,
+	/// [Parser::if_statement] is used instead. Both branches are whole expressions, so the `else`
+	/// branch extends as far to the right as it can.
+	fn if_expression(&mut self, keyword: Token) -> Result<B::Expr, Error> {
+		expect_token_type!(self, TokenType::LeftParen).map_err(|token| Error {
+			kind: ErrorKind::ExpectedLeftParenthesis,
+			token,
+		})?;
+		let condition = self.bracketed(Self::expression)?;
+		expect_token_type!(self, TokenType::RightParen).map_err(|token| Error {
+			kind: ErrorKind::ExpectedRightParenthesis,
+			token,
+		})?;
+		let then_branch = self.expression()?;
+		expect_token_type!(self, TokenType::Else).map_err(|token| Error {
+			kind: ErrorKind::ExpectedElse,
+			token,
+		})?;
+		let else_branch = self.expression()?;
+
+		Ok(self.builder.build_expr(ExprKind::If {
+			keyword,
+			condition,
+			then_branch,
+			else_branch,
+		}))
+	}
+
+	/// `{ statements value }`, after the `{`. A `{` at the start of a statement is always a block
+	/// statement, as [Parser::statement] gets to it first, so this only sees the ones in
+	/// expression position. Inside, statements are parsed as in a block statement, except that an
+	/// expression followed by `}` rather than `;` is the trailing value, which ends the block.
+	fn block_expression(&mut self, left_brace: Token) -> Result<B::Expr, Error> {
+		let mut statements = Vec::new();
+		loop {
+			if !self.at_expression_statement() {
+				statements.push(self.declaration()?);
+				continue;
+			}
+
+			let start = self.pos;
+			let expr = self.expression()?;
+			match self.advance() {
+				Some(Token {
+					token_type: TokenType::Semicolon,
+					..
+				}) => {
+					self.record_stmt(start);
+					statements.push(self.builder.build_stmt(StmtKind::Expr(expr)));
+				}
+				Some(
+					right_brace @ Token {
+						token_type: TokenType::RightBrace,
+						..
+					},
+				) => {
+					return Ok(self.builder.build_expr(ExprKind::Block {
+						left_brace,
+						statements,
+						value: expr,
+						right_brace,
+					}))
+				}
+				token => {
+					return Err(Error {
+						kind: ErrorKind::ExpectedSemicolon,
+						token,
+					})
+				}
+			}
+		}
+	}
+
+	/// Whether [Parser::declaration] would parse the next statement as an expression statement.
+	fn at_expression_statement(&self) -> bool {
+		match self.peek().map(|t| &t.token_type) {
+			Some(TokenType::Fun) => !matches!(
+				self.peek_next().map(|t| &t.token_type),
+				Some(TokenType::Identifier(_))
+			),
+			Some(TokenType::Identifier(_)) => {
+				!self.peek_next().is_some_and(|t| t.token_type == TokenType::Colon)
+			}
+			Some(
+				TokenType::Var
+				| TokenType::Class
+				| TokenType::If
+				| TokenType::Print
+				| TokenType::While
+				| TokenType::For
+				| TokenType::Break
+				| TokenType::Continue
+				| TokenType::LeftBrace
+				| TokenType::Semicolon
+				| TokenType::Return
+				| TokenType::Throw
+				| TokenType::Try
+				| TokenType::Assert
+				| TokenType::Import
+				| TokenType::Catch
+				| TokenType::Else
+				| TokenType::Global
+				| TokenType::Local,
+			) => false,
+			_ => true,
+		}
+	}
+
+	fn primary(&mut self) -> Result<B::Expr, Error> {
+		let token = self.advance().ok_or(Error {
+			kind: ErrorKind::ExpectedExpression,
+			token: None,
+		})?;
+
+		match token.token_type {
+			TokenType::Identifier(_) => Ok(self.builder.build_expr(ExprKind::Variable(token))),
+			TokenType::Number(_)
+			| TokenType::Integer(_)
+			| TokenType::String(_)
+			| TokenType::True
+			| TokenType::False
+			| TokenType::Nil
+			| TokenType::Undefined => Ok(self.builder.build_expr(ExprKind::Literal(token))),
+			TokenType::This => Ok(self.builder.build_expr(ExprKind::This { keyword: token })),
+			TokenType::Fun => {
+				let name = match self.peek() {
+					Some(Token {
+						token_type: TokenType::Identifier(_),
+						..
+					}) => self.advance(),
+					_ => None,
+				};
+				let (params, body, closing_brace) = self.bracketed(Self::parameters_and_body)?;
+				Ok(self.builder.build_expr(ExprKind::Function {
+					keyword: token,
+					name,
+					params,
+					body,
+					closing_brace,
+				}))
+			}
+			TokenType::Match => self.bracketed(|parser| parser.match_expression(token)),
+			TokenType::LeftBrace => self.bracketed(|parser| parser.block_expression(token)),
+			TokenType::If => self.if_expression(token),
+			TokenType::Super if self.check(&TokenType::LeftParen) => {
+				let _ = self.advance();
+				let (arguments, closing_parenthesis) = self.bracketed(Self::arguments)?;
+				Ok(self.builder.build_expr(ExprKind::SuperCall {
+					keyword: token,
+					closing_parenthesis,
+					arguments,
+				}))
+			}
+			TokenType::Super => {
+				expect_token_type!(self, TokenType::Dot).map_err(|token| Error {
+					kind: ErrorKind::ExpectedDot,
+					token,
+				})?;
+
+				let method =
+					expect_token_type!(self, TokenType::Identifier(_)).map_err(|token| Error {
+						kind: ErrorKind::ExpectedIdentifier { place: "super" },
+						token,
+					})?;
+				Ok(self.builder.build_expr(ExprKind::Super {
+					keyword: token,
+					method,
+				}))
+			}
+			TokenType::LeftParen if !self.in_pattern && self.at_arrow_function() => {
+				self.arrow_function(token)
+			}
+			TokenType::LeftParen => {
+				let expr = self.bracketed(Self::expression)?;
+				match self.advance() {
+					Some(Token {
+						token_type: TokenType::RightParen,
+						..
+					}) => (),
+					Some(token) => {
+						return Err(Error {
+							kind: ErrorKind::ExpectedRightParenthesis,
+							token: Some(token),
+						})
+					}
+					None => {
+						return Err(Error {
+							kind: ErrorKind::ExpectedRightParenthesis,
+							token: None,
+						})
+					}
+				}
+				Ok(self.builder.build_expr(ExprKind::Grouping(expr)))
+			}
+			_ => Err(Error {
+				kind: ErrorKind::ExpectedExpression,
+				token: Some(token),
+			}),
+		}
+	}
+
+	/// Skips ahead to where the next statement likely starts, to keep parsing after an error.
+	/// That's just past a `;`, or at a keyword that starts a statement, like `var`, `if` or
+	/// `return`. At least one token is skipped, so a parse loop that calls this after every error
+	/// always makes progress.
 	#[allow(dead_code)]
-	fn synchronize(&mut self) {
-		while let Some(token) = self.tokens.next() {
+	pub fn synchronize(&mut self) {
+		while let Some(token) = self.advance() {
 			if matches!(token.token_type, TokenType::Semicolon) {
 				return;
 			}
 
-			let Some(token) = self.tokens.peek() else {
+			let Some(token) = self.peek() else {
                 return;
             };
 			match token.token_type {
@@ -982,116 +2930,275 @@ impl Parser {
 				| TokenType::Print
 				| TokenType::Return
 				| TokenType::Var
-				| TokenType::While => {
+				| TokenType::Global
+				| TokenType::Local
+				| TokenType::While
+				| TokenType::Throw
+				| TokenType::Try
+				| TokenType::Assert
+				| TokenType::Import
+				| TokenType::Break
+				| TokenType::Continue => {
+					return;
+				}
+				_ => (),
+			}
+		}
+	}
+
+	/// Skips the rest of a malformed class member: up to and including its `;` or its body, or up
+	/// to the `}` closing the class.
+	fn synchronize_member(&mut self) {
+		let mut depth = 0usize;
+		while let Some(token) = self.peek() {
+			match token.token_type {
+				TokenType::Eof => return,
+				TokenType::RightBrace if depth == 0 => return,
+				TokenType::Semicolon if depth == 0 => {
+					let _ = self.advance();
 					return;
 				}
+				TokenType::LeftBrace => depth += 1,
+				TokenType::RightBrace => {
+					depth -= 1;
+					if depth == 0 {
+						let _ = self.advance();
+						return;
+					}
+				}
 				_ => (),
 			}
+			let _ = self.advance();
 		}
 	}
 }
 
 fn print_ast(expr: &Expr, w: &mut impl std::fmt::Write) -> std::fmt::Result {
-	fn parenthesize(w: &mut impl std::fmt::Write, name: &str, exprs: &[&Expr]) -> std::fmt::Result {
-		write!(w, "({name}")?;
+	expr.accept(&mut AstPrinter {
+		w,
+		escape_strings: false,
+	})
+}
+
+/// Like [print_ast], but prints strings quoted and escaped, so the output stays on one line.
+#[allow(dead_code)]
+fn print_ast_escaped(expr: &Expr, w: &mut impl std::fmt::Write) -> std::fmt::Result {
+	expr.accept(&mut AstPrinter {
+		w,
+		escape_strings: true,
+	})
+}
+
+struct AstPrinter<'w, W> {
+	w: &'w mut W,
+	escape_strings: bool,
+}
+
+impl<W: std::fmt::Write> AstPrinter<'_, W> {
+	fn parenthesize(&mut self, name: &str, exprs: &[&Expr]) -> std::fmt::Result {
+		write!(self.w, "({name}")?;
 		for expr in exprs {
-			write!(w, " ")?;
-			print_ast(expr, w)?;
+			write!(self.w, " ")?;
+			expr.accept(self)?;
 		}
-		write!(w, ")")?;
+		write!(self.w, ")")?;
 		Ok(())
 	}
 
-	match expr {
-		Expr::Literal(Token {
-			token_type: TokenType::Number(v),
-			..
-		}) => write!(w, "{v}"),
-		Expr::Literal(Token {
-			token_type: TokenType::String(v),
-			..
-		}) => write!(w, "{v}"),
-		Expr::Literal(Token {
-			token_type: TokenType::Identifier(v),
-			..
-		}) => write!(w, "{v}"),
-		Expr::Literal(Token {
-			token_type: TokenType::True,
-			..
-		}) => write!(w, "true"),
-		Expr::Literal(Token {
-			token_type: TokenType::False,
-			..
-		}) => write!(w, "false"),
-		Expr::Literal(Token {
-			token_type: TokenType::Nil,
-			..
-		}) => write!(w, "nil"),
-		Expr::Literal(l) => panic!("{l:?}"),
-		Expr::Variable(Token {
-			token_type: TokenType::Identifier(var_name),
-			..
-		}) => write!(w, "{var_name}"),
-		Expr::Variable(v) => panic!("{v:?}"),
-		Expr::Assign {
-			name: Token {
-				token_type: TokenType::Identifier(name),
-				..
-			},
-			value,
-		} => parenthesize(w, &format!("= {name}"), &[value]),
-		Expr::Assign { name, .. } => panic!("{name:?}"),
-		Expr::Binary {
-			left,
-			operator: Token { lexeme, .. },
-			right,
-		} => parenthesize(w, lexeme, &[left, right]),
-		Expr::Grouping(expr) => parenthesize(w, "group", &[expr]),
-		Expr::Unary { operator, expr } => parenthesize(w, &operator.lexeme, &[expr]),
-		Expr::Logical {
-			left,
-			operator,
-			right,
-		} => parenthesize(w, &operator.lexeme, &[left, right]),
-		expr => todo!("{expr:?}"),
+	/// Each argument preceded by a space. Spread arguments are printed as `(... expr)`, and named
+	/// ones as `(name: expr)`.
+	fn arguments(&mut self, arguments: &[Argument]) -> std::fmt::Result {
+		for argument in arguments {
+			write!(self.w, " ")?;
+			if argument.spread {
+				self.parenthesize("...", &[&argument.expr])?;
+			} else if let Some(name) = &argument.name {
+				self.parenthesize(&format!("{}:", name.token_type), &[&argument.expr])?;
+			} else {
+				argument.expr.accept(self)?;
+			}
+		}
+		Ok(())
+	}
+}
+
+impl<W: std::fmt::Write> ExprVisitor for AstPrinter<'_, W> {
+	type Output = std::fmt::Result;
+
+	fn visit_literal(&mut self, token: &Token) -> std::fmt::Result {
+		match &token.token_type {
+			TokenType::Number(v) => write!(self.w, "{}", format_number(*v)),
+			TokenType::Integer(v) => write!(self.w, "{v}"),
+			TokenType::String(v) if self.escape_strings => printer::write_string(self.w, v),
+			TokenType::String(v) => write!(self.w, "{v}"),
+			TokenType::Identifier(v) => write!(self.w, "{v}"),
+			TokenType::True => write!(self.w, "true"),
+			TokenType::False => write!(self.w, "false"),
+			TokenType::Nil => write!(self.w, "nil"),
+			TokenType::Undefined => write!(self.w, "undefined"),
+			_ => panic!("{token:?}"),
+		}
+	}
+
+	fn visit_variable(&mut self, name: &Token) -> std::fmt::Result {
+		match &name.token_type {
+			TokenType::Identifier(var_name) => write!(self.w, "{var_name}"),
+			_ => panic!("{name:?}"),
+		}
+	}
+
+	fn visit_assign(&mut self, name: &Token, value: &Expr) -> std::fmt::Result {
+		match &name.token_type {
+			TokenType::Identifier(name) => self.parenthesize(&format!("= {name}"), &[value]),
+			_ => panic!("{name:?}"),
+		}
+	}
+
+	fn visit_unary(&mut self, operator: &Token, expr: &Expr) -> std::fmt::Result {
+		self.parenthesize(&operator.token_type.to_string(), &[expr])
+	}
+
+	fn visit_binary(&mut self, left: &Expr, operator: &Token, right: &Expr) -> std::fmt::Result {
+		self.parenthesize(&operator.token_type.to_string(), &[left, right])
+	}
+
+	fn visit_grouping(&mut self, expr: &Expr) -> std::fmt::Result {
+		self.parenthesize("group", &[expr])
+	}
+
+	fn visit_logical(&mut self, left: &Expr, operator: &Token, right: &Expr) -> std::fmt::Result {
+		self.parenthesize(&operator.token_type.to_string(), &[left, right])
+	}
+
+	fn visit_call(
+		&mut self,
+		callee: &Expr,
+		_closing_parenthesis: &Token,
+		arguments: &[Argument],
+	) -> std::fmt::Result {
+		write!(self.w, "(call ")?;
+		callee.accept(self)?;
+		self.arguments(arguments)?;
+		write!(self.w, ")")
+	}
+
+	fn visit_get(&mut self, object: &Expr, name: &Token, optional: bool) -> std::fmt::Result {
+		let dot = if optional { "?." } else { "." };
+		self.parenthesize(&format!("{dot}{}", name.token_type), &[object])
+	}
+
+	fn visit_set(&mut self, object: &Expr, name: &Token, value: &Expr) -> std::fmt::Result {
+		self.parenthesize(&format!("= .{}", name.token_type), &[object, value])
+	}
+
+	fn visit_this(&mut self, _keyword: &Token) -> std::fmt::Result {
+		write!(self.w, "this")
+	}
+
+	fn visit_super(&mut self, _keyword: &Token, method: &Token) -> std::fmt::Result {
+		write!(self.w, "super.{}", method.token_type)
+	}
+
+	fn visit_super_call(&mut self, _keyword: &Token, arguments: &[Argument]) -> std::fmt::Result {
+		write!(self.w, "(call super")?;
+		self.arguments(arguments)?;
+		write!(self.w, ")")
+	}
+
+	/// The body isn't printed, as it's made of statements.
+	fn visit_function(
+		&mut self,
+		_keyword: &Token,
+		name: Option<&Token>,
+		params: &[Token],
+		_body: &[Stmt],
+	) -> std::fmt::Result {
+		write!(self.w, "(fun ")?;
+		if let Some(name) = name {
+			write!(self.w, "{} ", name.token_type)?;
+		}
+		write!(self.w, "(")?;
+		for (i, param) in params.iter().enumerate() {
+			if i > 0 {
+				write!(self.w, " ")?;
+			}
+			write!(self.w, "{}", param.token_type)?;
+		}
+		write!(self.w, "))")
+	}
+
+	fn visit_match(
+		&mut self,
+		subject: &Expr,
+		arms: &[(Expr, Expr)],
+		default: Option<&Expr>,
+	) -> std::fmt::Result {
+		write!(self.w, "(match ")?;
+		subject.accept(self)?;
+		for (pattern, value) in arms {
+			write!(self.w, " (")?;
+			pattern.accept(self)?;
+			write!(self.w, " ")?;
+			value.accept(self)?;
+			write!(self.w, ")")?;
+		}
+		if let Some(default) = default {
+			write!(self.w, " ")?;
+			self.parenthesize("_", &[default])?;
+		}
+		write!(self.w, ")")
+	}
+
+	fn visit_if_expr(
+		&mut self,
+		condition: &Expr,
+		then_branch: &Expr,
+		else_branch: &Expr,
+	) -> std::fmt::Result {
+		self.parenthesize("if", &[condition, then_branch, else_branch])
+	}
+
+	/// The statements aren't printed, only the value.
+	fn visit_block_expr(&mut self, _statements: &[Stmt], value: &Expr) -> std::fmt::Result {
+		self.parenthesize("block", &[value])
+	}
+
+	fn visit_range(&mut self, start: &Expr, end: &Expr, inclusive: bool) -> std::fmt::Result {
+		self.parenthesize(if inclusive { "..=" } else { ".." }, &[start, end])
 	}
 }
 
 #[cfg(test)]
 mod tests {
-	use super::Expr;
-	use crate::token::{Token, TokenType};
+	use super::{
+		block_span, format_errors, precedence_of, Error, ErrorKind, Expr, FunctionStatement,
+		ParseEvent, Parser, ParserOptions, Precedence, Stmt, VarScope,
+	};
+	use crate::alloc_count::count_allocations;
+	use crate::scanner::Scanner;
+	use crate::token::{reset_universal_index, Token, TokenType};
+	use std::time::{Duration, Instant};
+
+	fn parse(source: &str) -> Vec<Stmt> {
+		Parser::new(Scanner::new(source).scan_tokens())
+			.parse()
+			.unwrap()
+	}
+
+	fn parse_expr(source: &str) -> Expr {
+		match parse(&format!("{source};")).pop() {
+			Some(Stmt::Expr(expr)) => expr,
+			stmt => panic!("expected an expression statement, got {stmt:?}"),
+		}
+	}
 
 	#[test]
 	fn test_ast_printer() {
-		let expr = Expr::Binary {
-			left: Box::new(Expr::Unary {
-				operator: Token {
-					token_type: TokenType::Minus,
-					lexeme: "-".to_string(),
-					line: 1,
-					universal_index: 0,
-				},
-				expr: Box::new(Expr::Literal(Token {
-					token_type: TokenType::Number(123.0),
-					lexeme: "123".to_string(),
-					line: 1,
-					universal_index: 1,
-				})),
-			}),
-			operator: Token {
-				token_type: TokenType::Star,
-				lexeme: "*".to_string(),
-				line: 1,
-				universal_index: 2,
-			},
-			right: Box::new(Expr::Grouping(Box::new(Expr::Literal(Token {
-				token_type: TokenType::Number(45.67),
-				lexeme: "45.67".to_string(),
-				line: 1,
-				universal_index: 3,
-			})))),
-		};
+		let expr = Expr::binary(
+			Expr::unary(TokenType::Minus, Expr::number(123.0)),
+			TokenType::Star,
+			Expr::group(Expr::number(45.67)),
+		);
 
 		let expected = "(* (- 123) (group 45.67))";
 
@@ -1100,4 +3207,1667 @@ mod tests {
 
 		assert_eq!(expected, actual);
 	}
+
+	#[test]
+	fn test_print_ast_escaped() {
+		let expr = parse_expr("f(\"a\\tb\\nc\", \"\\\"q\\\"\") + \"\"");
+		assert_eq!(expr.to_string(), "(+ (call f a\tb\nc \"q\") )");
+
+		let mut escaped = String::new();
+		super::print_ast_escaped(&expr, &mut escaped).unwrap();
+		assert_eq!(escaped, r#"(+ (call f "a\tb\nc" "\"q\"") "")"#);
+	}
+
+	#[test]
+	fn test_expr_builders() {
+		let built = Expr::binary(
+			Expr::group(Expr::binary(
+				Expr::variable("a"),
+				TokenType::Less,
+				Expr::integer(2),
+			)),
+			TokenType::EqualEqual,
+			Expr::unary(TokenType::Bang, Expr::nil()),
+		);
+		assert!(built.structurally_eq(&parse_expr("(a < 2) == !nil")));
+		assert_eq!(Expr::bool(false).as_bool(), Some(false));
+		assert_eq!(Expr::string("s").as_string(), Some("s"));
+		assert_ne!(Expr::number(1.0), Expr::number(1.0));
+	}
+
+	#[test]
+	fn test_structurally_eq_ignores_positions() {
+		let a = parse_expr("-x + 2 * f(1, y.z)");
+		let b = parse_expr("\n\n-x + 2 * f(1, y.z)");
+
+		assert_ne!(a, b);
+		assert!(a.structurally_eq(&b));
+		assert!(!a.structurally_eq(&parse_expr("-x + 2 * f(1, y.w)")));
+		assert!(!a.structurally_eq(&parse_expr("-x - 2 * f(1, y.z)")));
+	}
+
+	#[test]
+	fn test_eq_ignoring_grouping() {
+		let a = parse_expr("(a + b) * c");
+		assert!(a.eq_ignoring_grouping(&parse_expr("((a + b)) * (c)")));
+		assert!(!a.structurally_eq(&parse_expr("((a + b)) * (c)")));
+		assert!(!a.eq_ignoring_grouping(&parse_expr("a + b * c")));
+		assert!(parse_expr("(a + b)").eq_ignoring_grouping(&parse_expr("a + b")));
+		assert!(parse_expr("a + (b * c)").eq_ignoring_grouping(&parse_expr("a + b * c")));
+		assert!(!parse_expr("(a - b) - c").eq_ignoring_grouping(&parse_expr("a - (b - c)")));
+	}
+
+	#[test]
+	fn test_stmt_equality() {
+		let source = "class A < B { init(x) { this.x = x; } }\n\
+			fun f(a) { if (a) return 1; else { while (a) print a; } return; }\n\
+			var v = f(1);";
+		let a = parse(source);
+		let b = parse(source);
+
+		assert_eq!(a, a.clone());
+		// Every parse mints fresh `universal_index`es, so `==` tells the two parses apart...
+		assert_ne!(a, b);
+		// ...while the structural comparison doesn't.
+		assert!(a.iter().zip(&b).all(|(a, b)| a.structurally_eq(b)));
+	}
+
+	#[test]
+	fn test_ast_printer_postfix() {
+		let expr = parse_expr("a.b = f(this.c, super.d)");
+		assert_eq!(expr.to_string(), "(= .b a (call f (.c this) super.d))");
+	}
+
+	#[test]
+	fn test_postfix_chaining() {
+		let name = |token: &Token| token.lexeme.clone();
+
+		// a.b.c
+		let Expr::Get { object, name: c, .. } = parse_expr("a.b.c") else {
+			panic!("expected a get");
+		};
+		let Expr::Get { object, name: b, .. } = *object else {
+			panic!("expected a get");
+		};
+		assert!(matches!(*object, Expr::Variable(ref a) if a.lexeme == "a"));
+		assert_eq!((name(&b), name(&c)), ("b".to_string(), "c".to_string()));
+
+		// a.b().c
+		let Expr::Get { object, name: c, .. } = parse_expr("a.b().c") else {
+			panic!("expected a get");
+		};
+		let Expr::Call(call) = *object else {
+			panic!("expected a call");
+		};
+		let Expr::Get { object, name: b, .. } = call.callee else {
+			panic!("expected a get");
+		};
+		assert!(matches!(*object, Expr::Variable(ref a) if a.lexeme == "a"));
+		assert_eq!((name(&b), name(&c)), ("b".to_string(), "c".to_string()));
+
+		assert_eq!(
+			parse_expr("a(1)(2)?.b.c(3)").to_string(),
+			"(call (.c (?.b (call (call a 1) 2))) 3)"
+		);
+	}
+
+	#[test]
+	fn test_parse_expression() {
+		let expr = Parser::new(Scanner::new("(1 + 2) * 3").scan_tokens())
+			.parse_expression()
+			.unwrap();
+		assert_eq!(expr.to_string(), "(* (group (+ 1 2)) 3)");
+
+		let err = Parser::new(Scanner::new("1 + 2;").scan_tokens())
+			.parse_expression()
+			.unwrap_err();
+		assert!(matches!(err.kind, ErrorKind::ExpectedEndOfInput));
+		assert_eq!(err.token.unwrap().lexeme, ";");
+	}
+
+	#[test]
+	fn test_parse_from_slice() {
+		let tokens = Scanner::new("var a = 1 + 2; print a;").scan_tokens();
+
+		let first = Parser::from_slice(&tokens).parse().unwrap();
+		let second = Parser::from_slice(&tokens).parse().unwrap();
+
+		assert_eq!(first, second);
+		assert_eq!(first, Parser::new(tokens).parse().unwrap());
+	}
+
+	#[test]
+	fn test_parse_from_tokens() {
+		let tokens = [
+			Token::synthetic(TokenType::Print, 1),
+			Token::synthetic(TokenType::Number(1.0), 1),
+			Token::synthetic(TokenType::Semicolon, 1),
+			Token::synthetic(TokenType::Eof, 1),
+		];
+		let statements = Parser::from_tokens(tokens).parse().unwrap();
+		assert!(matches!(statements[..], [Stmt::Print(_)]));
+
+		// Comments are dropped by the adaptor rather than the parser.
+		let tokens = Scanner::with_comments("print 1; // one\nprint 2;").scan_tokens();
+		let statements = Parser::from_tokens(
+			tokens
+				.into_iter()
+				.filter(|token| !matches!(token.token_type, TokenType::Comment(_))),
+		)
+		.parse()
+		.unwrap();
+		assert_eq!(statements.len(), 2);
+	}
+
+	#[test]
+	fn test_parse_all() {
+		let source = "print 1; // one\nvar = 2;\n/* two */ print 3;\nprint 4 +;";
+		let result = Parser::new(Scanner::with_comments(source).scan_tokens()).parse_all();
+		assert_eq!(result.statements.len(), 2);
+		assert!(result
+			.statements
+			.iter()
+			.all(|statement| matches!(statement, Stmt::Print(_))));
+		let errors: Vec<_> = result.errors.iter().map(ToString::to_string).collect();
+		assert_eq!(
+			errors,
+			[
+				"[line 2] expected variable identifier at `=`",
+				"[line 4] expected expression at `;`",
+			]
+		);
+		let comments: Vec<_> = result.comments.iter().map(|c| c.lexeme.as_str()).collect();
+		assert_eq!(comments, ["// one", "/* two */"]);
+
+		// `parse` fails with the first of them.
+		let err = Parser::new(Scanner::new(source).scan_tokens())
+			.parse()
+			.unwrap_err();
+		assert_eq!(err.to_string(), errors[0]);
+	}
+
+	#[test]
+	fn test_parse_events() {
+		let events = |source: &str| {
+			let tokens = Scanner::new(source).scan_tokens();
+			Parser::new(tokens)
+				.parse_events()
+				.map(|event| match event {
+					ParseEvent::EnterStmt => "stmt".to_string(),
+					ParseEvent::ExitStmt => "/stmt".to_string(),
+					ParseEvent::EnterExpr(kind) => kind.to_string(),
+					ParseEvent::ExitExpr => "/expr".to_string(),
+					ParseEvent::Token(token) => format!("`{}`", token.lexeme),
+					ParseEvent::Error(err) => err.to_string(),
+				})
+				.collect::<Vec<_>>()
+				.join(" ")
+		};
+
+		assert_eq!(
+			events("print 1;"),
+			"stmt `print` Literal `1` /expr `;` /stmt"
+		);
+		assert_eq!(
+			events("print (a) + 1;"),
+			"stmt `print` Binary Grouping `(` Variable `a` /expr `)` /expr `+` Literal `1` /expr \
+			 /expr `;` /stmt"
+		);
+		assert_eq!(
+			events("while (x) { x = nil; }"),
+			"stmt `while` `(` Variable `x` /expr `)` stmt `{` stmt Assign `x` `=` Literal `nil` \
+			 /expr /expr `;` /stmt `}` /stmt /stmt"
+		);
+		// A broken declaration is reported as its tokens and its error.
+		assert_eq!(
+			events("var = 1; print 2;"),
+			"`var` `=` `1` `;` [line 1] expected variable identifier at `=` \
+			 stmt `print` Literal `2` /expr `;` /stmt"
+		);
+	}
+
+	#[test]
+	fn test_statements_iterator() {
+		let tokens = Scanner::new("print 1; var = 2; print 3;").scan_tokens();
+		let mut statements = Parser::new(tokens).statements();
+
+		assert!(matches!(statements.next(), Some(Ok(Stmt::Print(_)))));
+		let err = statements.next().unwrap().unwrap_err();
+		assert!(matches!(
+			err.kind,
+			ErrorKind::ExpectedIdentifier { place: "variable" }
+		));
+		let Some(Ok(Stmt::Print(values))) = statements.next() else {
+			panic!("expected the parser to recover");
+		};
+		assert_eq!(values[0].to_string(), "3");
+		assert!(statements.next().is_none());
+	}
+
+	#[test]
+	fn test_try_catch_and_throw() {
+		let statements = parse("try { throw 1 + 2; } catch (e) { print e; } try {} catch {}");
+
+		let Stmt::Try {
+			keyword,
+			body,
+			catch_name: Some(catch_name),
+			catch_body,
+		} = &statements[0]
+		else {
+			panic!("expected a try statement with a named catch, got {:?}", statements[0]);
+		};
+		let Stmt::Block(body) = body.as_ref() else {
+			panic!("expected a block");
+		};
+		let [Stmt::Throw { value, .. }] = &body[..] else {
+			panic!("expected a throw statement, got {body:?}");
+		};
+		assert_eq!(value.to_string(), "(+ 1 2)");
+		assert_eq!(keyword.token_type, TokenType::Try);
+		assert_eq!(catch_name.lexeme, "e");
+		assert!(matches!(catch_body.as_ref(), Stmt::Block(body) if body.len() == 1));
+
+		assert!(matches!(
+			&statements[1],
+			Stmt::Try {
+				catch_name: None,
+				..
+			}
+		));
+
+		let error = |source: &str| {
+			Parser::new(Scanner::new(source).scan_tokens())
+				.parse()
+				.unwrap_err()
+		};
+		assert!(matches!(error("catch (e) {}").kind, ErrorKind::CatchWithoutTry));
+		assert!(matches!(error("try {} print 1;").kind, ErrorKind::ExpectedCatch));
+		assert!(matches!(
+			error("try {} catch () {}").kind,
+			ErrorKind::ExpectedIdentifier { place: "exception" }
+		));
+	}
+
+	#[test]
+	fn test_synchronize() {
+		let mut parser = Parser::new(Scanner::new("var = 1 + 2; print 3;").scan_tokens());
+		assert!(parser.declaration().is_err());
+		parser.synchronize();
+		assert!(matches!(parser.declaration(), Ok(Stmt::Print(_))));
+		assert!(parser.is_at_end());
+
+		let mut parser = Parser::new(Scanner::new("print 1 + * 2 var x;").scan_tokens());
+		assert!(parser.declaration().is_err());
+		parser.synchronize();
+		assert!(matches!(parser.declaration(), Ok(Stmt::Var { .. })));
+	}
+
+	#[test]
+	fn test_lookahead() {
+		let mut parser = Parser::new(Scanner::new("var x = 1;").scan_tokens());
+		assert_eq!(parser.peek().unwrap().token_type, TokenType::Var);
+		assert_eq!(parser.peek_next().unwrap().lexeme, "x");
+		// Looking ahead doesn't consume anything.
+		assert_eq!(parser.peek().unwrap().token_type, TokenType::Var);
+		assert_eq!(parser.advance().unwrap().token_type, TokenType::Var);
+		assert_eq!(parser.peek().unwrap().lexeme, "x");
+		assert_eq!(parser.peek_next().unwrap().token_type, TokenType::Equal);
+	}
+
+	#[test]
+	fn test_owned_tokens_are_moved() {
+		let tokens = Scanner::new(&"var abc = \"text\" + abc;\n".repeat(100)).scan_tokens();
+		let owned = tokens.clone();
+		let (statements, moved) = count_allocations(|| Parser::new(owned).parse());
+		assert_eq!(statements.unwrap().len(), 100);
+		let (statements, cloned) = count_allocations(|| Parser::from_slice(&tokens).parse());
+		assert_eq!(statements.unwrap().len(), 100);
+
+		// Cloning from a borrowed buffer costs at least the lexeme of every token but the last.
+		assert!(moved + tokens.len() - 1 <= cloned, "{moved} moved, {cloned} cloned");
+	}
+
+	#[test]
+	fn test_dangling_else() {
+		let err = Parser::new(Scanner::new("else { }").scan_tokens())
+			.parse()
+			.unwrap_err();
+		assert!(matches!(err.kind, ErrorKind::DanglingElse));
+		assert_eq!(err.token.as_ref().unwrap().lexeme, "else");
+		assert_eq!(
+			err.to_string(),
+			"[line 1] `else` without a preceding `if` at `else`"
+		);
+	}
+
+	#[test]
+	fn test_error_lexeme() {
+		let message = |source: &str| {
+			Parser::new(Scanner::new(source).scan_tokens())
+				.parse()
+				.unwrap_err()
+				.to_string()
+		};
+		assert_eq!(message("var a = 1 1.50;"), "[line 1] expected `;` after statement at `1.50`");
+		assert_eq!(message("var a = 1 \"a\";"), "[line 1] expected `;` after statement at `\"a\"`");
+
+		let err = Error {
+			kind: ErrorKind::ExpectedSemicolon,
+			token: Some(Token::synthetic(TokenType::True, 2)),
+		};
+		assert_eq!(err.to_string(), "[line 2] expected `;` after statement at `true`");
+	}
+
+	#[test]
+	fn test_labeled_loops() {
+		let statements = parse(
+			"outer: while (true) { while (x) { break outer; continue; } }\n\
+			inner: for (var i = 0; i < 3; i = i + 1) continue inner;",
+		);
+		let Stmt::While {
+			body,
+			label: Some(label),
+			..
+		} = &statements[0]
+		else {
+			panic!("expected a labeled while loop, got {statements:?}");
+		};
+		assert_eq!(label.lexeme, "outer");
+		let Stmt::Block(body) = body.as_ref() else {
+			panic!("expected a block, got {body:?}");
+		};
+		let Stmt::While {
+			body, label: None, ..
+		} = &body[0]
+		else {
+			panic!("expected an unlabeled while loop, got {body:?}");
+		};
+		assert!(matches!(
+			body.as_ref(),
+			Stmt::Block(statements) if matches!(
+				&statements[..],
+				[
+					Stmt::Break { label: Some(label), .. },
+					Stmt::Continue { label: None, .. },
+				] if label.lexeme == "outer"
+			)
+		));
+
+		// The label of a `for` loop ends up on the `while` it's desugared into.
+		let Stmt::Block(for_loop) = &statements[1] else {
+			panic!("expected a desugared for loop, got {:?}", statements[1]);
+		};
+		assert!(matches!(
+			&for_loop[1],
+			Stmt::While { label: Some(label), .. } if label.lexeme == "inner"
+		));
+
+		// Labels are only recorded, not checked.
+		assert!(matches!(
+			&parse("break nowhere;")[..],
+			[Stmt::Break { label: Some(label), .. }] if label.lexeme == "nowhere"
+		));
+
+		let err = Parser::new(Scanner::new("a: print 1;").scan_tokens())
+			.parse()
+			.unwrap_err();
+		assert!(matches!(err.kind, ErrorKind::ExpectedLoopAfterLabel));
+		assert_eq!(err.token.unwrap().token_type, TokenType::Print);
+
+		let err = Parser::new(Scanner::new("while (x) break 1;").scan_tokens())
+			.parse()
+			.unwrap_err();
+		assert!(matches!(err.kind, ErrorKind::ExpectedSemicolon));
+	}
+
+	#[test]
+	fn test_assert() {
+		let statements = parse("assert a == 1; assert b, \"b is \" + b;");
+		assert!(matches!(
+			&statements[0],
+			Stmt::Assert { condition, message: None, .. } if condition.to_string() == "(== a 1)"
+		));
+		assert!(matches!(
+			&statements[1],
+			Stmt::Assert { message: Some(message), .. } if message.to_string() == "(+ b is  b)"
+		));
+
+		let err = Parser::new(Scanner::new("assert a, \"message\" print").scan_tokens())
+			.parse()
+			.unwrap_err();
+		assert!(matches!(err.kind, ErrorKind::ExpectedSemicolon));
+		assert_eq!(err.token.unwrap().token_type, TokenType::Print);
+	}
+
+	#[test]
+	fn test_static_methods() {
+		let statements = parse("class Foo { bar() {} class baz(a) {} qux() {} }");
+		let Stmt::Class {
+			methods,
+			static_methods,
+			..
+		} = &statements[0]
+		else {
+			panic!("expected a class, got {:?}", statements[0]);
+		};
+		let names = |methods: &[FunctionStatement]| {
+			methods
+				.iter()
+				.map(|method| method.name.lexeme.clone())
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(names(methods), ["bar", "qux"]);
+		assert_eq!(names(static_methods), ["baz"]);
+	}
+
+	#[test]
+	fn test_is() {
+		let expr = parse_expr("obj is Foo");
+		let Expr::Binary {
+			operator, right, ..
+		} = &expr
+		else {
+			panic!("expected a binary expression, got {expr:?}");
+		};
+		assert_eq!(operator.token_type, TokenType::Is);
+		assert!(matches!(right.as_ref(), Expr::Variable(_)));
+		assert_eq!(expr.to_string(), "(is obj Foo)");
+		assert_eq!(parse_expr("a + 1 is B == c").to_string(), "(== (is (+ a 1) B) c)");
+
+		let err = Parser::new(Scanner::new("obj is (Foo);").scan_tokens())
+			.parse()
+			.unwrap_err();
+		assert!(matches!(
+			err.kind,
+			ErrorKind::ExpectedIdentifier { place: "class" }
+		));
+		assert_eq!(err.token.unwrap().token_type, TokenType::LeftParen);
+	}
+
+	#[test]
+	fn test_precedence_of() {
+		assert_eq!(precedence_of(&TokenType::Star), Some(Precedence::Factor));
+		assert_eq!(precedence_of(&TokenType::EqualEqual), Some(Precedence::Equality));
+		assert_eq!(precedence_of(&TokenType::Is), Some(Precedence::Comparison));
+		assert_eq!(precedence_of(&TokenType::Bang), None);
+		assert_eq!(precedence_of(&TokenType::Semicolon), None);
+
+		assert!(Precedence::Assignment < Precedence::Or);
+		assert!(Precedence::Term < Precedence::Factor);
+		assert_eq!(Precedence::Equality.next(), Precedence::Range);
+		assert_eq!(Precedence::Primary.next(), Precedence::Primary);
+	}
+
+	#[test]
+	fn test_binary_operators() {
+		for (source, expected) in [
+			("1 - 2 - 3", "(- (- 1 2) 3)"),
+			("8 / 4 * 2", "(* (/ 8 4) 2)"),
+			("a and b and c", "(and (and a b) c)"),
+			("1 + 2 * 3 - 4", "(- (+ 1 (* 2 3)) 4)"),
+			("a or b and c == d < e + f * -g", "(or a (and b (== c (< d (+ e (* f (- g)))))))"),
+			("a * b + c < d != e and f or g", "(or (and (!= (< (+ (* a b) c) d) e) f) g)"),
+			("1 < 2 < 3 == 4 == 5", "(== (== (< (< 1 2) 3) 4) 5)"),
+			("x == a..b != y", "(!= (== x (.. a b)) y)"),
+		] {
+			assert_eq!(parse_expr(source).to_string(), expected, "{source}");
+		}
+		assert!(matches!(parse_expr("a or b"), Expr::Logical { .. }));
+		assert!(matches!(parse_expr("a ?? b"), Expr::Logical { .. }));
+		assert!(matches!(parse_expr("a >= b"), Expr::Binary { .. }));
+	}
+
+	#[test]
+	fn test_comments() {
+		let tokens = Scanner::with_comments("// hi\n var x; /* after */").scan_tokens();
+		let (statements, comments) = Parser::from_slice(&tokens).parse_with_comments().unwrap();
+		assert!(matches!(&statements[..], [Stmt::Var { .. }]));
+		let comments: Vec<_> = comments
+			.iter()
+			.map(|token| (token.token_type.to_string(), token.line, token.column))
+			.collect();
+		assert_eq!(
+			comments,
+			[("// hi".to_string(), 1, 1), ("/* after */".to_string(), 2, 9)]
+		);
+
+		// Comments are invisible to the grammar, even in the middle of an expression.
+		let tokens = Scanner::with_comments("1 + /* two */ 2").scan_tokens();
+		assert_eq!(Parser::new(tokens).parse_expression().unwrap().to_string(), "(+ 1 2)");
+	}
+
+	#[test]
+	fn test_range() {
+		assert!(matches!(
+			parse_expr("1..10"),
+			Expr::Range {
+				inclusive: false,
+				..
+			}
+		));
+		assert!(matches!(
+			parse_expr("1..=10"),
+			Expr::Range {
+				inclusive: true,
+				..
+			}
+		));
+		assert_eq!(parse_expr("1..10").to_string(), "(.. 1 10)");
+		assert_eq!(parse_expr("a + 1..=b * 2").to_string(), "(..= (+ a 1) (* b 2))");
+		assert_eq!(parse_expr("0..n == r").to_string(), "(== (.. 0 n) r)");
+		assert_eq!(parse_expr("a < b..c").to_string(), "(.. (< a b) c)");
+
+		// Ranges don't chain.
+		for source in ["1..2..3;", "x == 1..2..3;"] {
+			let err = Parser::new(Scanner::new(source).scan_tokens())
+				.parse()
+				.unwrap_err();
+			assert!(matches!(err.kind, ErrorKind::ExpectedSemicolon), "{source}");
+		}
+
+		for source in ["..;", "1..;", "..=2;"] {
+			let err = Parser::new(Scanner::new(source).scan_tokens())
+				.parse()
+				.unwrap_err();
+			assert!(
+				matches!(err.kind, ErrorKind::ExpectedExpression),
+				"{source}: {err:?}"
+			);
+		}
+	}
+
+	#[test]
+	fn test_coalesce() {
+		assert_eq!(parse_expr("a ?? b ?? c").to_string(), "(?? (?? a b) c)");
+		assert_eq!(parse_expr("a ?? b or c").to_string(), "(?? a (or b c))");
+		assert_eq!(parse_expr("a or b ?? c").to_string(), "(?? (or a b) c)");
+		assert_eq!(parse_expr("x = a ?? b").to_string(), "(= x (?? a b))");
+	}
+
+	#[test]
+	fn test_optional_chaining() {
+		let plain = parse_expr("a.b");
+		let optional = parse_expr("a?.b");
+		assert!(matches!(plain, Expr::Get { optional: false, .. }));
+		assert!(matches!(optional, Expr::Get { optional: true, .. }));
+		assert!(!plain.structurally_eq(&optional));
+		assert_eq!(optional.to_string(), "(?.b a)");
+		assert_eq!(parse_expr("a?.b(1).c").to_string(), "(.c (call (?.b a) 1))");
+
+		let err = Parser::new(Scanner::new("a?.b = 1;").scan_tokens())
+			.parse()
+			.unwrap_err();
+		assert!(matches!(err.kind, ErrorKind::InvalidAssignmentTarget));
+	}
+
+	#[test]
+	fn test_super_call() {
+		let expr = parse_expr("super(1, 2)");
+		let Expr::SuperCall(super_call) = &expr else {
+			panic!("expected a super call, got {expr:?}");
+		};
+		assert_eq!(super_call.arguments.len(), 2);
+		assert_eq!(super_call.closing_parenthesis.token_type, TokenType::RightParen);
+		assert_eq!(expr.to_string(), "(call super 1 2)");
+
+		assert_eq!(parse_expr("super().x").to_string(), "(.x (call super))");
+		assert_eq!(parse_expr("super.m(1)").to_string(), "(call super.m 1)");
+	}
+
+	#[test]
+	fn test_literal_accessors() {
+		let [number, string, t, f, nil, variable, group] =
+			["1.5", "\"s\"", "true", "false", "nil", "x", "(1)"].map(parse_expr);
+
+		assert_eq!(number.as_number(), Some(1.5));
+		assert_eq!(string.as_number(), None);
+		assert_eq!(group.as_number(), None);
+
+		assert_eq!(string.as_string(), Some("s"));
+		assert_eq!(variable.as_string(), None);
+
+		assert_eq!(t.as_bool(), Some(true));
+		assert_eq!(f.as_bool(), Some(false));
+		assert_eq!(nil.as_bool(), None);
+
+		assert!(nil.is_nil());
+		assert!(!f.is_nil());
+		assert!(!number.is_nil());
+	}
+
+	#[test]
+	fn test_print_list() {
+		let statements = parse("print 1; print a, b + 1, \"c\";");
+		let [Stmt::Print(single), Stmt::Print(list)] = &statements[..] else {
+			panic!("expected two print statements, got {statements:?}");
+		};
+		assert_eq!(single.len(), 1);
+		let values = list.iter().map(|value| value.to_string()).collect::<Vec<_>>();
+		assert_eq!(values, ["a", "(+ b 1)", "c"]);
+
+		let error = |source: &str| {
+			Parser::new(Scanner::new(source).scan_tokens())
+				.parse()
+				.unwrap_err()
+		};
+		let err = error("print a b;");
+		assert!(matches!(err.kind, ErrorKind::ExpectedComma));
+		assert_eq!(err.token.unwrap().lexeme, "b");
+		let err = error("print a, b print");
+		assert!(matches!(err.kind, ErrorKind::ExpectedSemicolon));
+		assert_eq!(err.token.unwrap().token_type, TokenType::Print);
+		assert!(matches!(error("print a,;").kind, ErrorKind::ExpectedExpression));
+	}
+
+	#[test]
+	fn test_if_chain_branches() {
+		let statements = parse(
+			"if (a) print 1; else if (b) { print 2; } else if (c) print 3; else print 4;",
+		);
+		let (branches, else_branch) = statements[0].if_chain_branches();
+		let conditions = branches
+			.iter()
+			.map(|(condition, _)| condition.to_string())
+			.collect::<Vec<_>>();
+		assert_eq!(conditions, ["a", "b", "c"]);
+		assert!(matches!(branches[0].1, Stmt::Print(_)));
+		assert!(matches!(branches[1].1, Stmt::Block(_)));
+		assert!(matches!(else_branch, Some(Stmt::Print(_))));
+
+		// An `if` nested in a block isn't part of the chain.
+		let statements = parse("if (a) print 1; else { if (b) print 2; }");
+		let (branches, else_branch) = statements[0].if_chain_branches();
+		assert_eq!(branches.len(), 1);
+		assert!(matches!(else_branch, Some(Stmt::Block(_))));
+
+		assert_eq!(parse("print 1;")[0].if_chain_branches().0.len(), 0);
+	}
+
+	#[test]
+	fn test_class_fields() {
+		let statements = parse("class Foo { var x = 1 + 2; bar() {} var y; }");
+		let Stmt::Class {
+			methods, fields, ..
+		} = &statements[0]
+		else {
+			panic!("expected a class, got {:?}", statements[0]);
+		};
+		assert_eq!(methods.len(), 1);
+		let fields = fields
+			.iter()
+			.map(|(name, initializer)| {
+				(name.lexeme.as_str(), initializer.as_ref().map(Expr::to_string))
+			})
+			.collect::<Vec<_>>();
+		assert_eq!(fields, [("x", Some("(+ 1 2)".to_string())), ("y", None)]);
+
+		for source in ["class Foo { var x = 1 }", "class Foo { var x bar() {} }"] {
+			let err = Parser::new(Scanner::new(source).scan_tokens())
+				.parse()
+				.unwrap_err();
+			assert!(matches!(err.kind, ErrorKind::ExpectedSemicolon), "{source}");
+		}
+	}
+
+	#[test]
+	fn test_class_body_recovery() {
+		let source = "class A { bad(a b) {} ok() {} worse( { print 1; } fine() {} }\n\
+			print 2;";
+		let errors = Parser::new(Scanner::new(source).scan_tokens())
+			.parse_all()
+			.errors;
+		assert_eq!(errors.len(), 2);
+		assert!(matches!(errors[0].kind, ErrorKind::ExpectedComma));
+		assert!(matches!(
+			errors[1].kind,
+			ErrorKind::ExpectedIdentifier { place: "parameter" }
+		));
+
+		// The class is still parsed past its broken members.
+		let mut statements = Parser::new(Scanner::new(source).scan_tokens()).statements();
+		assert!(statements.next().unwrap().is_err());
+		assert!(statements.next().unwrap().is_err());
+		assert!(matches!(statements.next().unwrap(), Ok(Stmt::Print(_))));
+		assert!(statements.next().is_none());
+	}
+
+	#[test]
+	fn test_function_expressions() {
+		let expr = parse_expr("(fun(x){ return x; })(5)");
+		let Expr::Call(call) = &expr else {
+			panic!("expected a call, got {expr:?}");
+		};
+		let Expr::Grouping(callee) = &call.callee else {
+			panic!("expected a grouping, got {:?}", call.callee);
+		};
+		let Expr::Function(function) = callee.as_ref() else {
+			panic!("expected a function expression, got {callee:?}");
+		};
+		assert!(function.name.is_none());
+		assert!(matches!(&function.body[..], [Stmt::Return { .. }]));
+		assert_eq!(expr.to_string(), "(call (group (fun (x))) 5)");
+
+		let expr = Parser::new(Scanner::new("fun named(a, b) {}()").scan_tokens())
+			.parse_expression()
+			.unwrap();
+		assert_eq!(expr.to_string(), "(call (fun named (a b)))");
+		assert_eq!(expr.span(), (1, 1));
+
+		// With a name, a statement starting with `fun` is still a declaration.
+		assert!(matches!(&parse("fun f() {}")[..], [Stmt::Function(_)]));
+		assert!(matches!(&parse("fun () {};")[..], [Stmt::Expr(Expr::Function(_))]));
+	}
+
+	#[test]
+	fn test_unterminated_block() {
+		let err = Parser::new(Scanner::new("{ var x = 1;").scan_tokens())
+			.parse()
+			.unwrap_err();
+		assert!(matches!(err.kind, ErrorKind::ExpectedRightBrace));
+		assert_eq!(err.token.as_ref().unwrap().token_type, TokenType::Semicolon);
+		assert_eq!(err.to_string(), "[line 1] expected `}` at the end of a block at `;`");
+
+		let err = Parser::new(Scanner::new("fun f() {\n\tprint 1;\n\n").scan_tokens())
+			.parse()
+			.unwrap_err();
+		assert!(matches!(err.kind, ErrorKind::ExpectedRightBrace));
+		assert_eq!(err.token.unwrap().line, 2);
+	}
+
+	#[test]
+	fn test_trailing_commas_option() {
+		let source = "fun f(a, b,) { return a; } f(1, 2,);";
+
+		let err = Parser::new(Scanner::new(source).scan_tokens())
+			.parse()
+			.unwrap_err();
+		assert!(matches!(
+			err.kind,
+			ErrorKind::ExpectedIdentifier { place: "parameter" }
+		));
+
+		let options = ParserOptions {
+			trailing_commas: true,
+			..Default::default()
+		};
+		let statements = Parser::with_options(Scanner::new(source).scan_tokens(), options.clone())
+			.parse()
+			.unwrap();
+		let Stmt::Function(function) = &statements[0] else {
+			panic!("expected a function");
+		};
+		assert_eq!(function.params.len(), 2);
+		let Stmt::Expr(call) = &statements[1] else {
+			panic!("expected an expression statement");
+		};
+		assert_eq!(call.to_string(), "(call f 1 2)");
+
+		// A trailing comma after the last parameter allowed isn't another parameter.
+		let params = (0..255).map(|i| format!("p{i},")).collect::<String>();
+		let source = format!("fun f({params}) {{}}");
+		assert!(Parser::with_options(Scanner::new(&source).scan_tokens(), options)
+			.parse()
+			.is_ok());
+	}
+
+	#[test]
+	fn test_return_outside_function() {
+		let source = "return 1;\n\
+			fun f() { return 2; }\n\
+			class A { m() { return fun () { return; }; } }";
+		assert!(Parser::new(Scanner::new(source).scan_tokens())
+			.parse()
+			.is_ok());
+
+		let options = ParserOptions {
+			reject_return_outside_function: true,
+			..Default::default()
+		};
+		let errors = Parser::with_options(Scanner::new(source).scan_tokens(), options)
+			.parse_all()
+			.errors;
+		assert_eq!(errors.len(), 1);
+		assert!(matches!(errors[0].kind, ErrorKind::ReturnOutsideFunction));
+		let keyword = errors[0].token.as_ref().unwrap();
+		assert_eq!(keyword.token_type, TokenType::Return);
+		assert_eq!(keyword.line, 1);
+	}
+
+	#[test]
+	fn test_duplicate_parameters() {
+		let parser = |source: &str, reject_duplicate_parameters| {
+			let options = ParserOptions {
+				reject_duplicate_parameters,
+				..Default::default()
+			};
+			Parser::with_options(Scanner::new(source).scan_tokens(), options)
+		};
+		let source = "fun f(a, b, a) {} var g = fun (c, c) {};";
+		assert!(parser(source, false).parse().is_ok());
+
+		let errors = parser(source, true).parse_all().errors;
+		assert_eq!(errors.len(), 2);
+		assert!(errors
+			.iter()
+			.all(|error| matches!(error.kind, ErrorKind::DuplicateParameter)));
+		// Attached to the second occurrence.
+		let columns: Vec<_> = errors.iter().map(|e| e.token.as_ref().unwrap().column).collect();
+		assert_eq!(columns, [13, 35]);
+
+		assert!(parser("fun f(a, b, c) {} class A { m(a, ab) {} }", true)
+			.parse()
+			.is_ok());
+	}
+
+	#[test]
+	fn test_peek_type_and_is_at_end() {
+		let mut parser = Parser::new(Scanner::new("print 1;").scan_tokens());
+		assert_eq!(parser.peek_type(), Some(&TokenType::Print));
+		assert!(!parser.is_at_end());
+
+		parser.declaration().unwrap();
+		assert_eq!(parser.peek_type(), Some(&TokenType::Eof));
+		assert!(parser.is_at_end());
+
+		let parser = Parser::new(Vec::new());
+		assert_eq!(parser.peek_type(), None);
+		assert!(parser.is_at_end());
+	}
+
+	#[test]
+	fn test_arguments_limit() {
+		let call = |arguments: usize| {
+			let arguments = (0..arguments).map(|i| i.to_string()).collect::<Vec<_>>();
+			let source = format!("f({});", arguments.join(","));
+			Parser::new(Scanner::new(&source).scan_tokens()).parse()
+		};
+
+		let Stmt::Expr(Expr::Call(call_expr)) = &call(255).unwrap()[0] else {
+			panic!("expected a call");
+		};
+		assert_eq!(call_expr.arguments.len(), 255);
+
+		let err = call(256).unwrap_err();
+		assert!(matches!(err.kind, ErrorKind::ExceededArgumentsLimit { limit: 255 }));
+		assert_eq!(err.token.unwrap().lexeme, "255");
+	}
+
+	#[test]
+	fn test_arguments_limit_bench() {
+		let arguments = (0..200).map(|i| format!("a{i}")).collect::<Vec<_>>();
+		let tokens = Scanner::new(&format!("f({});", arguments.join(", "))).scan_tokens();
+		let options = |max_arguments| ParserOptions {
+			max_arguments,
+			..Default::default()
+		};
+
+		// Checking the limit on the way doesn't cost a single allocation.
+		let owned = tokens.clone();
+		let (limited, with_limit) =
+			count_allocations(|| Parser::with_options(owned, options(Some(255))).parse());
+		let owned = tokens.clone();
+		let (unlimited, without_limit) =
+			count_allocations(|| Parser::with_options(owned, options(None)).parse());
+		assert_eq!(limited.unwrap(), unlimited.unwrap());
+		assert_eq!(with_limit, without_limit);
+		// The tokens are moved into the tree rather than copied, so the allocations are only
+		// the tree's own.
+		assert!(with_limit < tokens.len() / 10, "{with_limit} allocations");
+
+		let buffers = vec![tokens; 1000];
+		let start = Instant::now();
+		for buffer in buffers {
+			Parser::with_options(buffer, options(Some(255))).parse().unwrap();
+		}
+		let elapsed = start.elapsed();
+		// Very generous, so it only catches something going quadratic, even in debug builds.
+		assert!(elapsed < Duration::from_secs(10), "1000 parses took {elapsed:?}");
+	}
+
+	#[test]
+	fn test_spread_arguments() {
+		let expr = parse_expr("f(a, ...b, c)");
+		let Expr::Call(call) = &expr else {
+			panic!("expected a call, got {expr:?}");
+		};
+		let spread: Vec<_> = call.arguments.iter().map(|argument| argument.spread).collect();
+		assert_eq!(spread, [false, true, false]);
+		assert_eq!(call.arguments[1].expr.to_string(), "b");
+		assert_eq!(expr.to_string(), "(call f a (... b) c)");
+		assert_eq!(parse_expr("super(...a.b)").to_string(), "(call super (... (.b a)))");
+
+		// A spread counts as a single argument towards the limit.
+		let arguments = vec!["...x"; 255].join(",");
+		assert!(Parser::new(Scanner::new(&format!("f({arguments});")).scan_tokens())
+			.parse()
+			.is_ok());
+	}
+
+	#[test]
+	fn test_named_arguments() {
+		let names = |source: &str| {
+			let Expr::Call(call) = parse_expr(source) else {
+				panic!("expected a call");
+			};
+			call.arguments
+				.iter()
+				.map(|argument| argument.name.as_ref().map(|name| name.lexeme.clone()))
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(
+			names("f(1, b, x: 2, y: b)"),
+			[None, None, Some("x".to_string()), Some("y".to_string())]
+		);
+		assert_eq!(
+			names("f(x: 1, y: 2)"),
+			[Some("x".to_string()), Some("y".to_string())]
+		);
+		assert_eq!(parse_expr("f(a, x: a + 1)").to_string(), "(call f a (x: (+ a 1)))");
+		// Only a bare name can be followed by `:`.
+		assert!(Parser::new(Scanner::new("f(a.b: 1)").scan_tokens())
+			.parse_expression()
+			.is_err());
+
+		for source in ["f(x: 1, 2);", "f(x: 1, ...y);"] {
+			let err = Parser::new(Scanner::new(source).scan_tokens())
+				.parse()
+				.unwrap_err();
+			assert!(
+				matches!(err.kind, ErrorKind::PositionalAfterNamed),
+				"{source}: {err:?}"
+			);
+		}
+	}
+
+	#[test]
+	fn test_match() {
+		let expr = parse_expr("match (x) { 1 => \"a\", 2 => \"b\", _ => \"c\", }");
+		assert_eq!(expr.to_string(), "(match x (1 a) (2 b) (_ c))");
+		let Expr::Match(match_expr) = expr else {
+			panic!("expected a match");
+		};
+		assert_eq!(match_expr.arms.len(), 2);
+		assert_eq!(match_expr.closing_brace.token_type, TokenType::RightBrace);
+		assert_eq!(parse_expr("match (x + 1) {}").to_string(), "(match (+ x 1))");
+		// A match nested in a pattern leaves the rest of the outer pattern a pattern.
+		assert_eq!(
+			parse_expr("match (v) { match (w) { 1 => 2 } + (x) => 3 }").to_string(),
+			"(match v ((+ (match w (1 2)) (group x)) 3))"
+		);
+
+		let err = |source: &str| {
+			Parser::new(Scanner::new(source).scan_tokens())
+				.parse()
+				.unwrap_err()
+				.kind
+		};
+		assert!(matches!(err("match (x) { 1 \"a\" };"), ErrorKind::ExpectedFatArrow));
+		// The default arm has to come last.
+		assert!(matches!(err("match (x) { _ => 1, 2 => 3 };"), ErrorKind::ExpectedRightBrace));
+
+		// Anywhere else, `_` is just a name, even at the start of a pattern.
+		assert_eq!(
+			parse_expr("match (_) { _ + 1 => _, _ => 0 }").to_string(),
+			"(match _ ((+ _ 1) _) (_ 0))"
+		);
+		let statements = parse("var _ = 1; fun f(_) { return _; } print _;");
+		assert!(matches!(&statements[0], Stmt::Var { name, .. } if name.lexeme == "_"));
+		let Stmt::Function(function) = &statements[1] else {
+			panic!("expected a function");
+		};
+		assert_eq!(function.params[0].lexeme, "_");
+	}
+
+	#[test]
+	fn test_block_span() {
+		let statements = parse("{\n\tvar a = 1;\n\n\tprint a +\n\t\t2;\n\t;\n}\n");
+		let [Stmt::Block(block)] = &statements[..] else {
+			panic!("expected a block");
+		};
+		assert_eq!(block_span(block), Some((2, 5)));
+		assert_eq!(block_span(&block[1..]), Some((4, 5)));
+		assert_eq!(block_span(&parse("fun f(a) {\n}\nclass C {}")), Some((1, 3)));
+		assert_eq!(block_span(&parse(";;")), None);
+		assert_eq!(block_span(&[]), None);
+	}
+
+	#[test]
+	fn test_call_arity() {
+		assert_eq!(parse_expr("f()").call_arity(), Some(0));
+		assert_eq!(parse_expr("a.b(1, c, d = 2)").call_arity(), Some(3));
+		assert_eq!(parse_expr("f(...xs, 1)").call_arity(), Some(2));
+		assert_eq!(parse_expr("f").call_arity(), None);
+		assert_eq!(parse_expr("(f(1))").call_arity(), None);
+
+		assert!(parse_expr("f(...xs, 1)").has_spread_argument());
+		assert!(!parse_expr("f(xs, 1)").has_spread_argument());
+		assert!(!parse_expr("xs").has_spread_argument());
+	}
+
+	#[test]
+	fn test_arrow_function() {
+		let function = |source: &str| {
+			let Expr::Function(function) = parse_expr(source) else {
+				panic!("expected a function expression");
+			};
+			function
+		};
+
+		let constant = function("() => 1");
+		assert!(constant.params.is_empty());
+		assert!(matches!(
+			&constant.body[..],
+			[Stmt::Return { value: Some(value), .. }] if value.to_string() == "1"
+		));
+		let identity = function("(x) => x");
+		assert_eq!(identity.params[0].lexeme, "x");
+		assert!(matches!(
+			&identity.body[..],
+			[Stmt::Return { keyword, .. }] if keyword.token_type == TokenType::FatArrow
+		));
+		assert_eq!(function("(a, b) => a + b").body.len(), 1);
+		assert_eq!(parse_expr("(x) => (y) => x + y").to_string(), "(fun (x))");
+
+		assert_eq!(parse_expr("(1 + 2)").to_string(), "(group (+ 1 2))");
+		assert_eq!(parse_expr("(x)").to_string(), "(group x)");
+		// In a match arm, `(x) =>` is a grouping pattern.
+		assert_eq!(
+			parse_expr("match (v) { (x) => 1 }").to_string(),
+			"(match v ((group x) 1))"
+		);
+		assert_eq!(
+			parse_expr("match (v) { -(x) => 1 }").to_string(),
+			"(match v ((- (group x)) 1))"
+		);
+		// Nested in brackets, it can only be an arrow function, and the grouping reading of it
+		// would fail to parse.
+		for source in [
+			"match (v) { ((x) => x) => 1 }",
+			"match (v) { f((x) => x) => 1 }",
+			"match (v) { { var g = (x) => x; g } => 1 }",
+			"match (v) { if (((x) => x)(1)) 1 else 2 => 3 }",
+			"match (v) { match (w) { 1 => (x) => x } => 1 }",
+		] {
+			let Expr::Match(match_expr) = parse_expr(source) else {
+				panic!("expected a match");
+			};
+			assert_eq!(match_expr.arms.len(), 1, "{source}");
+		}
+
+		// A trailing comma only makes it an arrow function where a parameter list could have one.
+		let parse_with = |source: &str, trailing_commas| {
+			let options = ParserOptions {
+				trailing_commas,
+				..Default::default()
+			};
+			Parser::with_options(Scanner::new(source).scan_tokens(), options).parse_expression()
+		};
+		let Ok(Expr::Function(function)) = parse_with("(a, b,) => a", true) else {
+			panic!("expected a function expression");
+		};
+		assert_eq!(function.params.len(), 2);
+		let err = parse_with("(a, b,) => a", false).unwrap_err();
+		assert!(matches!(err.kind, ErrorKind::ExpectedRightParenthesis));
+	}
+
+	#[test]
+	fn test_block_expression() {
+		let block = |source: &str| {
+			let expr = Parser::new(Scanner::new(source).scan_tokens()).parse_expression();
+			let Ok(Expr::Block(block)) = expr else {
+				panic!("expected a block expression");
+			};
+			block
+		};
+		let expr = block("{ var x = 1; x + 1 }");
+		assert_eq!(expr.statements.len(), 1);
+		assert_eq!(expr.value.to_string(), "(+ x 1)");
+		assert_eq!(expr.right_brace.token_type, TokenType::RightBrace);
+
+		// Expression statements end with `;`, only the trailing one with `}`.
+		let expr = block("{ f(); x = 2; while (a) a = b; x }");
+		assert!(matches!(
+			expr.statements[..],
+			[Stmt::Expr(Expr::Call(_)), Stmt::Expr(Expr::Assign { .. }), Stmt::While { .. }]
+		));
+		assert!(matches!(expr.value, Expr::Variable(_)));
+		assert_eq!(block("{ { 1; } 2 }").statements.len(), 1);
+		assert_eq!(parse_expr("-{ 1 } + { 2 }").to_string(), "(+ (- (block 1)) (block 2))");
+
+		let err = |source: &str| {
+			Parser::new(Scanner::new(source).scan_tokens())
+				.parse()
+				.unwrap_err()
+				.kind
+		};
+		assert!(matches!(err("print { var x = 1; };"), ErrorKind::ExpectedExpression));
+		assert!(matches!(err("print {};"), ErrorKind::ExpectedExpression));
+		assert!(matches!(err("print { 1 2 };"), ErrorKind::ExpectedSemicolon));
+		// At the start of a statement, `{` is a block statement.
+		assert!(matches!(err("{ 1 }"), ErrorKind::ExpectedSemicolon));
+	}
+
+	#[test]
+	fn test_if_expression() {
+		let [Stmt::Var {
+			initializer: Some(Expr::If(if_expr)),
+			..
+		}] = &parse("var x = if (true) 1 else 2;")[..]
+		else {
+			panic!("expected an if expression");
+		};
+		assert!(matches!(if_expr.condition, Expr::Literal(_)));
+		assert_eq!(if_expr.then_branch.to_string(), "1");
+		assert_eq!(if_expr.else_branch.to_string(), "2");
+		assert_eq!(
+			parse_expr("1 + if (a) b else c + d").to_string(),
+			"(+ 1 (if a b (+ c d)))"
+		);
+
+		// At the start of a statement, it's still an `if` statement.
+		let statements = parse("if (a) print 1;");
+		assert!(matches!(statements[..], [Stmt::If { else_branch: None, .. }]));
+
+		let err = Parser::new(Scanner::new("var x = if (a) 1;").scan_tokens())
+			.parse()
+			.unwrap_err();
+		assert!(matches!(err.kind, ErrorKind::ExpectedElse));
+	}
+
+	#[test]
+	fn test_format_errors() {
+		let source = "print 1\n\tvar x = 2 3;\n";
+		let tokens = Scanner::new(source).scan_tokens();
+		let error = |kind, index: usize| Error {
+			kind,
+			token: Some(tokens[index].clone()),
+		};
+		let errors = [
+			error(ErrorKind::ExpectedSemicolon, 6),
+			Error {
+				kind: ErrorKind::MaxDepthExceeded,
+				token: None,
+			},
+			error(ErrorKind::ExpectedSemicolon, 1),
+			error(ErrorKind::ExpectedSemicolon, 6),
+		];
+		assert_eq!(
+			format_errors(&errors, source),
+			"[line 1] expected `;` after statement at `1`\n  \
+			|\n1 | print 1\n  |       ^\n\n\
+			[line 2] expected `;` after statement at `3`\n  \
+			|\n2 | \tvar x = 2 3;\n  | \t          ^\n\n\
+			[line unknown] code nested too deeply at the end"
+		);
+	}
+
+	#[test]
+	fn test_function_statement_helpers() {
+		let function = |source: &str| {
+			let Some(Stmt::Function(function)) = parse(source).pop() else {
+				panic!("expected a function");
+			};
+			function
+		};
+
+		let f = function("fun f(a, b, a, b) {}");
+		assert_eq!(f.arity(), 4);
+		assert_eq!(f.name_str(), "f");
+		let duplicate = f.has_duplicate_params().unwrap();
+		assert_eq!((duplicate.lexeme.as_str(), duplicate.column), ("a", 13));
+
+		assert_eq!(function("fun f(a, a) {}").has_duplicate_params().unwrap().column, 10);
+		assert!(function("fun g(a, b, c) {}").has_duplicate_params().is_none());
+		assert!(function("fun h() {}").has_duplicate_params().is_none());
+	}
+
+	#[test]
+	fn test_empty_statements() {
+		assert_eq!(parse(";;;"), [Stmt::Empty, Stmt::Empty, Stmt::Empty]);
+		assert!(matches!(parse("print 1;;")[..], [Stmt::Print(_), Stmt::Empty]));
+		assert!(matches!(
+			parse("while (a) ;")[..],
+			[Stmt::While { ref body, .. }] if **body == Stmt::Empty
+		));
+		assert!(matches!(
+			parse("{ ; }")[..],
+			[Stmt::Block(ref statements)] if statements[..] == [Stmt::Empty]
+		));
+	}
+
+	#[test]
+	fn test_expected_expression_token() {
+		for (source, lexeme, column) in [("* 3;", "*", 1), ("1 + * 3;", "*", 5), ("-/2;", "/", 2)] {
+			let err = Parser::new(Scanner::new(source).scan_tokens())
+				.parse()
+				.unwrap_err();
+			assert!(matches!(err.kind, ErrorKind::ExpectedExpression), "{source}: {err:?}");
+			let token = err.token.unwrap();
+			assert_eq!((token.lexeme.as_str(), token.column), (lexeme, column), "{source}");
+		}
+		let err = Parser::new(Scanner::new("* 3").scan_tokens())
+			.parse_expression()
+			.unwrap_err();
+		assert_eq!(err.to_string(), "[line 1] expected expression at `*`");
+	}
+
+	#[test]
+	fn test_import() {
+		let statements = parse("import \"math\";\nimport \"lib/strings\" as s;");
+		let [Stmt::Import {
+			path, alias: None, ..
+		}, Stmt::Import {
+			path: other_path,
+			alias: Some(alias),
+			..
+		}] = &statements[..]
+		else {
+			panic!("expected two imports, got {statements:?}");
+		};
+		assert_eq!(path.token_type, TokenType::String("math".to_string()));
+		assert_eq!(other_path.token_type, TokenType::String("lib/strings".to_string()));
+		assert_eq!(alias.lexeme, "s");
+
+		let err = |source: &str| {
+			Parser::new(Scanner::new(source).scan_tokens())
+				.parse()
+				.unwrap_err()
+		};
+		let error = err("import math;");
+		assert!(matches!(error.kind, ErrorKind::ExpectedStringLiteral));
+		assert_eq!(error.token.unwrap().lexeme, "math");
+		assert!(matches!(
+			err("import \"math\" as;").kind,
+			ErrorKind::ExpectedIdentifier { place: "alias" }
+		));
+		assert!(matches!(err("import \"math\" m;").kind, ErrorKind::ExpectedSemicolon));
+	}
+
+	#[test]
+	fn test_var_scope() {
+		let scopes = |source: &str| {
+			parse(source)
+				.into_iter()
+				.map(|stmt| match stmt {
+					Stmt::Var { name, scope, .. } => (name.lexeme, scope),
+					stmt => panic!("expected a variable declaration, got {stmt:?}"),
+				})
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(
+			scopes("global var x = 1; local var y; var z;"),
+			[
+				("x".to_string(), VarScope::Global),
+				("y".to_string(), VarScope::Local),
+				("z".to_string(), VarScope::Unqualified),
+			]
+		);
+
+		let err = Parser::new(Scanner::new("global x = 1;").scan_tokens())
+			.parse()
+			.unwrap_err();
+		assert!(matches!(err.kind, ErrorKind::ExpectedVar));
+		assert_eq!(err.token.unwrap().lexeme, "x");
+	}
+
+	#[test]
+	fn test_undefined() {
+		let statements = parse("var x = undefined;");
+		let [Stmt::Var {
+			initializer: Some(Expr::Literal(token)),
+			..
+		}] = &statements[..]
+		else {
+			panic!("expected a variable declaration, got {statements:?}");
+		};
+		assert_eq!(token.token_type, TokenType::Undefined);
+		assert_eq!(parse_expr("undefined ?? nil").to_string(), "(?? undefined nil)");
+	}
+
+	#[test]
+	fn test_parameters_limit() {
+		let function = |params: usize| {
+			let params = (0..params).map(|i| format!("p{i}")).collect::<Vec<_>>();
+			let source = format!("fun f({}) {{}}", params.join(","));
+			Parser::new(Scanner::new(&source).scan_tokens()).parse()
+		};
+
+		let Stmt::Function(function_stmt) = &function(255).unwrap()[0] else {
+			panic!("expected a function");
+		};
+		assert_eq!(function_stmt.params.len(), 255);
+
+		let err = function(256).unwrap_err();
+		assert!(matches!(err.kind, ErrorKind::ExceededParametersLimit { limit: 255 }));
+		assert_eq!(err.token.as_ref().unwrap().lexeme, "p255");
+		assert!(err.to_string().contains("can't have more than 255 parameters"));
+	}
+
+	#[test]
+	fn test_custom_arguments_limit() {
+		let parse_with = |source: &str, max_arguments| {
+			let options = ParserOptions {
+				max_arguments,
+				..Default::default()
+			};
+			Parser::with_options(Scanner::new(source).scan_tokens(), options).parse()
+		};
+
+		assert!(parse_with("f(1, 2, 3); fun g(a, b, c) {}", Some(3)).is_ok());
+		let err = parse_with("f(1, 2, 3, 4);", Some(3)).unwrap_err();
+		assert!(matches!(err.kind, ErrorKind::ExceededArgumentsLimit { limit: 3 }));
+		assert_eq!(err.token.as_ref().unwrap().lexeme, "4");
+		assert!(err.to_string().contains("can't have more than 3 arguments"));
+		let err = parse_with("var h = fun (a, b, c, d) {};", Some(3)).unwrap_err();
+		assert!(matches!(err.kind, ErrorKind::ExceededParametersLimit { limit: 3 }));
+		assert_eq!(err.token.as_ref().unwrap().lexeme, "d");
+
+		let arguments = vec!["1"; 1000].join(", ");
+		assert!(parse_with(&format!("f({arguments});"), None).is_ok());
+
+		// A trailing comma doesn't count as another argument or parameter.
+		let parse_with = |source: &str| {
+			let options = ParserOptions {
+				trailing_commas: true,
+				max_arguments: Some(3),
+				..Default::default()
+			};
+			Parser::with_options(Scanner::new(source).scan_tokens(), options).parse()
+		};
+		assert!(parse_with("f(1, 2, 3,); fun g(a, b, c,) {} var h = fun (a, b, c,) {};").is_ok());
+		let err = parse_with("f(1, 2, 3, 4,);").unwrap_err();
+		assert!(matches!(err.kind, ErrorKind::ExceededArgumentsLimit { limit: 3 }));
+		let err = parse_with("fun g(a, b, c, d,) {}").unwrap_err();
+		assert!(matches!(err.kind, ErrorKind::ExceededParametersLimit { limit: 3 }));
+		assert_eq!(err.token.as_ref().unwrap().lexeme, "d");
+	}
+
+	#[test]
+	fn test_print_is_keyword() {
+		let parse_with = |source: &str, print_is_keyword: bool| {
+			let mut scanner = Scanner::new(source);
+			if !print_is_keyword {
+				scanner = scanner.without_print_keyword();
+			}
+			Parser::new(scanner.scan_tokens()).parse()
+		};
+
+		let statements = parse_with("print(x);", true).unwrap();
+		assert!(matches!(&statements[..], [Stmt::Print(_)]));
+		assert!(parse_with("var p = print;", true).is_err());
+
+		let statements = parse_with("print(x); var p = print;", false).unwrap();
+		assert!(matches!(
+			&statements[0],
+			Stmt::Expr(Expr::Call(call)) if call.callee.to_string() == "print"
+		));
+		assert!(matches!(
+			&statements[1],
+			Stmt::Var {
+				initializer: Some(Expr::Variable(name)),
+				..
+			} if name.lexeme == "print"
+		));
+		let err = parse_with("print x;", false).unwrap_err();
+		assert!(matches!(err.kind, ErrorKind::ExpectedSemicolon));
+	}
+
+	#[test]
+	fn test_require_braces() {
+		let statements = parse("while (c) x = x + 1; while (c) { x = x + 1; }");
+		assert!(matches!(
+			&statements[0],
+			Stmt::While { body, .. } if matches!(body.as_ref(), Stmt::Expr(_))
+		));
+		assert!(matches!(
+			&statements[1],
+			Stmt::While { body, .. } if matches!(body.as_ref(), Stmt::Block(_))
+		));
+
+		let parse_with = |source: &str| {
+			let options = ParserOptions {
+				require_braces: true,
+				..Default::default()
+			};
+			Parser::with_options(Scanner::new(source).scan_tokens(), options).parse()
+		};
+		assert!(parse_with("while (c) { x = x + 1; } for (;;) {}").is_ok());
+		assert!(parse_with("if (a) {} else if (b) {} else {}").is_ok());
+		for source in [
+			"while (c) x = x + 1;",
+			"if (a) print 1;",
+			"if (a) {} else print 1;",
+			"for (;;) print 1;",
+		] {
+			let err = parse_with(source).unwrap_err();
+			assert!(matches!(err.kind, ErrorKind::ExpectedLeftBrace), "{source}");
+		}
+	}
+
+	#[test]
+	fn test_cursor_helpers() {
+		let mut parser = Parser::new(Scanner::new("a.b").scan_tokens());
+		assert!(parser.check(&TokenType::Identifier(String::new())));
+		assert!(!parser.check(&TokenType::Dot));
+		assert_eq!(parser.peek_next().map(|t| &t.token_type), Some(&TokenType::Dot));
+
+		assert_eq!(parser.advance().map(|t| t.lexeme), Some("a".to_string()));
+		assert!(parser.check(&TokenType::Dot));
+		parser.advance();
+		parser.advance();
+		assert!(parser.check(&TokenType::Eof));
+		assert!(parser.peek_next().is_none());
+		parser.advance();
+		assert!(parser.advance().is_none());
+		assert!(!parser.check(&TokenType::Eof));
+	}
+
+	#[test]
+	fn test_max_depth() {
+		// The default limit is meant for the main thread's stack, rather than a test thread's.
+		let parse = |source: String| {
+			std::thread::Builder::new()
+				.stack_size(8 << 20)
+				.spawn(move || Parser::new(Scanner::new(&source).scan_tokens()).parse())
+				.unwrap()
+				.join()
+				.unwrap()
+		};
+		let nested = |open: &str, inner: &str, close: &str, n: usize| {
+			format!("{}{inner}{}", open.repeat(n), close.repeat(n))
+		};
+
+		for source in [
+			nested("(", "1", ")", 100_000) + ";",
+			nested("-", "1", "", 100_000) + ";",
+			nested("{", "", "}", 100_000),
+			nested("if (a) ", "print 1;", "", 100_000),
+			nested("fun f() {", "", "}", 100_000),
+		] {
+			let err = parse(source).unwrap_err();
+			assert!(matches!(err.kind, ErrorKind::MaxDepthExceeded));
+		}
+		// The limit doesn't get in the way of sensibly nested code.
+		assert!(parse(nested("(", "1", ")", 100) + ";").is_ok());
+		assert!(parse(nested("{", "print f(-(1));", "}", 100)).is_ok());
+
+		let options = ParserOptions {
+			max_depth: 5,
+			..Default::default()
+		};
+		let parse = |source: &str| {
+			Parser::with_options(Scanner::new(source).scan_tokens(), options.clone()).parse()
+		};
+		// The expression itself is a level, and each grouping, argument list or block another.
+		for source in ["((((1))));", "f(g((h(1))));", "{{{{{}}}}}", "{{{{print 1;}}}}"] {
+			assert!(parse(source).is_ok(), "{source}");
+		}
+		for source in ["(((((1)))));", "f(g((h(i(1)))));", "{{{{{{}}}}}}", "{{{{{print 1;}}}}}"] {
+			let err = parse(source).unwrap_err();
+			assert!(matches!(err.kind, ErrorKind::MaxDepthExceeded), "{source}");
+		}
+	}
+
+	#[test]
+	fn test_assignment_is_an_expression() {
+		let statements = parse("print a = 5; var x = a = b = 2; f(a = 1);");
+		let [Stmt::Print(values), Stmt::Var {
+			initializer: Some(initializer),
+			..
+		}, Stmt::Expr(call)] = &statements[..]
+		else {
+			panic!("unexpected statements {statements:?}");
+		};
+		assert_eq!(values[0].to_string(), "(= a 5)");
+		assert_eq!(initializer.to_string(), "(= a (= b 2))");
+		assert_eq!(call.to_string(), "(call f (= a 1))");
+		assert_eq!(parse_expr("(a = 5) + 1").to_string(), "(+ (group (= a 5)) 1)");
+		assert_eq!(parse_expr("a.b = c = d").to_string(), "(= .b a (= c d))");
+	}
+
+	#[test]
+	fn test_deep_assignment_chain() {
+		let source = format!("{}1;", "a = ".repeat(5000));
+		let statements = parse(&source);
+		let Stmt::Expr(mut expr) = statements.into_iter().next().unwrap() else {
+			panic!("expected an expression statement");
+		};
+		let mut assignments = 0;
+		while let Expr::Assign { value, .. } = expr {
+			assignments += 1;
+			expr = *value;
+		}
+		assert_eq!(assignments, 5000);
+		assert!(matches!(expr, Expr::Literal(_)));
+
+		// The innermost invalid target is reported, as before.
+		let err = Parser::new(Scanner::new("a = 1 = b.c = 2;").scan_tokens())
+			.parse()
+			.unwrap_err();
+		assert!(matches!(err.kind, ErrorKind::InvalidAssignmentTarget));
+		assert_eq!(err.token.unwrap().column, 7);
+	}
+
+	#[test]
+	fn test_bare_return() {
+		let statements = parse("fun f() { return; return nil; }");
+		let [Stmt::Function(function)] = &statements[..] else {
+			panic!("expected a function");
+		};
+		let [bare, nil] = &function.body[..] else {
+			panic!("expected two statements");
+		};
+		assert!(matches!(bare, Stmt::Return { value: None, .. }));
+		assert!(matches!(
+			nil,
+			Stmt::Return { value: Some(Expr::Literal(token)), .. }
+				if token.token_type == TokenType::Nil
+		));
+		assert!(!bare.structurally_eq(nil));
+	}
+
+	#[test]
+	fn test_compound_assignment() {
+		assert_eq!(parse_expr("a += 2").to_string(), "(= a (+ a 2))");
+		assert_eq!(parse_expr("a -= b /= 2").to_string(), "(= a (- a (= b (/ b 2))))");
+		let Expr::Set { value, .. } = parse_expr("obj.field *= 3") else {
+			panic!("expected a set expression");
+		};
+		assert!(matches!(
+			*value,
+			Expr::Binary { ref operator, .. } if operator.token_type == TokenType::Star
+		));
+		assert_eq!(value.to_string(), "(* (.field obj) 3)");
+
+		let err = Parser::new(Scanner::new("a + b += 1;").scan_tokens())
+			.parse()
+			.unwrap_err();
+		assert!(matches!(err.kind, ErrorKind::InvalidAssignmentTarget));
+		assert!(matches!(
+			err.token,
+			Some(Token { token_type: TokenType::PlusEqual, .. })
+		));
+
+		// `f()` would be called twice by the desugaring.
+		assert_eq!(
+			parse_expr("this.a.b -= 1").to_string(),
+			"(= .b (.a this) (- (.b (.a this)) 1))"
+		);
+		let err = Parser::new(Scanner::new("f().x += 1;").scan_tokens())
+			.parse()
+			.unwrap_err();
+		assert!(matches!(err.kind, ErrorKind::InvalidAssignmentTarget));
+		assert_eq!(err.token.unwrap().column, 7);
+		let tokens = Scanner::new("f().x += 1;").scan_tokens();
+		assert!(Parser::new(tokens).parse_arena().is_err());
+	}
+
+	#[test]
+	fn test_for_without_condition_line() {
+		let statements = parse("\n\n\nfor (;;) print 1;");
+		let Stmt::While {
+			condition: Expr::Literal(token),
+			..
+		} = &statements[0]
+		else {
+			panic!("expected a while loop, got {statements:?}");
+		};
+		assert_eq!(token.token_type, TokenType::True);
+		assert_eq!(token.line, 4);
+	}
+
+	#[test]
+	fn test_deterministic_universal_indices() {
+		reset_universal_index();
+		let expr = parse_expr("1 + 2");
+
+		let expected = Expr::Binary {
+			left: Box::new(Expr::Literal(Token {
+				token_type: TokenType::Number(1.0),
+				lexeme: "1".to_string(),
+				line: 1,
+				column: 1,
+				universal_index: 0,
+			})),
+			operator: Token {
+				token_type: TokenType::Plus,
+				lexeme: "+".to_string(),
+				line: 1,
+				column: 3,
+				universal_index: 1,
+			},
+			right: Box::new(Expr::Literal(Token {
+				token_type: TokenType::Number(2.0),
+				lexeme: "2".to_string(),
+				line: 1,
+				column: 5,
+				universal_index: 2,
+			})),
+		};
+		assert_eq!(expr, expected);
+	}
+
+	#[test]
+	fn test_expr_span() {
+		let expr = parse_expr("\na\n.b = f(\n1,\n2\n) +\n-c");
+		assert_eq!(expr.span(), (2, 7));
+
+		let Expr::Set { value, .. } = &expr else {
+			panic!("expected a set expression, got {expr:?}");
+		};
+		let Expr::Binary { left, .. } = value.as_ref() else {
+			panic!("expected a binary expression, got {value:?}");
+		};
+		assert_eq!(left.span(), (3, 6));
+		assert_eq!(parse_expr("this").span(), (1, 1));
+	}
+
+	#[test]
+	fn test_expr_size() {
+		// Was 160 bytes before the `Call` and `Super` payloads got boxed. `Super` holds two tokens
+		// and was the largest variant, followed by `Call` with 112 bytes. Now it's a token and two
+		// boxes (`Binary`, `Logical`, `Set`) plus the discriminant.
+		assert_eq!(std::mem::size_of::<Expr>(), 104);
+	}
 }
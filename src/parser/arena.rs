@@ -0,0 +1,345 @@
+use crate::parser::builder::NodeBuilder;
+use crate::parser::{Argument, FunctionStatement, VarScope};
+use crate::token::Token;
+
+/// Index of an expression in [Ast::exprs].
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct ExprId(usize);
+
+/// Index of a statement in [Ast::stmts].
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct StmtId(usize);
+
+/// One [Expr](crate::parser::Expr) node, with its child expressions as `E` and its statements
+/// as `S`. The parser hands these to a [NodeBuilder], and an [Ast] stores them as they are, with
+/// ids for children.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ExprKind<E = ExprId, S = StmtId> {
+	Literal(Token),
+	Variable(Token),
+	Assign {
+		name: Token,
+		value: E,
+	},
+	Unary {
+		operator: Token,
+		expr: E,
+	},
+	Binary {
+		left: E,
+		operator: Token,
+		right: E,
+	},
+	Grouping(E),
+	Logical {
+		left: E,
+		operator: Token,
+		right: E,
+	},
+	Call {
+		callee: E,
+		closing_parenthesis: Token,
+		arguments: Vec<Argument<E>>,
+	},
+	Get {
+		object: E,
+		name: Token,
+		optional: bool,
+	},
+	Set {
+		object: E,
+		name: Token,
+		value: E,
+	},
+	This {
+		keyword: Token,
+	},
+	Super {
+		keyword: Token,
+		method: Token,
+	},
+	SuperCall {
+		keyword: Token,
+		closing_parenthesis: Token,
+		arguments: Vec<Argument<E>>,
+	},
+	Function {
+		keyword: Token,
+		name: Option<Token>,
+		params: Vec<Token>,
+		body: Vec<S>,
+		closing_brace: Token,
+	},
+	Match {
+		keyword: Token,
+		subject: E,
+		arms: Vec<(E, E)>,
+		default: Option<E>,
+		closing_brace: Token,
+	},
+	Block {
+		left_brace: Token,
+		statements: Vec<S>,
+		value: E,
+		right_brace: Token,
+	},
+	If {
+		keyword: Token,
+		condition: E,
+		then_branch: E,
+		else_branch: E,
+	},
+	Range {
+		start: E,
+		end: E,
+		inclusive: bool,
+	},
+}
+
+/// One [Stmt](crate::parser::Stmt) node, like [ExprKind].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum StmtKind<E = ExprId, S = StmtId> {
+	Expr(E),
+	Print(Vec<E>),
+	Var {
+		name: Token,
+		initializer: Option<E>,
+		scope: VarScope,
+	},
+	Block(Vec<S>),
+	If {
+		condition: E,
+		then_branch: S,
+		else_branch: Option<S>,
+	},
+	While {
+		condition: E,
+		body: S,
+		label: Option<Token>,
+	},
+	Empty,
+	Break {
+		keyword: Token,
+		label: Option<Token>,
+	},
+	Continue {
+		keyword: Token,
+		label: Option<Token>,
+	},
+	Function(FunctionStatement<S>),
+	Return {
+		keyword: Token,
+		value: Option<E>,
+	},
+	Class {
+		name: Token,
+		superclass: Option<Token>,
+		methods: Vec<FunctionStatement<S>>,
+		static_methods: Vec<FunctionStatement<S>>,
+		fields: Vec<(Token, Option<E>)>,
+	},
+	Throw {
+		keyword: Token,
+		value: E,
+	},
+	Import {
+		keyword: Token,
+		path: Token,
+		alias: Option<Token>,
+	},
+	Try {
+		keyword: Token,
+		body: S,
+		catch_name: Option<Token>,
+		catch_body: S,
+	},
+	Assert {
+		keyword: Token,
+		condition: E,
+		message: Option<E>,
+	},
+}
+
+/// A whole program with every node stored in one of two flat buffers. Children are always pushed
+/// before their parents. The target of an assignment stays behind as a node of its own, which
+/// only a compound assignment refers to.
+#[derive(Debug, Default)]
+pub struct Ast {
+	pub exprs: Vec<ExprKind>,
+	pub stmts: Vec<StmtKind>,
+	/// Top-level declarations, in source order.
+	pub roots: Vec<StmtId>,
+}
+
+impl Ast {
+	#[allow(dead_code)]
+	pub fn expr(&self, id: ExprId) -> &ExprKind {
+		&self.exprs[id.0]
+	}
+
+	#[allow(dead_code)]
+	pub fn stmt(&self, id: StmtId) -> &StmtKind {
+		&self.stmts[id.0]
+	}
+}
+
+impl NodeBuilder for Ast {
+	type Expr = ExprId;
+	type Stmt = StmtId;
+
+	fn build_expr(&mut self, expr: ExprKind) -> ExprId {
+		self.exprs.push(expr);
+		ExprId(self.exprs.len() - 1)
+	}
+
+	fn build_stmt(&mut self, stmt: StmtKind) -> StmtId {
+		self.stmts.push(stmt);
+		StmtId(self.stmts.len() - 1)
+	}
+
+	fn assign(&mut self, target: ExprId, value: ExprId) -> Option<ExprId> {
+		let assignment = match &self.exprs[target.0] {
+			ExprKind::Variable(name) => ExprKind::Assign {
+				name: name.clone(),
+				value,
+			},
+			ExprKind::Get {
+				object,
+				name,
+				optional: false,
+			} => ExprKind::Set {
+				object: *object,
+				name: name.clone(),
+				value,
+			},
+			_ => return None,
+		};
+		Some(self.build_expr(assignment))
+	}
+
+	fn is_reevaluable(&self, expr: &ExprId) -> bool {
+		match &self.exprs[expr.0] {
+			ExprKind::Variable(_) | ExprKind::This { .. } => true,
+			ExprKind::Get {
+				object,
+				optional: false,
+				..
+			} => self.is_reevaluable(object),
+			_ => false,
+		}
+	}
+
+	fn first_token<'b>(&'b self, expr: &'b ExprId) -> &'b Token {
+		match &self.exprs[expr.0] {
+			ExprKind::Literal(token) | ExprKind::Variable(token) => token,
+			ExprKind::Assign { name, .. } => name,
+			ExprKind::Unary { operator, .. } => operator,
+			ExprKind::Binary { left, .. } | ExprKind::Logical { left, .. } => {
+				self.first_token(left)
+			}
+			ExprKind::Grouping(expr) => self.first_token(expr),
+			ExprKind::Call { callee, .. } => self.first_token(callee),
+			ExprKind::Get { object, .. } | ExprKind::Set { object, .. } => self.first_token(object),
+			ExprKind::This { keyword }
+			| ExprKind::Super { keyword, .. }
+			| ExprKind::SuperCall { keyword, .. }
+			| ExprKind::Function { keyword, .. }
+			| ExprKind::Match { keyword, .. }
+			| ExprKind::If { keyword, .. } => keyword,
+			ExprKind::Block { left_brace, .. } => left_brace,
+			ExprKind::Range { start, .. } => self.first_token(start),
+		}
+	}
+
+	fn last_token<'b>(&'b self, expr: &'b ExprId) -> &'b Token {
+		match &self.exprs[expr.0] {
+			ExprKind::Literal(token) | ExprKind::Variable(token) => token,
+			ExprKind::Assign { value, .. } | ExprKind::Set { value, .. } => self.last_token(value),
+			ExprKind::Unary { expr, .. } | ExprKind::Grouping(expr) => self.last_token(expr),
+			ExprKind::Binary { right, .. } | ExprKind::Logical { right, .. } => {
+				self.last_token(right)
+			}
+			ExprKind::Call {
+				closing_parenthesis,
+				..
+			}
+			| ExprKind::SuperCall {
+				closing_parenthesis,
+				..
+			} => closing_parenthesis,
+			ExprKind::Get { name, .. } => name,
+			ExprKind::This { keyword } => keyword,
+			ExprKind::Super { method, .. } => method,
+			ExprKind::Function { closing_brace, .. } | ExprKind::Match { closing_brace, .. } => {
+				closing_brace
+			}
+			ExprKind::Block { right_brace, .. } => right_brace,
+			ExprKind::If { else_branch, .. } => self.last_token(else_branch),
+			ExprKind::Range { end, .. } => self.last_token(end),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{ExprKind, StmtKind};
+	use crate::alloc_count::count_allocations;
+	use crate::parser::Parser;
+	use crate::scanner::Scanner;
+	use crate::token::TokenType;
+
+	#[test]
+	fn test_parse_arena() {
+		let source = "var a = 1 + f(2, 3);\n\
+			fun g(x) { while (x) print -x; }";
+		let ast = Parser::new(Scanner::new(source).scan_tokens())
+			.parse_arena()
+			.unwrap();
+		// One node per expression and statement, with no boxes in between.
+		assert_eq!(ast.exprs.len(), 9);
+		assert_eq!(ast.stmts.len(), 4);
+		assert_eq!(ast.roots.len(), 2);
+
+		let StmtKind::Var {
+			initializer: Some(initializer),
+			..
+		} = ast.stmt(ast.roots[0])
+		else {
+			panic!("expected a variable declaration");
+		};
+		let ExprKind::Binary { left, right, .. } = ast.expr(*initializer) else {
+			panic!("expected a binary expression");
+		};
+		assert!(matches!(
+			ast.expr(*left),
+			ExprKind::Literal(token) if token.token_type == TokenType::Integer(1)
+		));
+		let ExprKind::Call { arguments, .. } = ast.expr(*right) else {
+			panic!("expected a call");
+		};
+		assert_eq!(arguments.len(), 2);
+
+		let StmtKind::Function(function) = ast.stmt(ast.roots[1]) else {
+			panic!("expected a function");
+		};
+		let StmtKind::While { body, .. } = ast.stmt(function.body[0]) else {
+			panic!("expected a while loop");
+		};
+		assert!(matches!(ast.stmt(*body), StmtKind::Print(_)));
+	}
+
+	#[test]
+	fn test_parse_arena_allocations() {
+		let source = "fun f(a, b) { return -(a + b) * 2 > g(a, b.c) and !h; }\n".repeat(200);
+		let tokens = Scanner::new(&source).scan_tokens();
+
+		let (statements, tree) = count_allocations(|| Parser::from_slice(&tokens).parse());
+		assert_eq!(statements.unwrap().len(), 200);
+		let (ast, arena) = count_allocations(|| Parser::from_slice(&tokens).parse_arena());
+		assert_eq!(ast.unwrap().roots.len(), 200);
+
+		// Both clone the same tokens, but the arena has no box per node. Building the tree along
+		// the way would cost at least as much as the tree alone.
+		assert!(arena < tree, "{arena} allocations, {tree} for the tree");
+	}
+}
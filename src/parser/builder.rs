@@ -0,0 +1,291 @@
+use crate::parser::arena::{ExprKind, StmtKind};
+use crate::parser::{
+	BlockExpr, CallExpr, Expr, FunctionExpr, IfExpr, MatchExpr, Stmt, SuperCallExpr, SuperExpr,
+};
+use crate::token::Token;
+
+/// Where the [Parser](crate::parser::Parser) puts the nodes it parses. Each node is handed over
+/// once its children are built, and whatever it's turned into stands for it from then on.
+pub trait NodeBuilder {
+	type Expr: Clone;
+	type Stmt;
+
+	fn build_expr(&mut self, expr: ExprKind<Self::Expr, Self::Stmt>) -> Self::Expr;
+
+	fn build_stmt(&mut self, stmt: StmtKind<Self::Expr, Self::Stmt>) -> Self::Stmt;
+
+	/// `target = value`, or `None` if `target` can't be assigned to.
+	fn assign(&mut self, target: Self::Expr, value: Self::Expr) -> Option<Self::Expr>;
+
+	/// Whether evaluating `expr` twice is the same as evaluating it once, i.e. it's a variable,
+	/// `this`, or a property of one.
+	fn is_reevaluable(&self, expr: &Self::Expr) -> bool;
+
+	/// See [Expr::first_token].
+	fn first_token<'b>(&'b self, expr: &'b Self::Expr) -> &'b Token;
+
+	/// See [Expr::last_token].
+	fn last_token<'b>(&'b self, expr: &'b Self::Expr) -> &'b Token;
+}
+
+/// Builds the usual tree of [Expr] and [Stmt], where every child is a box of its own.
+#[derive(Debug, Default)]
+pub struct Tree;
+
+impl NodeBuilder for Tree {
+	type Expr = Expr;
+	type Stmt = Stmt;
+
+	fn build_expr(&mut self, expr: ExprKind<Expr, Stmt>) -> Expr {
+		match expr {
+			ExprKind::Literal(token) => Expr::Literal(token),
+			ExprKind::Variable(name) => Expr::Variable(name),
+			ExprKind::Assign { name, value } => Expr::Assign {
+				name,
+				value: Box::new(value),
+			},
+			ExprKind::Unary { operator, expr } => Expr::Unary {
+				operator,
+				expr: Box::new(expr),
+			},
+			ExprKind::Binary {
+				left,
+				operator,
+				right,
+			} => Expr::Binary {
+				left: Box::new(left),
+				operator,
+				right: Box::new(right),
+			},
+			ExprKind::Grouping(expr) => Expr::Grouping(Box::new(expr)),
+			ExprKind::Logical {
+				left,
+				operator,
+				right,
+			} => Expr::Logical {
+				left: Box::new(left),
+				operator,
+				right: Box::new(right),
+			},
+			ExprKind::Call {
+				callee,
+				closing_parenthesis,
+				arguments,
+			} => Expr::Call(Box::new(CallExpr {
+				callee,
+				closing_parenthesis,
+				arguments,
+			})),
+			ExprKind::Get {
+				object,
+				name,
+				optional,
+			} => Expr::Get {
+				object: Box::new(object),
+				name,
+				optional,
+			},
+			ExprKind::Set {
+				object,
+				name,
+				value,
+			} => Expr::Set {
+				object: Box::new(object),
+				name,
+				value: Box::new(value),
+			},
+			ExprKind::This { keyword } => Expr::This { keyword },
+			ExprKind::Super { keyword, method } => {
+				Expr::Super(Box::new(SuperExpr { keyword, method }))
+			}
+			ExprKind::SuperCall {
+				keyword,
+				closing_parenthesis,
+				arguments,
+			} => Expr::SuperCall(Box::new(SuperCallExpr {
+				keyword,
+				closing_parenthesis,
+				arguments,
+			})),
+			ExprKind::Function {
+				keyword,
+				name,
+				params,
+				body,
+				closing_brace,
+			} => Expr::Function(Box::new(FunctionExpr {
+				keyword,
+				name,
+				params,
+				body,
+				closing_brace,
+			})),
+			ExprKind::Match {
+				keyword,
+				subject,
+				arms,
+				default,
+				closing_brace,
+			} => Expr::Match(Box::new(MatchExpr {
+				keyword,
+				subject,
+				arms,
+				default,
+				closing_brace,
+			})),
+			ExprKind::Block {
+				left_brace,
+				statements,
+				value,
+				right_brace,
+			} => Expr::Block(Box::new(BlockExpr {
+				left_brace,
+				statements,
+				value,
+				right_brace,
+			})),
+			ExprKind::If {
+				keyword,
+				condition,
+				then_branch,
+				else_branch,
+			} => Expr::If(Box::new(IfExpr {
+				keyword,
+				condition,
+				then_branch,
+				else_branch,
+			})),
+			ExprKind::Range {
+				start,
+				end,
+				inclusive,
+			} => Expr::Range {
+				start: Box::new(start),
+				end: Box::new(end),
+				inclusive,
+			},
+		}
+	}
+
+	fn build_stmt(&mut self, stmt: StmtKind<Expr, Stmt>) -> Stmt {
+		match stmt {
+			StmtKind::Expr(expr) => Stmt::Expr(expr),
+			StmtKind::Print(values) => Stmt::Print(values),
+			StmtKind::Var {
+				name,
+				initializer,
+				scope,
+			} => Stmt::Var {
+				name,
+				initializer,
+				scope,
+			},
+			StmtKind::Block(statements) => Stmt::Block(statements),
+			StmtKind::If {
+				condition,
+				then_branch,
+				else_branch,
+			} => Stmt::If {
+				condition,
+				then_branch: Box::new(then_branch),
+				else_branch: else_branch.map(Box::new),
+			},
+			StmtKind::While {
+				condition,
+				body,
+				label,
+			} => Stmt::While {
+				condition,
+				body: Box::new(body),
+				label,
+			},
+			StmtKind::Empty => Stmt::Empty,
+			StmtKind::Break { keyword, label } => Stmt::Break { keyword, label },
+			StmtKind::Continue { keyword, label } => Stmt::Continue { keyword, label },
+			StmtKind::Function(function) => Stmt::Function(function),
+			StmtKind::Return { keyword, value } => Stmt::Return { keyword, value },
+			StmtKind::Class {
+				name,
+				superclass,
+				methods,
+				static_methods,
+				fields,
+			} => Stmt::Class {
+				name,
+				superclass,
+				methods,
+				static_methods,
+				fields,
+			},
+			StmtKind::Throw { keyword, value } => Stmt::Throw { keyword, value },
+			StmtKind::Import {
+				keyword,
+				path,
+				alias,
+			} => Stmt::Import {
+				keyword,
+				path,
+				alias,
+			},
+			StmtKind::Try {
+				keyword,
+				body,
+				catch_name,
+				catch_body,
+			} => Stmt::Try {
+				keyword,
+				body: Box::new(body),
+				catch_name,
+				catch_body: Box::new(catch_body),
+			},
+			StmtKind::Assert {
+				keyword,
+				condition,
+				message,
+			} => Stmt::Assert {
+				keyword,
+				condition,
+				message,
+			},
+		}
+	}
+
+	fn assign(&mut self, target: Expr, value: Expr) -> Option<Expr> {
+		match target {
+			Expr::Variable(name) => Some(Expr::Assign {
+				name,
+				value: Box::new(value),
+			}),
+			Expr::Get {
+				object,
+				name,
+				optional: false,
+			} => Some(Expr::Set {
+				object,
+				name,
+				value: Box::new(value),
+			}),
+			_ => None,
+		}
+	}
+
+	fn is_reevaluable(&self, expr: &Expr) -> bool {
+		match expr {
+			Expr::Variable(_) | Expr::This { .. } => true,
+			Expr::Get {
+				object,
+				optional: false,
+				..
+			} => self.is_reevaluable(object),
+			_ => false,
+		}
+	}
+
+	fn first_token<'b>(&'b self, expr: &'b Expr) -> &'b Token {
+		expr.first_token()
+	}
+
+	fn last_token<'b>(&'b self, expr: &'b Expr) -> &'b Token {
+		expr.last_token()
+	}
+}
@@ -0,0 +1,361 @@
+use std::collections::HashMap;
+
+use crate::parser::visitor::{expr_children, Node, StmtVisitor};
+use crate::parser::{Expr, FunctionStatement, Stmt};
+use crate::token::Token;
+
+/// Finds statements that can never run because they follow a `return` in the same block, either
+/// directly or through an `if` whose branches all return. Returns the first token of every such
+/// statement.
+#[allow(dead_code)]
+pub fn find_unreachable(stmts: &[Stmt]) -> Vec<Token> {
+	/// Returns whether the block always returns.
+	fn check_block(stmts: &[Stmt], unreachable: &mut Vec<Token>) -> bool {
+		let mut returned = false;
+		for stmt in stmts {
+			if returned {
+				unreachable.extend(stmt_first_token(stmt).cloned());
+			} else {
+				returned = check_stmt(stmt, unreachable);
+			}
+		}
+		returned
+	}
+
+	/// Returns whether the statement always returns.
+	fn check_stmt(stmt: &Stmt, unreachable: &mut Vec<Token>) -> bool {
+		match stmt {
+			Stmt::Return { .. } | Stmt::Throw { .. } => true,
+			Stmt::Block(statements) => check_block(statements, unreachable),
+			Stmt::If {
+				then_branch,
+				else_branch,
+				..
+			} => {
+				let then_returns = check_stmt(then_branch, unreachable);
+				let else_returns = else_branch
+					.as_ref()
+					.map(|else_branch| check_stmt(else_branch, unreachable))
+					.unwrap_or_default();
+				then_returns && else_returns
+			}
+			Stmt::While { body, .. } => {
+				check_stmt(body, unreachable);
+				false
+			}
+			Stmt::Function(function) => {
+				check_block(&function.body, unreachable);
+				false
+			}
+			Stmt::Class {
+				methods,
+				static_methods,
+				..
+			} => {
+				for method in methods.iter().chain(static_methods) {
+					check_block(&method.body, unreachable);
+				}
+				false
+			}
+			Stmt::Try {
+				body, catch_body, ..
+			} => {
+				let body_returns = check_stmt(body, unreachable);
+				let catch_returns = check_stmt(catch_body, unreachable);
+				body_returns && catch_returns
+			}
+			Stmt::Expr(_)
+			| Stmt::Print(_)
+			| Stmt::Var { .. }
+			| Stmt::Empty
+			| Stmt::Assert { .. }
+			| Stmt::Import { .. }
+			| Stmt::Break { .. }
+			| Stmt::Continue { .. } => false,
+		}
+	}
+
+	let mut unreachable = Vec::new();
+	check_block(stmts, &mut unreachable);
+	unreachable
+}
+
+/// Finds `if` and `while` conditions (including desugared `for` conditions) that are an assignment,
+/// likely a mistyped `==`. The tree doesn't keep the `=` token, so the assigned name is returned
+/// instead. Wrapping the assignment in parentheses silences the lint.
+#[allow(dead_code)]
+pub fn find_assign_in_condition(stmts: &[Stmt]) -> Vec<Token> {
+	struct Finder(Vec<Token>);
+
+	impl Finder {
+		fn check(&mut self, condition: &Expr) {
+			if let Expr::Assign { name, .. } | Expr::Set { name, .. } = condition {
+				self.0.push(name.clone());
+			}
+		}
+	}
+
+	impl StmtVisitor for Finder {
+		fn visit_if(&mut self, condition: &Expr, then_branch: &Stmt, else_branch: Option<&Stmt>) {
+			self.check(condition);
+			then_branch.accept(self);
+			if let Some(else_branch) = else_branch {
+				else_branch.accept(self);
+			}
+		}
+
+		fn visit_while(&mut self, condition: &Expr, body: &Stmt, _label: Option<&Token>) {
+			self.check(condition);
+			body.accept(self);
+		}
+	}
+
+	let mut finder = Finder(Vec::new());
+	stmts.iter().for_each(|stmt| stmt.accept(&mut finder));
+	finder.0
+}
+
+fn stmt_first_token(stmt: &Stmt) -> Option<&Token> {
+	match stmt {
+		Stmt::Expr(expr) => Some(expr.first_token()),
+		Stmt::Print(exprs) => exprs.first().map(Expr::first_token),
+		Stmt::Var { name, .. } | Stmt::Class { name, .. } => Some(name),
+		Stmt::Block(statements) => statements.first().and_then(stmt_first_token),
+		Stmt::Empty => None,
+		Stmt::If { condition, .. } | Stmt::While { condition, .. } => {
+			Some(condition.first_token())
+		}
+		Stmt::Function(function) => Some(&function.name),
+		Stmt::Return { keyword, .. }
+		| Stmt::Throw { keyword, .. }
+		| Stmt::Assert { keyword, .. }
+		| Stmt::Import { keyword, .. }
+		| Stmt::Try { keyword, .. }
+		| Stmt::Break { keyword, .. }
+		| Stmt::Continue { keyword, .. } => Some(keyword),
+	}
+}
+
+/// Finds variable declarations whose initializer reads the variable being declared, like
+/// `var a = a;`. The resolver rejects these in local scopes, while at the top level they read
+/// the previous global of that name. Returns the offending reference. Function bodies and block
+/// expressions are skipped, as the name may legitimately refer to the function itself or to
+/// a shadowing variable there.
+#[allow(dead_code)]
+pub fn find_self_referential_init(stmts: &[Stmt]) -> Vec<Token> {
+	fn find<'a>(expr: &'a Expr, name: &Token) -> Option<&'a Token> {
+		match expr {
+			Expr::Variable(variable) if variable.lexeme == name.lexeme => Some(variable),
+			Expr::Function(_) | Expr::Block(_) => None,
+			_ => expr_children(expr).into_iter().find_map(|child| match child {
+				Node::Expr(child) => find(child, name),
+				Node::Stmt(_) => None,
+			}),
+		}
+	}
+
+	struct Finder(Vec<Token>);
+
+	impl StmtVisitor for Finder {
+		fn visit_var(&mut self, name: &Token, initializer: Option<&Expr>) {
+			self.0.extend(initializer.and_then(|expr| find(expr, name)).cloned());
+		}
+	}
+
+	let mut finder = Finder(Vec::new());
+	stmts.iter().for_each(|stmt| stmt.accept(&mut finder));
+	finder.0
+}
+
+/// Finds function declarations and methods with an empty body, including ones nested in other
+/// functions. Returns their names.
+#[allow(dead_code)]
+pub fn find_empty_functions(stmts: &[Stmt]) -> Vec<Token> {
+	struct Finder(Vec<Token>);
+
+	impl StmtVisitor for Finder {
+		fn visit_function(&mut self, function: &FunctionStatement) {
+			if function.body.is_empty() {
+				self.0.push(function.name.clone());
+			}
+			function.body.iter().for_each(|statement| statement.accept(self));
+		}
+	}
+
+	let mut finder = Finder(Vec::new());
+	stmts.iter().for_each(|stmt| stmt.accept(&mut finder));
+	finder.0
+}
+
+/// Finds variables declared twice directly in the same block, which the resolver rejects.
+/// Nested blocks are separate scopes, so shadowing an outer variable there isn't reported.
+/// Returns pairs of the first declaration and the redeclaration.
+#[allow(dead_code)]
+pub fn find_shadowed_vars(stmts: &[Stmt]) -> Vec<(Token, Token)> {
+	struct Finder(Vec<(Token, Token)>);
+
+	impl StmtVisitor for Finder {
+		fn visit_block(&mut self, statements: &[Stmt]) {
+			let mut declared = HashMap::new();
+			for statement in statements {
+				if let Stmt::Var { name, .. } = statement {
+					match declared.get(&name.lexeme) {
+						Some(&first) => self.0.push((Token::clone(first), name.clone())),
+						None => {
+							declared.insert(&name.lexeme, name);
+						}
+					}
+				}
+				statement.accept(self);
+			}
+		}
+	}
+
+	let mut finder = Finder(Vec::new());
+	stmts.iter().for_each(|stmt| stmt.accept(&mut finder));
+	finder.0
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{
+		find_assign_in_condition, find_empty_functions, find_self_referential_init,
+		find_shadowed_vars, find_unreachable,
+	};
+	use crate::parser::{Parser, Stmt};
+	use crate::scanner::Scanner;
+
+	fn parse(source: &str) -> Vec<Stmt> {
+		Parser::new(Scanner::new(source).scan_tokens())
+			.parse()
+			.unwrap()
+	}
+
+	#[test]
+	fn test_find_unreachable() {
+		let statements = parse(
+			"fun f(a) {\n\
+				if (a) {\n\
+					return 1;\n\
+					print \"dead 1\";\n\
+				}\n\
+				return 2;\n\
+				var dead2 = 3;\n\
+				dead3();\n\
+			}\n\
+			class A {\n\
+				m(a) {\n\
+					if (a) return 1; else { return 2; }\n\
+					print \"dead 4\";\n\
+				}\n\
+				n(a) {\n\
+					if (a) return 1;\n\
+					while (a) return 2;\n\
+					print \"alive\";\n\
+				}\n\
+			}",
+		);
+
+		let unreachable = find_unreachable(&statements)
+			.into_iter()
+			.map(|token| (token.lexeme, token.line))
+			.collect::<Vec<_>>();
+		assert_eq!(
+			unreachable,
+			[
+				("\"dead 1\"".to_string(), 4),
+				("dead2".to_string(), 7),
+				("dead3".to_string(), 8),
+				("\"dead 4\"".to_string(), 13),
+			]
+		);
+	}
+
+	#[test]
+	fn test_find_assign_in_condition() {
+		let statements = parse(
+			"while (x = next()) print x;\n\
+			if (x == 1) {} else if (a.b = 2) {}\n\
+			for (var i = 0; i = 10; i = i + 1) {}\n\
+			fun f() { if ((y = 3)) {} while (z = 4) {} }",
+		);
+
+		let found = find_assign_in_condition(&statements)
+			.into_iter()
+			.map(|token| (token.lexeme, token.line))
+			.collect::<Vec<_>>();
+		assert_eq!(
+			found,
+			[
+				("x".to_string(), 1),
+				("b".to_string(), 2),
+				("i".to_string(), 3),
+				("z".to_string(), 4),
+			]
+		);
+	}
+
+	#[test]
+	fn test_find_self_referential_init() {
+		let statements = parse(
+			"var a = a;\n\
+			var b = c;\n\
+			{ var d = 1 + f(d); }\n\
+			fun g() { var e = e.x; var f = fun () { return f; }; }\n\
+			var h = { var h = 1; h };",
+		);
+
+		let found = find_self_referential_init(&statements)
+			.into_iter()
+			.map(|token| (token.lexeme, token.line, token.column))
+			.collect::<Vec<_>>();
+		assert_eq!(
+			found,
+			[
+				("a".to_string(), 1, 9),
+				("d".to_string(), 3, 17),
+				("e".to_string(), 4, 19),
+			]
+		);
+	}
+
+	#[test]
+	fn test_find_empty_functions() {
+		let statements = parse(
+			"fun empty() {}
+			fun full() { fun inner() {} return 1; }
+			class A { m() {} n() { print 1; } class s() {} }
+			var f = fun () {};",
+		);
+
+		let found = find_empty_functions(&statements)
+			.into_iter()
+			.map(|token| (token.lexeme, token.line))
+			.collect::<Vec<_>>();
+		assert_eq!(
+			found,
+			[
+				("empty".to_string(), 1),
+				("inner".to_string(), 2),
+				("m".to_string(), 3),
+				("s".to_string(), 3),
+			]
+		);
+	}
+
+	#[test]
+	fn test_find_shadowed_vars() {
+		let statements = parse(
+			"var a; var a;
+			{ var a; var b; var a; var a; }
+			{ var c; { var c; } }
+			fun f() { { var d; if (true) { var d; } } }",
+		);
+
+		let found = find_shadowed_vars(&statements)
+			.into_iter()
+			.map(|(first, second)| (first.lexeme, first.column, second.column))
+			.collect::<Vec<_>>();
+		assert_eq!(found, [("a".to_string(), 10, 24), ("a".to_string(), 10, 31)]);
+	}
+}
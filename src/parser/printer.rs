@@ -0,0 +1,624 @@
+use std::fmt::{Result, Write};
+
+use crate::parser::{
+	precedence_of, Argument, CallExpr, Expr, FunctionExpr, FunctionStatement, MatchExpr,
+	Precedence, Stmt, SuperCallExpr, SuperExpr, VarScope,
+};
+use crate::token::{format_number, Token, TokenType};
+
+/// Prints `expr` as Lox source, adding only the parentheses needed to parse it back into the same
+/// tree. Groupings present in the tree are printed as written.
+#[allow(dead_code)]
+pub fn print_source(expr: &Expr, w: &mut impl Write) -> Result {
+	SourcePrinter { w, indent: 0 }.expr(expr, Precedence::Assignment)
+}
+
+/// Prints a whole program as Lox source, one top-level statement per line.
+#[allow(dead_code)]
+pub fn pretty_print(statements: &[Stmt], w: &mut impl Write) -> Result {
+	let mut printer = SourcePrinter { w, indent: 0 };
+	for statement in statements {
+		printer.stmt(statement)?;
+		writeln!(printer.w)?;
+	}
+	Ok(())
+}
+
+/// Prints `s` as a quoted string literal, escaping the characters that can't appear in one as is.
+pub(crate) fn write_string(w: &mut impl Write, s: &str) -> Result {
+	write!(w, "\"")?;
+	for c in s.chars() {
+		match c {
+			'\n' => write!(w, "\\n")?,
+			'\t' => write!(w, "\\t")?,
+			'\r' => write!(w, "\\r")?,
+			'\\' => write!(w, "\\\\")?,
+			'"' => write!(w, "\\\"")?,
+			'\0' => write!(w, "\\0")?,
+			c => write!(w, "{c}")?,
+		}
+	}
+	write!(w, "\"")
+}
+
+/// All binary and logical operators are left-associative, so only their right operand needs to
+/// bind tighter than the operator itself.
+fn operator_precedence(operator: &TokenType) -> Precedence {
+	match precedence_of(operator) {
+		Some(Precedence::Assignment | Precedence::Call) | None => {
+			panic!("{operator:?} is not a binary operator")
+		}
+		Some(precedence) => precedence,
+	}
+}
+
+fn precedence(expr: &Expr) -> Precedence {
+	match expr {
+		Expr::Assign { .. } | Expr::Set { .. } => Precedence::Assignment,
+		// The `else` branch extends as far to the right as it can, like an assigned value.
+		Expr::If(_) => Precedence::Assignment,
+		Expr::Binary { operator, .. } | Expr::Logical { operator, .. } => {
+			operator_precedence(&operator.token_type)
+		}
+		Expr::Range { .. } => Precedence::Range,
+		Expr::Unary { .. } => Precedence::Unary,
+		Expr::Call(_) | Expr::Get { .. } | Expr::SuperCall(_) => Precedence::Call,
+		Expr::Literal(_)
+		| Expr::Variable(_)
+		| Expr::Grouping(_)
+		| Expr::This { .. }
+		| Expr::Super(_)
+		| Expr::Function(_)
+		| Expr::Match(_)
+		| Expr::Block(_) => Precedence::Primary,
+	}
+}
+
+struct SourcePrinter<'w, W> {
+	w: &'w mut W,
+	/// Nesting level of the statement being printed.
+	indent: usize,
+}
+
+impl<W: Write> SourcePrinter<'_, W> {
+	/// Prints `expr`, in parentheses if it binds looser than `min_precedence`.
+	fn expr(&mut self, expr: &Expr, min_precedence: Precedence) -> Result {
+		let parenthesize = precedence(expr) < min_precedence;
+		if parenthesize {
+			write!(self.w, "(")?;
+		}
+		match expr {
+			Expr::Literal(token) => self.literal(token)?,
+			Expr::Variable(name) => write!(self.w, "{}", name.token_type)?,
+			Expr::Assign { name, value } => {
+				write!(self.w, "{} = ", name.token_type)?;
+				self.expr(value, Precedence::Assignment)?;
+			}
+			Expr::Unary { operator, expr } => {
+				write!(self.w, "{}", operator.token_type)?;
+				self.expr(expr, Precedence::Unary)?;
+			}
+			Expr::Binary {
+				left,
+				operator,
+				right,
+			}
+			| Expr::Logical {
+				left,
+				operator,
+				right,
+			} => {
+				let precedence = operator_precedence(&operator.token_type);
+				self.expr(left, precedence)?;
+				write!(self.w, " {} ", operator.token_type)?;
+				self.expr(right, precedence.next())?;
+			}
+			Expr::Grouping(expr) => {
+				write!(self.w, "(")?;
+				self.expr(expr, Precedence::Assignment)?;
+				write!(self.w, ")")?;
+			}
+			Expr::Call(call) => {
+				let CallExpr {
+					callee, arguments, ..
+				} = call.as_ref();
+				self.expr(callee, Precedence::Call)?;
+				self.arguments(arguments)?;
+			}
+			Expr::Get {
+				object,
+				name,
+				optional,
+			} => {
+				self.expr(object, Precedence::Call)?;
+				write!(self.w, "{}{}", if *optional { "?." } else { "." }, name.token_type)?;
+			}
+			Expr::Set {
+				object,
+				name,
+				value,
+			} => {
+				self.expr(object, Precedence::Call)?;
+				write!(self.w, ".{} = ", name.token_type)?;
+				self.expr(value, Precedence::Assignment)?;
+			}
+			Expr::This { .. } => write!(self.w, "this")?,
+			Expr::Super(super_expr) => {
+				let SuperExpr { method, .. } = super_expr.as_ref();
+				write!(self.w, "super.{}", method.token_type)?;
+			}
+			Expr::SuperCall(super_call) => {
+				let SuperCallExpr { arguments, .. } = super_call.as_ref();
+				write!(self.w, "super")?;
+				self.arguments(arguments)?;
+			}
+			Expr::Function(function) => {
+				let FunctionExpr {
+					name, params, body, ..
+				} = function.as_ref();
+				write!(self.w, "fun")?;
+				if let Some(name) = name {
+					write!(self.w, " {}", name.token_type)?;
+				}
+				self.parameters_and_body(params, body)?;
+			}
+			Expr::Match(match_expr) => {
+				let MatchExpr {
+					subject,
+					arms,
+					default,
+					..
+				} = match_expr.as_ref();
+				write!(self.w, "match (")?;
+				self.expr(subject, Precedence::Assignment)?;
+				write!(self.w, ") {{")?;
+				let default = default.as_ref().map(|default| (None, default));
+				let arms = arms.iter().map(|(pattern, value)| (Some(pattern), value));
+				for (i, (pattern, value)) in arms.chain(default).enumerate() {
+					write!(self.w, "{}", if i > 0 { ", " } else { " " })?;
+					match pattern {
+						Some(pattern) => self.expr(pattern, Precedence::Assignment)?,
+						None => write!(self.w, "_")?,
+					}
+					write!(self.w, " => ")?;
+					self.expr(value, Precedence::Assignment)?;
+				}
+				write!(self.w, " }}")?;
+			}
+			Expr::Block(block) => {
+				write!(self.w, "{{")?;
+				self.indent += 1;
+				for statement in &block.statements {
+					self.new_line()?;
+					self.stmt(statement)?;
+				}
+				self.new_line()?;
+				self.expr(&block.value, Precedence::Assignment)?;
+				self.indent -= 1;
+				self.new_line()?;
+				write!(self.w, "}}")?;
+			}
+			Expr::If(if_expr) => {
+				write!(self.w, "if (")?;
+				self.expr(&if_expr.condition, Precedence::Assignment)?;
+				write!(self.w, ") ")?;
+				self.expr(&if_expr.then_branch, Precedence::Assignment)?;
+				write!(self.w, " else ")?;
+				self.expr(&if_expr.else_branch, Precedence::Assignment)?;
+			}
+			// Ranges don't chain, so neither operand can be a range itself.
+			Expr::Range {
+				start,
+				end,
+				inclusive,
+			} => {
+				self.expr(start, Precedence::Range.next())?;
+				write!(self.w, " {} ", if *inclusive { "..=" } else { ".." })?;
+				self.expr(end, Precedence::Range.next())?;
+			}
+		}
+		if parenthesize {
+			write!(self.w, ")")?;
+		}
+		Ok(())
+	}
+
+	fn arguments(&mut self, arguments: &[Argument]) -> Result {
+		write!(self.w, "(")?;
+		for (i, argument) in arguments.iter().enumerate() {
+			if i > 0 {
+				write!(self.w, ", ")?;
+			}
+			if argument.spread {
+				write!(self.w, "...")?;
+			}
+			if let Some(name) = &argument.name {
+				write!(self.w, "{}: ", name.token_type)?;
+			}
+			self.expr(&argument.expr, Precedence::Assignment)?;
+		}
+		write!(self.w, ")")
+	}
+
+	fn literal(&mut self, token: &Token) -> Result {
+		match &token.token_type {
+			TokenType::String(s) => write_string(self.w, s),
+			// Without the fraction, it would be read back as an integer.
+			TokenType::Number(n) if n.is_finite() && n.fract() == 0.0 => {
+				write!(self.w, "{}.0", format_number(*n))
+			}
+			token_type => write!(self.w, "{token_type}"),
+		}
+	}
+
+	/// Prints a statement on the current line, leaving nested lines indented one level deeper.
+	fn stmt(&mut self, stmt: &Stmt) -> Result {
+		match stmt {
+			Stmt::Expr(expr) => {
+				let mut printed = String::new();
+				SourcePrinter {
+					w: &mut printed,
+					indent: self.indent,
+				}
+				.expr(expr, Precedence::Assignment)?;
+				// A leading `{` would be read back as a block statement.
+				if printed.starts_with('{') {
+					write!(self.w, "({printed});")
+				} else {
+					write!(self.w, "{printed};")
+				}
+			}
+			Stmt::Print(exprs) => {
+				write!(self.w, "print ")?;
+				for (i, expr) in exprs.iter().enumerate() {
+					if i > 0 {
+						write!(self.w, ", ")?;
+					}
+					self.expr(expr, Precedence::Assignment)?;
+				}
+				write!(self.w, ";")
+			}
+			Stmt::Var {
+				name,
+				initializer,
+				scope,
+			} => {
+				match scope {
+					VarScope::Unqualified => (),
+					VarScope::Global => write!(self.w, "global ")?,
+					VarScope::Local => write!(self.w, "local ")?,
+				}
+				self.variable(name, initializer.as_ref())
+			}
+			Stmt::Block(statements) => self.block(statements),
+			Stmt::Empty => write!(self.w, ";"),
+			Stmt::If {
+				condition,
+				then_branch,
+				else_branch,
+			} => {
+				write!(self.w, "if (")?;
+				self.expr(condition, Precedence::Assignment)?;
+				write!(self.w, ") ")?;
+				self.stmt(then_branch)?;
+				if let Some(else_branch) = else_branch {
+					write!(self.w, " else ")?;
+					self.stmt(else_branch)?;
+				}
+				Ok(())
+			}
+			Stmt::While {
+				condition,
+				body,
+				label,
+			} => {
+				if let Some(label) = label {
+					write!(self.w, "{}: ", label.token_type)?;
+				}
+				write!(self.w, "while (")?;
+				self.expr(condition, Precedence::Assignment)?;
+				write!(self.w, ") ")?;
+				self.stmt(body)
+			}
+			Stmt::Break { keyword, label } | Stmt::Continue { keyword, label } => {
+				write!(self.w, "{}", keyword.token_type)?;
+				if let Some(label) = label {
+					write!(self.w, " {}", label.token_type)?;
+				}
+				write!(self.w, ";")
+			}
+			Stmt::Function(function) => {
+				write!(self.w, "fun ")?;
+				self.function(function)
+			}
+			Stmt::Return { value: None, .. } => write!(self.w, "return;"),
+			Stmt::Return {
+				value: Some(value),
+				..
+			} => {
+				write!(self.w, "return ")?;
+				self.expr(value, Precedence::Assignment)?;
+				write!(self.w, ";")
+			}
+			Stmt::Class {
+				name,
+				superclass,
+				methods,
+				static_methods,
+				fields,
+			} => {
+				write!(self.w, "class {}", name.token_type)?;
+				if let Some(superclass) = superclass {
+					write!(self.w, " < {}", superclass.token_type)?;
+				}
+				write!(self.w, " {{")?;
+				self.indent += 1;
+				for (name, initializer) in fields {
+					self.new_line()?;
+					self.variable(name, initializer.as_ref())?;
+				}
+				for method in static_methods {
+					self.new_line()?;
+					write!(self.w, "class ")?;
+					self.function(method)?;
+				}
+				for method in methods {
+					self.new_line()?;
+					self.function(method)?;
+				}
+				self.indent -= 1;
+				if !(fields.is_empty() && static_methods.is_empty() && methods.is_empty()) {
+					self.new_line()?;
+				}
+				write!(self.w, "}}")
+			}
+			Stmt::Throw { value, .. } => {
+				write!(self.w, "throw ")?;
+				self.expr(value, Precedence::Assignment)?;
+				write!(self.w, ";")
+			}
+			Stmt::Import { path, alias, .. } => {
+				write!(self.w, "import ")?;
+				self.literal(path)?;
+				if let Some(alias) = alias {
+					write!(self.w, " as {}", alias.token_type)?;
+				}
+				write!(self.w, ";")
+			}
+			Stmt::Try {
+				body,
+				catch_name,
+				catch_body,
+				..
+			} => {
+				write!(self.w, "try ")?;
+				self.stmt(body)?;
+				write!(self.w, " catch ")?;
+				if let Some(catch_name) = catch_name {
+					write!(self.w, "({}) ", catch_name.token_type)?;
+				}
+				self.stmt(catch_body)
+			}
+			Stmt::Assert {
+				condition, message, ..
+			} => {
+				write!(self.w, "assert ")?;
+				self.expr(condition, Precedence::Assignment)?;
+				if let Some(message) = message {
+					write!(self.w, ", ")?;
+					self.expr(message, Precedence::Assignment)?;
+				}
+				write!(self.w, ";")
+			}
+		}
+	}
+
+	fn variable(&mut self, name: &Token, initializer: Option<&Expr>) -> Result {
+		write!(self.w, "var {}", name.token_type)?;
+		if let Some(initializer) = initializer {
+			write!(self.w, " = ")?;
+			self.expr(initializer, Precedence::Assignment)?;
+		}
+		write!(self.w, ";")
+	}
+
+	fn block(&mut self, statements: &[Stmt]) -> Result {
+		write!(self.w, "{{")?;
+		if statements.is_empty() {
+			return write!(self.w, "}}");
+		}
+		self.indent += 1;
+		for statement in statements {
+			self.new_line()?;
+			self.stmt(statement)?;
+		}
+		self.indent -= 1;
+		self.new_line()?;
+		write!(self.w, "}}")
+	}
+
+	/// Everything after `fun` in a function declaration, or the whole method declaration.
+	fn function(&mut self, function: &FunctionStatement) -> Result {
+		write!(self.w, "{}", function.name.token_type)?;
+		self.parameters_and_body(&function.params, &function.body)
+	}
+
+	fn parameters_and_body(&mut self, params: &[Token], body: &[Stmt]) -> Result {
+		write!(self.w, "(")?;
+		for (i, param) in params.iter().enumerate() {
+			if i > 0 {
+				write!(self.w, ", ")?;
+			}
+			write!(self.w, "{}", param.token_type)?;
+		}
+		write!(self.w, ") ")?;
+		self.block(body)
+	}
+
+	fn new_line(&mut self) -> Result {
+		writeln!(self.w)?;
+		for _ in 0..self.indent {
+			write!(self.w, "\t")?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{pretty_print, print_source};
+	use crate::parser::{Expr, Parser, Stmt};
+	use crate::scanner::Scanner;
+
+	fn parse(source: &str) -> Vec<Stmt> {
+		Parser::new(Scanner::new(source).scan_tokens())
+			.parse()
+			.unwrap_or_else(|err| panic!("{err} in:\n{source}"))
+	}
+
+	/// Asserts that printing `src` back and parsing the output gives the same tree.
+	fn assert_roundtrips(src: &str) {
+		let statements = parse(src);
+		let mut printed = String::new();
+		pretty_print(&statements, &mut printed).unwrap();
+		let reparsed = parse(&printed);
+		assert_eq!(reparsed.len(), statements.len(), "{src}\nprinted as:\n{printed}");
+		for (a, b) in statements.iter().zip(&reparsed) {
+			assert!(a.structurally_eq(b), "{src}\nprinted as:\n{printed}");
+		}
+	}
+
+	fn parse_expr(source: &str) -> Expr {
+		Parser::new(Scanner::new(source).scan_tokens())
+			.parse_expression()
+			.unwrap()
+	}
+
+	fn source(expr: &Expr) -> String {
+		let mut s = String::new();
+		print_source(expr, &mut s).unwrap();
+		s
+	}
+
+	/// `template` with `operand` in place of its left (or right) operand.
+	fn with_operand(template: &str, operand: &str, left: bool) -> Expr {
+		let Expr::Binary {
+			left: l,
+			operator,
+			right: r,
+		} = parse_expr(template)
+		else {
+			panic!("expected a binary expression");
+		};
+		let operand = Box::new(parse_expr(operand));
+		let (left_operand, right_operand) = if left { (operand, r) } else { (l, operand) };
+		Expr::Binary {
+			left: left_operand,
+			operator,
+			right: right_operand,
+		}
+	}
+
+	#[test]
+	fn test_print_source() {
+		for source_code in [
+			"1 + 2 + 3",
+			"1 + (2 + 3)",
+			"1 + 2 * 3 - 4 / 5",
+			"a = b = c or d and !e",
+			"a ?? (b ?? c) ?? d or e",
+			"a.b is C == !(c is D)",
+			"(a ?? b) or c",
+			"-a.b(c, d = 1).e",
+			"a?.b.c?.d(1)?.e",
+			"!(a == b) != (c < d)",
+			"this.x = super.y(\"say \\\"hi\\\"\\n\")",
+			"fun (x) { return x; }(1)",
+			"(0..n) == (a + 1..=b < c)",
+			"(1..2)..3",
+		] {
+			let expr = parse_expr(source_code);
+			let printed = source(&expr);
+			assert!(
+				parse_expr(&printed).structurally_eq(&expr),
+				"{source_code} printed as {printed}"
+			);
+		}
+		assert_eq!(source(&parse_expr("1+2  +3")), "1 + 2 + 3");
+		assert_eq!(source(&parse_expr("a=-b.c ( 1,2 )")), "a = -b.c(1, 2)");
+		assert_eq!(
+			source(&parse_expr("fun named(a, b) { print a; }")),
+			"fun named(a, b) {\n\tprint a;\n}"
+		);
+	}
+
+	#[test]
+	fn test_print_source_minimal_parentheses() {
+		// Neither of these has a grouping node, so the parentheses come from precedence alone.
+		assert_eq!(source(&with_operand("x * 3", "1 + 2", true)), "(1 + 2) * 3");
+		assert_eq!(source(&with_operand("1 - x", "2 - 3", false)), "1 - (2 - 3)");
+		assert_eq!(source(&with_operand("x - 3", "1 - 2", true)), "1 - 2 - 3");
+		assert_eq!(source(&with_operand("1 + x", "2 * 3", false)), "1 + 2 * 3");
+		assert_eq!(source(&with_operand("x == 3", "a = 2", true)), "(a = 2) == 3");
+
+		// A unary operand only needs parentheses if it binds looser than the operator.
+		let Expr::Unary { operator, .. } = parse_expr("-x") else {
+			panic!("expected a unary expression");
+		};
+		let negated = |operand: &str| Expr::Unary {
+			operator: operator.clone(),
+			expr: Box::new(parse_expr(operand)),
+		};
+		assert_eq!(source(&negated("a + b")), "-(a + b)");
+		assert_eq!(source(&negated("a.b()")), "-a.b()");
+		assert_eq!(source(&negated("!a")), "-!a");
+		assert_eq!(source(&parse_expr("-(a + b)")), "-(a + b)");
+		assert_eq!(source(&parse_expr("-a * b")), "-a * b");
+		assert_eq!(source(&parse_expr("-(a * b)")), "-(a * b)");
+	}
+
+	#[test]
+	fn test_roundtrips() {
+		for src in [
+			"print 1 + 2 * 3 - 4 / -5;",
+			"print 1.0, 2, 0.5, 1.0 + 2;",
+			"print 1, a = 2, \"three\";",
+			"print (1 + 2) * (3 - (4 - 5));",
+			"print !true == !!false != (nil == \"a\\tb\");",
+			"var a; var b = a = 1 < 2 and 3 >= 4 or 5 <= 6;",
+			"{ var a = 1; { print a; } }",
+			"if (a) print 1; else if (b) { print 2; } else print 3;",
+			"while (i < 10) { i = i + 1; }",
+			"while (a()) ; ; if (b) ; else { ; }",
+			"outer: while (a) { inner: for (;;) { if (b) break outer; continue inner; } break; }",
+			"for (var i = 0; i < 10; i = i + 1) print i;",
+			"fun add(a, b) { return a + b; } print add(1, 2)(3);",
+			"f(a, ...b.c, ...d(e));",
+			"f(1, x: 2, y: z = 3);",
+			"print match (x) { 1 => \"a\", 2 => -b, _ => nil };",
+			"print match (x) {};",
+			"print { var x = 1; x + 1 };",
+			"({ f(); 2 } + 1);",
+			"var x = if (a) 1 else if (b) 2 else 3; print (if (a) b else c) + -(if (d) e else f);",
+			"fun f() { return; } fun g() { return nil; }",
+			"class A { init(x) { this.x = x; } get() { return this.x; } }",
+			"class C < A { init(x) { super(x, 1); super.init(x).y = 2; } }",
+			"class B < A { var y = 2; var z; class make() { return B(1); } \
+				get() { return super.get() + this.y; } }",
+			"class Empty {}",
+			"a.b.c = d.e(f).g;",
+			"var f = fun (x) { return -x; }; (fun () {})();",
+			"try { throw \"oops\"; } catch (e) { print e; } try {} catch {}",
+			"assert 1 < 2; assert false, \"message\";",
+			"import \"math\"; import \"a\\\\b\" as b;",
+			"var x = undefined; print x == nil;",
+			"global var g = 1; local var l; var v;",
+		] {
+			assert_roundtrips(src);
+		}
+
+		let mut printed = String::new();
+		pretty_print(&[Stmt::Expr(parse_expr("{ 1 } + 2"))], &mut printed).unwrap();
+		assert_eq!(printed, "({\n\t1\n} + 2);\n");
+	}
+}
@@ -0,0 +1,576 @@
+use crate::parser::{Argument, Expr, FunctionStatement, Stmt};
+use crate::token::{format_number, Token, TokenType};
+
+/// Rebuilds `expr` bottom-up, passing every node to `f` after its children have been mapped.
+/// Tokens of the nodes are carried over unchanged, so `f` only has to handle the nodes it rewrites.
+/// Function expressions are passed to `f` as a whole; their bodies are statements, which this
+/// doesn't descend into. For the same reason, only the value of a block expression is mapped.
+pub fn map_expr<F: FnMut(Expr) -> Expr>(expr: Expr, f: &mut F) -> Expr {
+	// Reuses the allocation of the original child.
+	fn map_boxed<F: FnMut(Expr) -> Expr>(mut expr: Box<Expr>, f: &mut F) -> Box<Expr> {
+		*expr = map_expr(*expr, f);
+		expr
+	}
+
+	let expr = match expr {
+		expr @ (Expr::Literal(_)
+		| Expr::Variable(_)
+		| Expr::This { .. }
+		| Expr::Super(_)
+		| Expr::Function(_)) => expr,
+		Expr::Assign { name, value } => Expr::Assign {
+			name,
+			value: map_boxed(value, f),
+		},
+		Expr::Unary { operator, expr } => Expr::Unary {
+			operator,
+			expr: map_boxed(expr, f),
+		},
+		Expr::Binary {
+			left,
+			operator,
+			right,
+		} => Expr::Binary {
+			left: map_boxed(left, f),
+			operator,
+			right: map_boxed(right, f),
+		},
+		Expr::Grouping(expr) => Expr::Grouping(map_boxed(expr, f)),
+		Expr::Logical {
+			left,
+			operator,
+			right,
+		} => Expr::Logical {
+			left: map_boxed(left, f),
+			operator,
+			right: map_boxed(right, f),
+		},
+		Expr::Call(mut call) => {
+			call.callee = map_expr(call.callee, f);
+			call.arguments = call
+				.arguments
+				.into_iter()
+				.map(|argument| Argument {
+					expr: map_expr(argument.expr, f),
+					..argument
+				})
+				.collect();
+			Expr::Call(call)
+		}
+		Expr::SuperCall(mut super_call) => {
+			super_call.arguments = super_call
+				.arguments
+				.into_iter()
+				.map(|argument| Argument {
+					expr: map_expr(argument.expr, f),
+					..argument
+				})
+				.collect();
+			Expr::SuperCall(super_call)
+		}
+		Expr::Get {
+			object,
+			name,
+			optional,
+		} => Expr::Get {
+			object: map_boxed(object, f),
+			name,
+			optional,
+		},
+		Expr::Set {
+			object,
+			name,
+			value,
+		} => Expr::Set {
+			object: map_boxed(object, f),
+			name,
+			value: map_boxed(value, f),
+		},
+		Expr::Match(mut match_expr) => {
+			match_expr.subject = map_expr(match_expr.subject, f);
+			match_expr.arms = match_expr
+				.arms
+				.into_iter()
+				.map(|(pattern, value)| (map_expr(pattern, f), map_expr(value, f)))
+				.collect();
+			match_expr.default = match_expr.default.map(|default| map_expr(default, f));
+			Expr::Match(match_expr)
+		}
+		Expr::Block(mut block) => {
+			block.value = map_expr(block.value, f);
+			Expr::Block(block)
+		}
+		Expr::If(mut if_expr) => {
+			if_expr.condition = map_expr(if_expr.condition, f);
+			if_expr.then_branch = map_expr(if_expr.then_branch, f);
+			if_expr.else_branch = map_expr(if_expr.else_branch, f);
+			Expr::If(if_expr)
+		}
+		Expr::Range {
+			start,
+			end,
+			inclusive,
+		} => Expr::Range {
+			start: map_boxed(start, f),
+			end: map_boxed(end, f),
+			inclusive,
+		},
+	};
+	f(expr)
+}
+
+/// Removes the groupings that can't affect how `expr` parses: ones wrapping a single primary or
+/// postfix expression, e.g. `((x))` or `(a.b(c))`. Groupings around operators, assignments and
+/// function expressions are kept.
+#[allow(dead_code)]
+pub fn strip_redundant_groupings(expr: Expr) -> Expr {
+	map_expr(expr, &mut |expr| match expr {
+		Expr::Grouping(inner)
+			if matches!(
+				*inner,
+				Expr::Literal(_)
+					| Expr::Variable(_)
+					| Expr::This { .. }
+					| Expr::Super(_)
+					| Expr::Grouping(_)
+					| Expr::Call(_)
+					| Expr::SuperCall(_)
+					| Expr::Get { .. }
+					| Expr::Match(_)
+			) =>
+		{
+			*inner
+		}
+		expr => expr,
+	})
+}
+
+/// Turns `-` applied to a number literal into a negative literal, e.g. so that `-5` can be
+/// matched like any other literal. This works bottom-up, so `- -5` becomes `5`. Negations of
+/// anything else, including `-(5)`, are left alone.
+#[allow(dead_code)]
+pub fn fold_negative_literals(expr: Expr) -> Expr {
+	map_expr(expr, &mut |expr| match expr {
+		Expr::Unary { operator, expr } if operator.token_type == TokenType::Minus => {
+			let token_type = match &*expr {
+				Expr::Literal(Token {
+					token_type: TokenType::Number(n),
+					..
+				}) => TokenType::Number(-n),
+				Expr::Literal(Token {
+					token_type: TokenType::Integer(n),
+					..
+				}) => match n.checked_neg() {
+					Some(n) => TokenType::Integer(n),
+					None => TokenType::Number(-(*n as f64)),
+				},
+				_ => return Expr::Unary { operator, expr },
+			};
+			folded(token_type, &operator)
+		}
+		expr => expr,
+	})
+}
+
+/// Evaluates operators whose operands are number or boolean literals, e.g. `2 + 3 * 4` becomes a
+/// single `14` literal. `and`/`or` with a literal left side are short-circuited. Anything that
+/// can't be folded without changing the runtime behaviour (division by zero, operands of mixed
+/// types, ...) is left as is, so that the interpreter still reports it.
+#[allow(dead_code)]
+pub fn fold_constants(stmts: Vec<Stmt>) -> Vec<Stmt> {
+	stmts.into_iter().map(fold_stmt).collect()
+}
+
+fn fold_stmt(stmt: Stmt) -> Stmt {
+	fn fold_expr(expr: Expr) -> Expr {
+		map_expr(expr, &mut fold_node)
+	}
+
+	fn fold_function(function: FunctionStatement) -> FunctionStatement {
+		FunctionStatement {
+			body: fold_constants(function.body),
+			..function
+		}
+	}
+
+	fn fold_boxed(mut stmt: Box<Stmt>) -> Box<Stmt> {
+		*stmt = fold_stmt(*stmt);
+		stmt
+	}
+
+	match stmt {
+		Stmt::Expr(expr) => Stmt::Expr(fold_expr(expr)),
+		Stmt::Print(exprs) => Stmt::Print(exprs.into_iter().map(fold_expr).collect()),
+		Stmt::Var {
+			name,
+			initializer,
+			scope,
+		} => Stmt::Var {
+			name,
+			initializer: initializer.map(fold_expr),
+			scope,
+		},
+		Stmt::Block(statements) => Stmt::Block(fold_constants(statements)),
+		Stmt::If {
+			condition,
+			then_branch,
+			else_branch,
+		} => Stmt::If {
+			condition: fold_expr(condition),
+			then_branch: fold_boxed(then_branch),
+			else_branch: else_branch.map(fold_boxed),
+		},
+		Stmt::While {
+			condition,
+			body,
+			label,
+		} => Stmt::While {
+			condition: fold_expr(condition),
+			body: fold_boxed(body),
+			label,
+		},
+		stmt @ (Stmt::Empty | Stmt::Break { .. } | Stmt::Continue { .. } | Stmt::Import { .. }) => {
+			stmt
+		}
+		Stmt::Function(function) => Stmt::Function(fold_function(function)),
+		Stmt::Return { keyword, value } => Stmt::Return {
+			keyword,
+			value: value.map(fold_expr),
+		},
+		Stmt::Class {
+			name,
+			superclass,
+			methods,
+			static_methods,
+			fields,
+		} => Stmt::Class {
+			name,
+			superclass,
+			methods: methods.into_iter().map(fold_function).collect(),
+			static_methods: static_methods.into_iter().map(fold_function).collect(),
+			fields: fields
+				.into_iter()
+				.map(|(name, initializer)| (name, initializer.map(fold_expr)))
+				.collect(),
+		},
+		Stmt::Throw { keyword, value } => Stmt::Throw {
+			keyword,
+			value: fold_expr(value),
+		},
+		Stmt::Try {
+			keyword,
+			body,
+			catch_name,
+			catch_body,
+		} => Stmt::Try {
+			keyword,
+			body: fold_boxed(body),
+			catch_name,
+			catch_body: fold_boxed(catch_body),
+		},
+		Stmt::Assert {
+			keyword,
+			condition,
+			message,
+		} => Stmt::Assert {
+			keyword,
+			condition: fold_expr(condition),
+			message: message.map(fold_expr),
+		},
+	}
+}
+
+/// A literal with the value `token_type`, in place of the operator it was folded from.
+fn folded(token_type: TokenType, operator: &Token) -> Expr {
+	let lexeme = match token_type {
+		TokenType::Number(n) => format_number(n),
+		TokenType::Integer(n) => n.to_string(),
+		TokenType::True => "true".to_string(),
+		TokenType::False => "false".to_string(),
+		_ => unreachable!("only numbers and booleans are folded"),
+	};
+	Expr::Literal(Token {
+		lexeme,
+		column: operator.column,
+		..Token::synthetic(token_type, operator.line)
+	})
+}
+
+/// Folds a single node, assuming its children have already been folded.
+fn fold_node(expr: Expr) -> Expr {
+	fn literal(expr: &Expr) -> Option<&TokenType> {
+		match expr {
+			Expr::Literal(Token {
+				token_type:
+					token_type @ (TokenType::Number(_)
+					| TokenType::Integer(_)
+					| TokenType::String(_)
+					| TokenType::True
+					| TokenType::False
+					| TokenType::Nil),
+				..
+			}) => Some(token_type),
+			_ => None,
+		}
+	}
+
+	fn number(literal: &TokenType) -> Option<f64> {
+		match *literal {
+			TokenType::Number(n) => Some(n),
+			TokenType::Integer(n) => Some(n as f64),
+			_ => None,
+		}
+	}
+
+	fn is_truthy(literal: &TokenType) -> bool {
+		!matches!(literal, TokenType::False | TokenType::Nil)
+	}
+
+	fn boolean(b: bool) -> TokenType {
+		if b {
+			TokenType::True
+		} else {
+			TokenType::False
+		}
+	}
+
+	match expr {
+		Expr::Grouping(inner) if literal(&inner).is_some() => *inner,
+		Expr::Unary { operator, expr } => {
+			let token_type = match (&operator.token_type, literal(&expr)) {
+				(TokenType::Minus, Some(TokenType::Number(n))) => TokenType::Number(-n),
+				(TokenType::Minus, Some(TokenType::Integer(n))) => match n.checked_neg() {
+					Some(n) => TokenType::Integer(n),
+					None => TokenType::Number(-(*n as f64)),
+				},
+				(TokenType::Bang, Some(literal)) => boolean(!is_truthy(literal)),
+				_ => return Expr::Unary { operator, expr },
+			};
+			folded(token_type, &operator)
+		}
+		Expr::Binary {
+			left,
+			operator,
+			right,
+		} => {
+			// Integer arithmetic stays exact, unless it overflows.
+			let integer = match (literal(&left), &operator.token_type, literal(&right)) {
+				(Some(TokenType::Integer(a)), operator_type, Some(TokenType::Integer(b))) => {
+					match operator_type {
+						TokenType::Plus => a.checked_add(*b),
+						TokenType::Minus => a.checked_sub(*b),
+						TokenType::Star => a.checked_mul(*b),
+						_ => None,
+					}
+				}
+				_ => None,
+			};
+			if let Some(n) = integer {
+				return folded(TokenType::Integer(n), &operator);
+			}
+
+			let numbers = literal(&left).and_then(number).zip(literal(&right).and_then(number));
+			let token_type = match (literal(&left), &operator.token_type, literal(&right)) {
+				(_, operator_type, _) if numbers.is_some() => {
+					let (a, b) = numbers.unwrap();
+					match operator_type {
+						TokenType::Plus => TokenType::Number(a + b),
+						TokenType::Minus => TokenType::Number(a - b),
+						TokenType::Star => TokenType::Number(a * b),
+						TokenType::Slash if b != 0.0 => TokenType::Number(a / b),
+						TokenType::Greater => boolean(a > b),
+						TokenType::GreaterEqual => boolean(a >= b),
+						TokenType::Less => boolean(a < b),
+						TokenType::LessEqual => boolean(a <= b),
+						TokenType::EqualEqual => boolean(a == b),
+						TokenType::BangEqual => boolean(a != b),
+						_ => {
+							return Expr::Binary {
+								left,
+								operator,
+								right,
+							}
+						}
+					}
+				}
+				(
+					Some(a @ (TokenType::True | TokenType::False)),
+					TokenType::EqualEqual | TokenType::BangEqual,
+					Some(b @ (TokenType::True | TokenType::False)),
+				) => boolean((a == b) == matches!(operator.token_type, TokenType::EqualEqual)),
+				_ => {
+					return Expr::Binary {
+						left,
+						operator,
+						right,
+					}
+				}
+			};
+			folded(token_type, &operator)
+		}
+		Expr::Logical {
+			left,
+			operator:
+				operator @ Token {
+					token_type: TokenType::QuestionQuestion,
+					..
+				},
+			right,
+		} => match literal(&left) {
+			Some(TokenType::Nil) => *right,
+			Some(_) => *left,
+			None => Expr::Logical {
+				left,
+				operator,
+				right,
+			},
+		},
+		Expr::Logical {
+			left,
+			operator,
+			right,
+		} => match (literal(&left).map(is_truthy), &operator.token_type) {
+			(Some(true), TokenType::Or) | (Some(false), TokenType::And) => *left,
+			(Some(false), TokenType::Or) | (Some(true), TokenType::And) => *right,
+			_ => Expr::Logical {
+				left,
+				operator,
+				right,
+			},
+		},
+		Expr::Function(mut function) => {
+			function.body = fold_constants(function.body);
+			Expr::Function(function)
+		}
+		Expr::Block(mut block) => {
+			block.statements = fold_constants(block.statements);
+			Expr::Block(block)
+		}
+		expr => expr,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{fold_constants, fold_negative_literals, map_expr, strip_redundant_groupings};
+	use crate::parser::{Expr, Parser, Stmt};
+	use crate::scanner::Scanner;
+	use crate::token::{Token, TokenType};
+
+	fn parse_expr(source: &str) -> Expr {
+		let tokens = Scanner::new(&format!("{source};")).scan_tokens();
+		match Parser::new(tokens).parse().unwrap().pop() {
+			Some(Stmt::Expr(expr)) => expr,
+			stmt => panic!("expected an expression statement, got {stmt:?}"),
+		}
+	}
+
+	#[test]
+	fn test_map_expr_increments_numbers() {
+		let expr = parse_expr("1 + f(2, a.b = -3) or (4)");
+
+		let expr = map_expr(expr, &mut |expr| match expr {
+			Expr::Literal(Token {
+				token_type: TokenType::Integer(n),
+				line,
+				column,
+				universal_index,
+				..
+			}) => Expr::Literal(Token {
+				token_type: TokenType::Integer(n + 1),
+				lexeme: (n + 1).to_string(),
+				line,
+				column,
+				universal_index,
+			}),
+			expr => expr,
+		});
+
+		assert_eq!(
+			expr.to_string(),
+			"(or (+ 2 (call f 3 (= .b a (- 4)))) (group 5))"
+		);
+	}
+
+	fn fold(source: &str) -> String {
+		let tokens = Scanner::new(&format!("print {source};")).scan_tokens();
+		match fold_constants(Parser::new(tokens).parse().unwrap()).pop() {
+			Some(Stmt::Print(mut values)) => values.remove(0).to_string(),
+			stmt => panic!("expected a print statement, got {stmt:?}"),
+		}
+	}
+
+	#[test]
+	fn test_fold_constants() {
+		assert_eq!(fold("2 + 3 * 4"), "14");
+		assert_eq!(fold("-(2 - 5) / 2"), "1.5");
+		assert_eq!(fold("1<2 == !nil"), "true");
+		assert_eq!(fold("x * (1 + 1)"), "(* x 2)");
+		assert_eq!(fold("nil or 1 + 1"), "2");
+		assert_eq!(fold("false and x"), "false");
+		assert_eq!(fold("1 and x"), "x");
+		assert_eq!(fold("x or 1"), "(or x 1)");
+		assert_eq!(fold("nil ?? x"), "x");
+		assert_eq!(fold("false ?? x"), "false");
+		assert_eq!(fold("x ?? 1"), "(?? x 1)");
+
+		// Left for the interpreter to report.
+		assert_eq!(fold("1 / 0"), "(/ 1 0)");
+		assert_eq!(fold("1 + true"), "(+ 1 true)");
+		assert_eq!(fold("-\"a\""), "(- a)");
+	}
+
+	#[test]
+	fn test_fold_constants_in_nested_statements() {
+		let tokens = Scanner::new("fun f() { while (1 < 2) { return 2 * 2; } }").scan_tokens();
+		let statements = fold_constants(Parser::new(tokens).parse().unwrap());
+
+		let Stmt::Function(function) = &statements[0] else {
+			panic!("expected a function");
+		};
+		let Stmt::While {
+			condition, body, ..
+		} = &function.body[0]
+		else {
+			panic!("expected a while loop");
+		};
+		assert_eq!(condition.to_string(), "true");
+		let Stmt::Block(body) = body.as_ref() else {
+			panic!("expected a block");
+		};
+		let Stmt::Return {
+			value: Some(value), ..
+		} = &body[0]
+		else {
+			panic!("expected a return");
+		};
+		assert_eq!(value.to_string(), "4");
+	}
+
+	#[test]
+	fn test_strip_redundant_groupings() {
+		let strip = |source: &str| strip_redundant_groupings(parse_expr(source)).to_string();
+		assert_eq!(strip("((1))"), "1");
+		assert_eq!(strip("(x) + ((a.b)(c))"), "(+ x (call (.b a) c))");
+		assert_eq!(strip("(1 + 2) * 3"), "(* (group (+ 1 2)) 3)");
+		assert_eq!(strip("1 - ((2 - 3))"), "(- 1 (group (- 2 3)))");
+		assert_eq!(strip("(-x).y"), "(.y (group (- x)))");
+		assert_eq!(strip("(fun () {})()"), "(call (group (fun ())))");
+	}
+
+	#[test]
+	fn test_fold_negative_literals() {
+		let fold = |source: &str| fold_negative_literals(parse_expr(source));
+		let Expr::Literal(token) = fold("-5") else {
+			panic!("expected a literal");
+		};
+		assert_eq!(token.token_type, TokenType::Integer(-5));
+		assert_eq!((token.lexeme.as_str(), token.column), ("-5", 1));
+		assert_eq!(fold("-2.5").to_string(), "-2.5");
+		assert_eq!(fold("- -5").to_string(), "5");
+		assert_eq!(fold("-(a)").to_string(), "(- (group a))");
+		assert_eq!(fold("-(5)").to_string(), "(- (group 5))");
+		assert_eq!(fold("-x").to_string(), "(- x)");
+		assert_eq!(fold("a - -1 * !-2").to_string(), "(- a (* -1 (! -2)))");
+	}
+}
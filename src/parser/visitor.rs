@@ -0,0 +1,606 @@
+use crate::parser::{Argument, Expr, FunctionExpr, FunctionStatement, MatchExpr, Stmt};
+use crate::token::Token;
+
+/// Double dispatch over [Expr] variants. Implementors get one method per variant, with the
+/// variant's fields already destructured, and recurse into children by calling [Expr::accept].
+pub trait ExprVisitor {
+	type Output;
+
+	fn visit_literal(&mut self, token: &Token) -> Self::Output;
+	fn visit_variable(&mut self, name: &Token) -> Self::Output;
+	fn visit_assign(&mut self, name: &Token, value: &Expr) -> Self::Output;
+	fn visit_unary(&mut self, operator: &Token, expr: &Expr) -> Self::Output;
+	fn visit_binary(&mut self, left: &Expr, operator: &Token, right: &Expr) -> Self::Output;
+	fn visit_grouping(&mut self, expr: &Expr) -> Self::Output;
+	fn visit_logical(&mut self, left: &Expr, operator: &Token, right: &Expr) -> Self::Output;
+	fn visit_call(
+		&mut self,
+		callee: &Expr,
+		closing_parenthesis: &Token,
+		arguments: &[Argument],
+	) -> Self::Output;
+	fn visit_get(&mut self, object: &Expr, name: &Token, optional: bool) -> Self::Output;
+	fn visit_set(&mut self, object: &Expr, name: &Token, value: &Expr) -> Self::Output;
+	fn visit_this(&mut self, keyword: &Token) -> Self::Output;
+	fn visit_super(&mut self, keyword: &Token, method: &Token) -> Self::Output;
+	fn visit_super_call(&mut self, keyword: &Token, arguments: &[Argument]) -> Self::Output;
+	fn visit_function(
+		&mut self,
+		keyword: &Token,
+		name: Option<&Token>,
+		params: &[Token],
+		body: &[Stmt],
+	) -> Self::Output;
+	fn visit_match(
+		&mut self,
+		subject: &Expr,
+		arms: &[(Expr, Expr)],
+		default: Option<&Expr>,
+	) -> Self::Output;
+	fn visit_block_expr(&mut self, statements: &[Stmt], value: &Expr) -> Self::Output;
+	fn visit_if_expr(
+		&mut self,
+		condition: &Expr,
+		then_branch: &Expr,
+		else_branch: &Expr,
+	) -> Self::Output;
+	fn visit_range(&mut self, start: &Expr, end: &Expr, inclusive: bool) -> Self::Output;
+}
+
+impl Expr {
+	pub fn accept<V: ExprVisitor>(&self, v: &mut V) -> V::Output {
+		match self {
+			Expr::Literal(token) => v.visit_literal(token),
+			Expr::Variable(name) => v.visit_variable(name),
+			Expr::Assign { name, value } => v.visit_assign(name, value),
+			Expr::Unary { operator, expr } => v.visit_unary(operator, expr),
+			Expr::Binary {
+				left,
+				operator,
+				right,
+			} => v.visit_binary(left, operator, right),
+			Expr::Grouping(expr) => v.visit_grouping(expr),
+			Expr::Logical {
+				left,
+				operator,
+				right,
+			} => v.visit_logical(left, operator, right),
+			Expr::Call(call) => {
+				v.visit_call(&call.callee, &call.closing_parenthesis, &call.arguments)
+			}
+			Expr::Get {
+				object,
+				name,
+				optional,
+			} => v.visit_get(object, name, *optional),
+			Expr::Set {
+				object,
+				name,
+				value,
+			} => v.visit_set(object, name, value),
+			Expr::This { keyword } => v.visit_this(keyword),
+			Expr::Super(super_expr) => v.visit_super(&super_expr.keyword, &super_expr.method),
+			Expr::SuperCall(super_call) => {
+				v.visit_super_call(&super_call.keyword, &super_call.arguments)
+			}
+			Expr::Function(function) => {
+				let FunctionExpr {
+					keyword,
+					name,
+					params,
+					body,
+					..
+				} = function.as_ref();
+				v.visit_function(keyword, name.as_ref(), params, body)
+			}
+			Expr::Match(match_expr) => {
+				let MatchExpr {
+					subject,
+					arms,
+					default,
+					..
+				} = match_expr.as_ref();
+				v.visit_match(subject, arms, default.as_ref())
+			}
+			Expr::Block(block) => v.visit_block_expr(&block.statements, &block.value),
+			Expr::If(if_expr) => {
+				v.visit_if_expr(&if_expr.condition, &if_expr.then_branch, &if_expr.else_branch)
+			}
+			Expr::Range {
+				start,
+				end,
+				inclusive,
+			} => v.visit_range(start, end, *inclusive),
+		}
+	}
+}
+
+/// Double dispatch over [Stmt] variants. Statements are visited for their side effects only, which
+/// lets every method default to walking into the nested statements (see [walk_stmt]), so
+/// implementors only override the variants they care about. Expressions aren't descended into;
+/// use [ExprVisitor] for those.
+#[allow(dead_code)]
+pub trait StmtVisitor {
+	fn visit_expr(&mut self, _expr: &Expr) {}
+
+	fn visit_print(&mut self, _exprs: &[Expr]) {}
+
+	fn visit_var(&mut self, _name: &Token, _initializer: Option<&Expr>) {}
+
+	fn visit_empty(&mut self) {}
+
+	fn visit_block(&mut self, statements: &[Stmt]) {
+		walk_stmts(self, statements);
+	}
+
+	fn visit_if(&mut self, _condition: &Expr, then_branch: &Stmt, else_branch: Option<&Stmt>) {
+		walk_if(self, then_branch, else_branch);
+	}
+
+	fn visit_while(&mut self, _condition: &Expr, body: &Stmt, _label: Option<&Token>) {
+		body.accept(self);
+	}
+
+	fn visit_break(&mut self, _keyword: &Token, _label: Option<&Token>) {}
+
+	fn visit_continue(&mut self, _keyword: &Token, _label: Option<&Token>) {}
+
+	fn visit_function(&mut self, function: &FunctionStatement) {
+		walk_stmts(self, &function.body);
+	}
+
+	fn visit_return(&mut self, _keyword: &Token, _value: Option<&Expr>) {}
+
+	fn visit_class(
+		&mut self,
+		_name: &Token,
+		_superclass: Option<&Token>,
+		methods: &[FunctionStatement],
+		static_methods: &[FunctionStatement],
+		_fields: &[(Token, Option<Expr>)],
+	) {
+		walk_class(self, methods, static_methods);
+	}
+
+	fn visit_throw(&mut self, _keyword: &Token, _value: &Expr) {}
+
+	fn visit_import(&mut self, _keyword: &Token, _path: &Token, _alias: Option<&Token>) {}
+
+	fn visit_assert(&mut self, _keyword: &Token, _condition: &Expr, _message: Option<&Expr>) {}
+
+	fn visit_try(
+		&mut self,
+		_keyword: &Token,
+		body: &Stmt,
+		_catch_name: Option<&Token>,
+		catch_body: &Stmt,
+	) {
+		walk_try(self, body, catch_body);
+	}
+}
+
+#[allow(dead_code)]
+impl Stmt {
+	pub fn accept<V: StmtVisitor + ?Sized>(&self, v: &mut V) {
+		match self {
+			Stmt::Expr(expr) => v.visit_expr(expr),
+			Stmt::Print(exprs) => v.visit_print(exprs),
+			Stmt::Var {
+				name, initializer, ..
+			} => v.visit_var(name, initializer.as_ref()),
+			Stmt::Block(statements) => v.visit_block(statements),
+			Stmt::Empty => v.visit_empty(),
+			Stmt::If {
+				condition,
+				then_branch,
+				else_branch,
+			} => v.visit_if(condition, then_branch, else_branch.as_deref()),
+			Stmt::While {
+				condition,
+				body,
+				label,
+			} => v.visit_while(condition, body, label.as_ref()),
+			Stmt::Break { keyword, label } => v.visit_break(keyword, label.as_ref()),
+			Stmt::Continue { keyword, label } => v.visit_continue(keyword, label.as_ref()),
+			Stmt::Function(function) => v.visit_function(function),
+			Stmt::Return { keyword, value } => v.visit_return(keyword, value.as_ref()),
+			Stmt::Class {
+				name,
+				superclass,
+				methods,
+				static_methods,
+				fields,
+			} => v.visit_class(name, superclass.as_ref(), methods, static_methods, fields),
+			Stmt::Throw { keyword, value } => v.visit_throw(keyword, value),
+			Stmt::Try {
+				keyword,
+				body,
+				catch_name,
+				catch_body,
+			} => v.visit_try(keyword, body, catch_name.as_ref(), catch_body),
+			Stmt::Assert {
+				keyword,
+				condition,
+				message,
+			} => v.visit_assert(keyword, condition, message.as_ref()),
+			Stmt::Import {
+				keyword,
+				path,
+				alias,
+			} => v.visit_import(keyword, path, alias.as_ref()),
+		}
+	}
+}
+
+/// Visits the statements nested directly inside `stmt`. This is what the default [StmtVisitor]
+/// methods do, and it's meant for overrides that still want to descend into the children.
+#[allow(dead_code)]
+pub fn walk_stmt<V: StmtVisitor + ?Sized>(v: &mut V, stmt: &Stmt) {
+	match stmt {
+		Stmt::Expr(_)
+		| Stmt::Print(_)
+		| Stmt::Var { .. }
+		| Stmt::Empty
+		| Stmt::Return { .. }
+		| Stmt::Throw { .. }
+		| Stmt::Assert { .. }
+		| Stmt::Import { .. }
+		| Stmt::Break { .. }
+		| Stmt::Continue { .. } => (),
+		Stmt::Block(statements) => walk_stmts(v, statements),
+		Stmt::If {
+			then_branch,
+			else_branch,
+			..
+		} => walk_if(v, then_branch, else_branch.as_deref()),
+		Stmt::While { body, .. } => body.accept(v),
+		Stmt::Function(function) => walk_stmts(v, &function.body),
+		Stmt::Class {
+			methods,
+			static_methods,
+			..
+		} => walk_class(v, methods, static_methods),
+		Stmt::Try {
+			body, catch_body, ..
+		} => walk_try(v, body, catch_body),
+	}
+}
+
+fn walk_stmts<V: StmtVisitor + ?Sized>(v: &mut V, statements: &[Stmt]) {
+	statements.iter().for_each(|statement| statement.accept(v));
+}
+
+fn walk_if<V: StmtVisitor + ?Sized>(v: &mut V, then_branch: &Stmt, else_branch: Option<&Stmt>) {
+	then_branch.accept(v);
+	if let Some(else_branch) = else_branch {
+		else_branch.accept(v);
+	}
+}
+
+/// Methods are visited as functions, static ones after the rest.
+fn walk_class<V: StmtVisitor + ?Sized>(
+	v: &mut V,
+	methods: &[FunctionStatement],
+	static_methods: &[FunctionStatement],
+) {
+	methods
+		.iter()
+		.chain(static_methods)
+		.for_each(|method| v.visit_function(method));
+}
+
+fn walk_try<V: StmtVisitor + ?Sized>(v: &mut V, body: &Stmt, catch_body: &Stmt) {
+	body.accept(v);
+	catch_body.accept(v);
+}
+
+/// Every expression in `stmts`, parents before their children, in source order. This descends
+/// into nested statements too, including the bodies of functions and block expressions. Class
+/// members are visited fields first, then methods, then static methods.
+#[allow(dead_code)]
+pub fn iter_exprs(stmts: &[Stmt]) -> impl Iterator<Item = &Expr> {
+	let mut stack: Vec<Node> = stmts.iter().rev().map(Node::Stmt).collect();
+	std::iter::from_fn(move || loop {
+		match stack.pop()? {
+			Node::Stmt(stmt) => stack.extend(stmt_children(stmt).into_iter().rev()),
+			Node::Expr(expr) => {
+				stack.extend(expr_children(expr).into_iter().rev());
+				return Some(expr);
+			}
+		}
+	})
+}
+
+pub(crate) enum Node<'a> {
+	Stmt(&'a Stmt),
+	Expr(&'a Expr),
+}
+
+/// The statements and expressions directly inside `stmt`, in source order.
+pub(crate) fn stmt_children<'a>(stmt: &'a Stmt) -> Vec<Node<'a>> {
+	let stmts = |stmts: &'a [Stmt]| stmts.iter().map(Node::Stmt).collect();
+	match stmt {
+		Stmt::Expr(expr) | Stmt::Throw { value: expr, .. } => vec![Node::Expr(expr)],
+		Stmt::Return { value, .. } => value.iter().map(Node::Expr).collect(),
+		Stmt::Print(exprs) => exprs.iter().map(Node::Expr).collect(),
+		Stmt::Var { initializer, .. } => initializer.iter().map(Node::Expr).collect(),
+		Stmt::Block(statements) => stmts(statements),
+		Stmt::Empty | Stmt::Break { .. } | Stmt::Continue { .. } | Stmt::Import { .. } => {
+			Vec::new()
+		}
+		Stmt::If {
+			condition,
+			then_branch,
+			else_branch,
+		} => [Node::Expr(condition), Node::Stmt(then_branch)]
+			.into_iter()
+			.chain(else_branch.as_deref().map(Node::Stmt))
+			.collect(),
+		Stmt::While {
+			condition, body, ..
+		} => vec![Node::Expr(condition), Node::Stmt(body)],
+		Stmt::Function(function) => stmts(&function.body),
+		Stmt::Class {
+			methods,
+			static_methods,
+			fields,
+			..
+		} => fields
+			.iter()
+			.filter_map(|(_, initializer)| initializer.as_ref().map(Node::Expr))
+			.chain(
+				methods
+					.iter()
+					.chain(static_methods)
+					.flat_map(|method| &method.body)
+					.map(Node::Stmt),
+			)
+			.collect(),
+		Stmt::Try {
+			body, catch_body, ..
+		} => vec![Node::Stmt(body), Node::Stmt(catch_body)],
+		Stmt::Assert {
+			condition, message, ..
+		} => std::iter::once(condition)
+			.chain(message)
+			.map(Node::Expr)
+			.collect(),
+	}
+}
+
+/// The expressions, and statements of nested bodies, directly inside `expr`, in source order.
+pub(crate) fn expr_children<'a>(expr: &'a Expr) -> Vec<Node<'a>> {
+	let arguments =
+		|arguments: &'a [Argument]| arguments.iter().map(|argument| Node::Expr(&argument.expr));
+	match expr {
+		Expr::Literal(_) | Expr::Variable(_) | Expr::This { .. } | Expr::Super(_) => Vec::new(),
+		Expr::Assign { value: expr, .. }
+		| Expr::Unary { expr, .. }
+		| Expr::Grouping(expr)
+		| Expr::Get { object: expr, .. } => vec![Node::Expr(expr)],
+		Expr::Binary { left, right, .. }
+		| Expr::Logical { left, right, .. }
+		| Expr::Set {
+			object: left,
+			value: right,
+			..
+		}
+		| Expr::Range {
+			start: left,
+			end: right,
+			..
+		} => vec![Node::Expr(left), Node::Expr(right)],
+		Expr::Call(call) => std::iter::once(Node::Expr(&call.callee))
+			.chain(arguments(&call.arguments))
+			.collect(),
+		Expr::SuperCall(super_call) => arguments(&super_call.arguments).collect(),
+		Expr::Function(function) => function.body.iter().map(Node::Stmt).collect(),
+		Expr::Match(match_expr) => std::iter::once(&match_expr.subject)
+			.chain(match_expr.arms.iter().flat_map(|(pattern, value)| [pattern, value]))
+			.chain(&match_expr.default)
+			.map(Node::Expr)
+			.collect(),
+		Expr::Block(block) => block
+			.statements
+			.iter()
+			.map(Node::Stmt)
+			.chain(std::iter::once(Node::Expr(&block.value)))
+			.collect(),
+		Expr::If(if_expr) => vec![
+			Node::Expr(&if_expr.condition),
+			Node::Expr(&if_expr.then_branch),
+			Node::Expr(&if_expr.else_branch),
+		],
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{iter_exprs, walk_stmt, ExprVisitor, StmtVisitor};
+	use crate::parser::{Argument, Expr, FunctionStatement, Parser, Stmt};
+	use crate::scanner::Scanner;
+	use crate::token::Token;
+
+	/// Collects names of all the variables read by an expression.
+	struct VariableCollector(Vec<String>);
+
+	impl ExprVisitor for VariableCollector {
+		type Output = ();
+
+		fn visit_literal(&mut self, _token: &Token) {}
+
+		fn visit_variable(&mut self, name: &Token) {
+			self.0.push(name.lexeme.clone());
+		}
+
+		fn visit_assign(&mut self, _name: &Token, value: &Expr) {
+			value.accept(self)
+		}
+
+		fn visit_unary(&mut self, _operator: &Token, expr: &Expr) {
+			expr.accept(self)
+		}
+
+		fn visit_binary(&mut self, left: &Expr, _operator: &Token, right: &Expr) {
+			left.accept(self);
+			right.accept(self);
+		}
+
+		fn visit_grouping(&mut self, expr: &Expr) {
+			expr.accept(self)
+		}
+
+		fn visit_logical(&mut self, left: &Expr, _operator: &Token, right: &Expr) {
+			left.accept(self);
+			right.accept(self);
+		}
+
+		fn visit_call(
+			&mut self,
+			callee: &Expr,
+			_closing_parenthesis: &Token,
+			arguments: &[Argument],
+		) {
+			callee.accept(self);
+			arguments.iter().for_each(|argument| argument.expr.accept(self));
+		}
+
+		fn visit_get(&mut self, object: &Expr, _name: &Token, _optional: bool) {
+			object.accept(self)
+		}
+
+		fn visit_set(&mut self, object: &Expr, _name: &Token, value: &Expr) {
+			object.accept(self);
+			value.accept(self);
+		}
+
+		fn visit_this(&mut self, _keyword: &Token) {}
+
+		fn visit_super(&mut self, _keyword: &Token, _method: &Token) {}
+
+		fn visit_super_call(&mut self, _keyword: &Token, arguments: &[Argument]) {
+			arguments.iter().for_each(|argument| argument.expr.accept(self));
+		}
+
+		fn visit_function(
+			&mut self,
+			_keyword: &Token,
+			_name: Option<&Token>,
+			_params: &[Token],
+			_body: &[Stmt],
+		) {
+		}
+
+		fn visit_match(&mut self, subject: &Expr, arms: &[(Expr, Expr)], default: Option<&Expr>) {
+			subject.accept(self);
+			for (pattern, value) in arms {
+				pattern.accept(self);
+				value.accept(self);
+			}
+			if let Some(default) = default {
+				default.accept(self);
+			}
+		}
+
+		fn visit_block_expr(&mut self, _statements: &[Stmt], value: &Expr) {
+			value.accept(self);
+		}
+
+		fn visit_if_expr(&mut self, condition: &Expr, then_branch: &Expr, else_branch: &Expr) {
+			condition.accept(self);
+			then_branch.accept(self);
+			else_branch.accept(self);
+		}
+
+		fn visit_range(&mut self, start: &Expr, end: &Expr, _inclusive: bool) {
+			start.accept(self);
+			end.accept(self);
+		}
+	}
+
+	#[test]
+	fn test_expr_visitor() {
+		let tokens = Scanner::new("a = -b + f(c, (d)).e or 1;").scan_tokens();
+		let Some(Stmt::Expr(expr)) = Parser::new(tokens).parse().unwrap().pop() else {
+			panic!("expected an expression statement");
+		};
+
+		let mut collector = VariableCollector(Vec::new());
+		expr.accept(&mut collector);
+		assert_eq!(collector.0, ["b", "f", "c", "d"]);
+	}
+
+	#[derive(Default)]
+	struct PrintCounter {
+		prints: usize,
+		functions: usize,
+	}
+
+	impl StmtVisitor for PrintCounter {
+		fn visit_print(&mut self, _exprs: &[Expr]) {
+			self.prints += 1;
+		}
+
+		fn visit_function(&mut self, function: &FunctionStatement) {
+			self.functions += 1;
+			function.body.iter().for_each(|statement| statement.accept(self));
+		}
+	}
+
+	#[test]
+	fn test_stmt_visitor() {
+		let source = "print 1;\n\
+			fun f() { print 2; if (true) print 3; else { while (false) print 4; } }\n\
+			class A { m() { print 5; } }\n\
+			{ var a = 1; print a; }";
+		let statements = Parser::new(Scanner::new(source).scan_tokens())
+			.parse()
+			.unwrap();
+
+		let mut counter = PrintCounter::default();
+		statements.iter().for_each(|statement| statement.accept(&mut counter));
+		assert_eq!(counter.prints, 6);
+		assert_eq!(counter.functions, 2);
+
+		let mut counter = PrintCounter::default();
+		walk_stmt(&mut counter, &statements[1]);
+		assert_eq!(counter.prints, 3);
+		assert_eq!(counter.functions, 0);
+
+		// Walking a class visits its methods the same way the default visit_class does.
+		let mut counter = PrintCounter::default();
+		walk_stmt(&mut counter, &statements[2]);
+		assert_eq!(counter.prints, 1);
+		assert_eq!(counter.functions, 1);
+	}
+
+	#[test]
+	fn test_iter_exprs() {
+		let source = "var a = 1 + 2;\n\
+			fun f(x) { return -x; }\n\
+			if (a) print f(a), { var b = a; b };";
+		let statements = Parser::new(Scanner::new(source).scan_tokens())
+			.parse()
+			.unwrap();
+
+		let exprs: Vec<_> = iter_exprs(&statements).map(Expr::to_string).collect();
+		assert_eq!(
+			exprs,
+			[
+				"(+ 1 2)",
+				"1",
+				"2",
+				"(- x)",
+				"x",
+				"a",
+				"(call f a)",
+				"f",
+				"a",
+				"(block b)",
+				"a",
+				"b",
+			]
+		);
+		assert_eq!(iter_exprs(&[]).count(), 0);
+	}
+}
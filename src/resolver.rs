@@ -1,5 +1,5 @@
-use crate::parser::{Expr, FunctionStatement, Stmt};
-use crate::token::{Token, TokenType};
+use crate::parser::{Expr, FunctionExpr, FunctionStatement, Stmt};
+use crate::token::Token;
 use crate::Interpreter;
 use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
@@ -97,8 +97,14 @@ impl<'a> Resolver<'a> {
 		for statement in statements {
 			match statement {
 				Stmt::Expr(expr) => self.resolve_expr(expr)?,
-				Stmt::Print(expr) => self.resolve_expr(expr)?,
-				Stmt::Var { name, initializer } => {
+				Stmt::Print(exprs) => {
+					for expr in exprs {
+						self.resolve_expr(expr)?;
+					}
+				}
+				Stmt::Var {
+					name, initializer, ..
+				} => {
 					self.declare(name.clone())?;
 					if let Some(initializer) = initializer {
 						self.resolve_expr(initializer)?;
@@ -121,10 +127,13 @@ impl<'a> Resolver<'a> {
 						self.resolve_statements(std::iter::once(*else_branch))?;
 					}
 				}
-				Stmt::While { condition, body } => {
+				Stmt::While {
+					condition, body, ..
+				} => {
 					self.resolve_expr(condition)?;
 					self.resolve_statements(std::iter::once(*body))?;
 				}
+				Stmt::Empty | Stmt::Break { .. } | Stmt::Continue { .. } => (),
 				Stmt::Function(function) => {
 					self.declare(function.name.clone())?;
 					self.define(function.name.clone());
@@ -135,13 +144,7 @@ impl<'a> Resolver<'a> {
 						return Err(Error::ReturnFromGlobalScope(keyword));
 					}
 
-					if !matches!(
-						value,
-						Expr::Literal(Token {
-							token_type: TokenType::Nil,
-							..
-						})
-					) {
+					if let Some(value) = value {
 						if let FunctionType::Initializer = self.current_function {
 							return Err(Error::ReturnValueFromInitializer(keyword));
 						}
@@ -153,7 +156,16 @@ impl<'a> Resolver<'a> {
 					name,
 					superclass,
 					methods,
+					static_methods,
+					fields,
 				} => {
+					// Field initializers see the scope enclosing the class.
+					for (_, initializer) in fields {
+						if let Some(initializer) = initializer {
+							self.resolve_expr(initializer)?;
+						}
+					}
+
 					let enclosing_class = self.current_class;
 					self.current_class = ClassType::Class;
 
@@ -180,6 +192,14 @@ impl<'a> Resolver<'a> {
 						false
 					};
 
+					// Static methods aren't bound to an instance, so they see neither `this` nor
+					// `super`.
+					let current_class = std::mem::replace(&mut self.current_class, ClassType::None);
+					for method in static_methods {
+						self.resolve_function(method, FunctionType::Function)?;
+					}
+					self.current_class = current_class;
+
 					self.begin_scope();
 
 					self.scopes
@@ -204,6 +224,34 @@ impl<'a> Resolver<'a> {
 
 					self.current_class = enclosing_class;
 				}
+				Stmt::Throw { value, .. } => self.resolve_expr(value)?,
+				Stmt::Import { .. } => (),
+				Stmt::Assert {
+					condition, message, ..
+				} => {
+					self.resolve_expr(condition)?;
+					if let Some(message) = message {
+						self.resolve_expr(message)?;
+					}
+				}
+				Stmt::Try {
+					body,
+					catch_name,
+					catch_body,
+					..
+				} => {
+					self.resolve_statements(std::iter::once(*body))?;
+					// The interpreter can't run `try` yet, but the catch block is still checked.
+					// The exception gets a scope of its own, even when it's not named, as its
+					// environment will once exceptions can be caught.
+					self.begin_scope();
+					if let Some(catch_name) = catch_name {
+						self.declare(catch_name.clone())?;
+						self.define(catch_name);
+					}
+					self.resolve_statements(std::iter::once(*catch_body))?;
+					self.end_scope();
+				}
 			}
 		}
 		Ok(())
@@ -253,20 +301,13 @@ impl<'a> Resolver<'a> {
 				self.resolve_expr(*left)?;
 				self.resolve_expr(*right)?;
 			}
-			Expr::Call {
-				callee,
-				closing_parenthesis: _closing_parenthesis,
-				arguments,
-			} => {
-				self.resolve_expr(*callee)?;
-				for argument in arguments {
-					self.resolve_expr(argument)?;
+			Expr::Call(call) => {
+				self.resolve_expr(call.callee)?;
+				for argument in call.arguments {
+					self.resolve_expr(argument.expr)?;
 				}
 			}
-			Expr::Get {
-				object,
-				name: _name,
-			} => {
+			Expr::Get { object, .. } => {
 				self.resolve_expr(*object)?;
 			}
 			Expr::Set {
@@ -284,15 +325,78 @@ impl<'a> Resolver<'a> {
 				}
 				self.resolve_local(expr, keyword);
 			}
-			Expr::Super { ref keyword, .. } => {
-				let keyword = keyword.clone();
+			Expr::Super(ref super_expr) => {
+				let keyword = super_expr.keyword.clone();
+				match self.current_class {
+					ClassType::Class => return Err(Error::SuperInClassWithoutSuperclass(keyword)),
+					ClassType::None => return Err(Error::SuperOutsideClass(keyword)),
+					ClassType::Subclass => (),
+				}
+				self.resolve_local(expr, keyword);
+			}
+			Expr::SuperCall(ref super_call) => {
+				let keyword = super_call.keyword.clone();
 				match self.current_class {
 					ClassType::Class => return Err(Error::SuperInClassWithoutSuperclass(keyword)),
 					ClassType::None => return Err(Error::SuperOutsideClass(keyword)),
 					ClassType::Subclass => (),
 				}
+				for argument in super_call.arguments.clone() {
+					self.resolve_expr(argument.expr)?;
+				}
 				self.resolve_local(expr, keyword);
 			}
+			Expr::Function(function) => {
+				let FunctionExpr {
+					keyword,
+					name,
+					params,
+					body,
+					..
+				} = *function;
+				// The name of a function expression is only in scope inside the function, so it
+				// can call itself.
+				let named = name.is_some();
+				if let Some(name) = &name {
+					self.begin_scope();
+					self.declare(name.clone())?;
+					self.define(name.clone());
+				}
+				let function = FunctionStatement {
+					name: name.unwrap_or(keyword),
+					params,
+					body,
+				};
+				self.resolve_function(function, FunctionType::Function)?;
+				if named {
+					self.end_scope();
+				}
+			}
+			Expr::Match(match_expr) => {
+				self.resolve_expr(match_expr.subject)?;
+				for (pattern, value) in match_expr.arms {
+					self.resolve_expr(pattern)?;
+					self.resolve_expr(value)?;
+				}
+				if let Some(default) = match_expr.default {
+					self.resolve_expr(default)?;
+				}
+			}
+			Expr::Block(block) => {
+				self.begin_scope();
+				self.resolve_statements(block.statements)?;
+				self.resolve_expr(block.value)?;
+				self.end_scope();
+			}
+			Expr::If(if_expr) => {
+				self.resolve_expr(if_expr.condition)?;
+				self.resolve_expr(if_expr.then_branch)?;
+				self.resolve_expr(if_expr.else_branch)?;
+			}
+			Expr::Range { start, end, .. } => {
+				self.resolve_expr(*start)?;
+				self.resolve_expr(*end)?;
+			}
 		}
 		Ok(())
 	}
@@ -11,6 +11,10 @@ pub struct Scanner<'a> {
 	current: usize,
 	/// Tracks which source line [Scanner.current] is on
 	line: usize,
+	/// Number of characters consumed on [Scanner.line] so far
+	column: usize,
+	/// Whether comments are emitted as [TokenType::Comment] tokens instead of being skipped.
+	comments: bool,
 
 	keywords: HashMap<&'static str, TokenType>,
 }
@@ -20,8 +24,12 @@ pub enum Error {
 	UnexpectedCharacter(u8),
 	// I know it's kinda weird to specify comment as an "error"
 	LineComment,
+	BlockComment,
 	Whitespace,
-	UnteminatedString,
+	UnterminatedString { line: usize },
+	UnterminatedBlockComment { line: usize },
+	InvalidNumber(String),
+	InvalidEscape { escape: char, line: usize },
 }
 
 impl Display for Error {
@@ -29,8 +37,12 @@ impl Display for Error {
 		match self {
 			Error::UnexpectedCharacter(b) => write!(f, "Unexpected character {}", *b as char),
 			Error::LineComment => write!(f, "Line comment"),
+			Error::BlockComment => write!(f, "Block comment"),
 			Error::Whitespace => write!(f, "Whitespace"),
-			Error::UnteminatedString => write!(f, "Unteminated string"),
+			Error::UnterminatedString { .. } => write!(f, "Unterminated string"),
+			Error::UnterminatedBlockComment { .. } => write!(f, "Unterminated block comment"),
+			Error::InvalidNumber(literal) => write!(f, "Invalid number literal {literal}"),
+			Error::InvalidEscape { escape, .. } => write!(f, "Invalid escape sequence \\{escape}"),
 		}
 	}
 }
@@ -53,11 +65,40 @@ fn keywords() -> HashMap<&'static str, TokenType> {
 		("true", TokenType::True),
 		("var", TokenType::Var),
 		("while", TokenType::While),
+		("throw", TokenType::Throw),
+		("try", TokenType::Try),
+		("catch", TokenType::Catch),
+		("assert", TokenType::Assert),
+		("is", TokenType::Is),
+		("break", TokenType::Break),
+		("continue", TokenType::Continue),
+		("match", TokenType::Match),
+		("import", TokenType::Import),
+		("as", TokenType::As),
+		("undefined", TokenType::Undefined),
+		("global", TokenType::Global),
+		("local", TokenType::Local),
 	]
 	.into_iter()
 	.collect()
 }
 
+/// Removes the `_` digit separators from a number literal. `None` if any of them isn't placed
+/// between two digits.
+fn strip_separators(literal: &str) -> Option<String> {
+	let bytes = literal.as_bytes();
+	let misplaced = bytes.iter().enumerate().any(|(i, b)| {
+		*b == b'_'
+			&& !(i > 0
+				&& bytes[i - 1].is_ascii_alphanumeric()
+				&& bytes.get(i + 1).is_some_and(u8::is_ascii_alphanumeric))
+	});
+	if misplaced {
+		return None;
+	}
+	Some(literal.replace('_', ""))
+}
+
 impl<'a> Scanner<'a> {
 	pub fn new(source: &'a str) -> Self {
 		Self {
@@ -65,19 +106,65 @@ impl<'a> Scanner<'a> {
 			start: 0,
 			current: 0,
 			line: 1,
+			column: 0,
+			comments: false,
 			keywords: keywords(),
 		}
 	}
 
-	pub fn scan_tokens(mut self) -> Vec<Token> {
+	/// A scanner that keeps the comments, e.g. for a formatter to put them back in place.
+	#[allow(dead_code)]
+	pub fn with_comments(source: &'a str) -> Self {
+		Self {
+			comments: true,
+			..Self::new(source)
+		}
+	}
+
+	/// Scans `print` as an ordinary identifier, so that `print(x)` parses as a call rather than
+	/// as a print statement. Running such a program then takes a `print` function defined by
+	/// the embedder.
+	#[allow(dead_code)]
+	pub fn without_print_keyword(mut self) -> Self {
+		self.keywords.remove("print");
+		self
+	}
+
+	pub fn scan_tokens(self) -> Vec<Token> {
+		self.scan(crate::error)
+	}
+
+	/// Scans the whole source, passing every error along with its line to `report`.
+	fn scan(mut self, mut report: impl FnMut(usize, Error)) -> Vec<Token> {
 		let mut tokens = Vec::new();
 		while !self.is_at_end() {
 			self.start = self.current;
+			let (line, column) = (self.line, self.column + 1);
 			let token = match self.scan_token() {
 				Ok(v) => v,
-				Err(Error::LineComment | Error::Whitespace) => continue,
-				Err(err @ Error::UnexpectedCharacter(_) | err @ Error::UnteminatedString) => {
-					crate::error(self.line, err);
+				Err(Error::LineComment | Error::BlockComment) if self.comments => {
+					let text = self.source[self.start..self.current].to_string();
+					Token {
+						token_type: TokenType::Comment(text.clone()),
+						lexeme: text,
+						line,
+						column,
+						universal_index: next_universal_index(),
+					}
+				}
+				Err(Error::LineComment | Error::BlockComment | Error::Whitespace) => continue,
+				Err(
+					err @ (Error::UnterminatedBlockComment { line }
+					| Error::UnterminatedString { line }
+					| Error::InvalidEscape { line, .. }),
+				) => {
+					report(line, err);
+					continue;
+				}
+				Err(
+					err @ Error::UnexpectedCharacter(_) | err @ Error::InvalidNumber(_),
+				) => {
+					report(self.line, err);
 					continue;
 				}
 			};
@@ -88,6 +175,7 @@ impl<'a> Scanner<'a> {
 			token_type: TokenType::Eof,
 			lexeme: "".to_string(),
 			line: self.line,
+			column: self.column + 1,
 			universal_index: next_universal_index(),
 		});
 
@@ -95,6 +183,7 @@ impl<'a> Scanner<'a> {
 	}
 
 	fn scan_token(&mut self) -> Result<Token, Error> {
+		let (line, column) = (self.line, self.column + 1);
 		let b = self.advance();
 		let token_type = match b {
 			b'(' => TokenType::LeftParen,
@@ -102,61 +191,163 @@ impl<'a> Scanner<'a> {
 			b'{' => TokenType::LeftBrace,
 			b'}' => TokenType::RightBrace,
 			b',' => TokenType::Comma,
+			b'.' if self.next_matches(b'.') => {
+				if self.next_matches(b'=') {
+					TokenType::DotDotEqual
+				} else if self.next_matches(b'.') {
+					TokenType::DotDotDot
+				} else {
+					TokenType::DotDot
+				}
+			}
 			b'.' => TokenType::Dot,
+			b'-' if self.next_matches(b'=') => TokenType::MinusEqual,
 			b'-' => TokenType::Minus,
+			b'+' if self.next_matches(b'=') => TokenType::PlusEqual,
 			b'+' => TokenType::Plus,
 			b';' => TokenType::Semicolon,
+			b':' => TokenType::Colon,
+			b'*' if self.next_matches(b'=') => TokenType::StarEqual,
 			b'*' => TokenType::Star,
 			b'!' if self.next_matches(b'=') => TokenType::BangEqual,
 			b'!' => TokenType::Bang,
 			b'=' if self.next_matches(b'=') => TokenType::EqualEqual,
+			b'=' if self.next_matches(b'>') => TokenType::FatArrow,
 			b'=' => TokenType::Equal,
 			b'<' if self.next_matches(b'=') => TokenType::LessEqual,
 			b'<' => TokenType::Less,
 			b'>' if self.next_matches(b'=') => TokenType::GreaterEqual,
 			b'>' => TokenType::Greater,
+			b'?' if self.next_matches(b'?') => TokenType::QuestionQuestion,
+			b'?' if self.next_matches(b'.') => TokenType::QuestionDot,
 			b'/' if self.next_matches(b'/') => {
 				while !matches!(self.peek(), b'\n' | b'\0') {
 					self.advance();
 				}
 				return Err(Error::LineComment);
 			}
+			b'/' if self.next_matches(b'*') => {
+				let mut depth = 1;
+				while depth > 0 {
+					match self.peek() {
+						b'\0' if self.is_at_end() => {
+							return Err(Error::UnterminatedBlockComment { line })
+						}
+						b'/' if self.peek_offset(1) == b'*' => {
+							self.advance();
+							self.advance();
+							depth += 1;
+						}
+						b'*' if self.peek_offset(1) == b'/' => {
+							self.advance();
+							self.advance();
+							depth -= 1;
+						}
+						_ => {
+							if self.advance() == b'\n' {
+								self.new_line();
+							}
+						}
+					}
+				}
+				return Err(Error::BlockComment);
+			}
+			b'/' if self.next_matches(b'=') => TokenType::SlashEqual,
 			b'/' => TokenType::Slash,
 			b' ' | b'\r' | b'\t' => return Err(Error::Whitespace),
 			b'\n' => {
-				self.line += 1;
+				self.new_line();
 				return Err(Error::Whitespace);
 			}
 			b'"' => {
+				let line = self.line;
+				let mut value = Vec::new();
+				// Only the first invalid escape gets reported, after the whole string is consumed.
+				let mut invalid_escape = None;
 				while !matches!(self.peek(), b'"' | b'\0') {
-					if self.peek() == b'\n' {
-						self.line += 1;
+					match self.advance() {
+						b'\\' => {
+							let escaped = match self.peek() {
+								b'n' => b'\n',
+								b't' => b'\t',
+								b'r' => b'\r',
+								b'\\' => b'\\',
+								b'"' => b'"',
+								b'0' => b'\0',
+								_ => {
+									let escape = self.source[self.current..].chars().next();
+									invalid_escape = invalid_escape.or(Some(Error::InvalidEscape {
+										escape: escape.unwrap_or('\0'),
+										line: self.line,
+									}));
+									continue;
+								}
+							};
+							self.advance();
+							value.push(escaped);
+						}
+						b'\n' => {
+							self.new_line();
+							value.push(b'\n');
+						}
+						b => value.push(b),
 					}
-					self.advance();
 				}
 				if self.is_at_end() {
-					return Err(Error::UnteminatedString);
+					return Err(Error::UnterminatedString { line });
 				}
 
 				self.advance(); // The closing ".
 
-				// Trim the surrounding quotes.
-				let literal_value = self.source[(self.start + 1)..(self.current - 1)].to_string();
-				TokenType::String(literal_value)
+				if let Some(err) = invalid_escape {
+					return Err(err);
+				}
+				// Escapes only ever replace ASCII with ASCII, so the source's UTF-8 stays intact.
+				TokenType::String(String::from_utf8(value).unwrap())
+			}
+			b'0' if matches!(self.peek(), b'x' | b'X' | b'b' | b'B' | b'o' | b'O') => {
+				let radix = match self.advance() {
+					b'x' | b'X' => 16,
+					b'b' | b'B' => 2,
+					_ => 8,
+				};
+				// Take in everything that could belong to the literal, so that e.g. `0b12` is
+				// rejected as a whole instead of being split into `0b1` and `2`.
+				while self.peek().is_ascii_alphanumeric() || self.peek() == b'_' {
+					self.advance();
+				}
+				let literal = &self.source[self.start..self.current];
+				match strip_separators(&literal[2..])
+					.and_then(|digits| u64::from_str_radix(&digits, radix).ok())
+				{
+					Some(value) => match i64::try_from(value) {
+						Ok(value) => TokenType::Integer(value),
+						Err(_) => TokenType::Number(value as f64),
+					},
+					None => return Err(Error::InvalidNumber(literal.to_string())),
+				}
 			}
 			b'0'..=b'9' => {
-				while self.peek().is_ascii_digit() {
+				while self.peek().is_ascii_digit() || self.peek() == b'_' {
 					self.advance();
 				}
-				if self.peek() == b'.' && self.peek_offset(1).is_ascii_digit() {
+				let fractional = self.peek() == b'.' && self.peek_offset(1).is_ascii_digit();
+				if fractional {
 					self.advance();
-					while self.peek().is_ascii_digit() {
+					while self.peek().is_ascii_digit() || self.peek() == b'_' {
 						self.advance();
 					}
 				}
 
-				let literal_value = &self.source[self.start..self.current];
-				TokenType::Number(literal_value.parse::<f64>().unwrap())
+				let literal = &self.source[self.start..self.current];
+				let Some(digits) = strip_separators(literal) else {
+					return Err(Error::InvalidNumber(literal.to_string()));
+				};
+				// Integers too big for an `i64` are still numbers, just not exact ones.
+				match digits.parse::<i64>() {
+					Ok(value) if !fractional => TokenType::Integer(value),
+					_ => TokenType::Number(digits.parse::<f64>().unwrap()),
+				}
 			}
 			b'a'..=b'z' | b'A'..=b'Z' | b'_' => {
 				while matches!(self.peek(), b'a'..=b'z' | b'A'..=b'Z' | b'_' | b'0'..=b'9') {
@@ -175,11 +366,18 @@ impl<'a> Scanner<'a> {
 			token_type,
 			lexeme: self.source[self.start..self.current].to_string(),
 			//literal: Box::new(Option::<()>::None),
-			line: self.line,
+			line,
+			column,
 			universal_index: next_universal_index(),
 		})
 	}
 
+	/// Must be called right after consuming a `\n`.
+	fn new_line(&mut self) {
+		self.line += 1;
+		self.column = 0;
+	}
+
 	fn next_matches(&mut self, expected: u8) -> bool {
 		let matches = self
 			.source
@@ -188,13 +386,20 @@ impl<'a> Scanner<'a> {
 			.copied()
 			.map(|next| next == expected)
 			.unwrap_or_default();
-		self.current += 1;
+		if matches {
+			self.current += 1;
+			self.column += 1;
+		}
 		matches
 	}
 
 	fn advance(&mut self) -> u8 {
 		let c = self.source.as_bytes()[self.current];
 		self.current += 1;
+		// Count characters, not bytes: skip UTF-8 continuation bytes.
+		if c & 0b1100_0000 != 0b1000_0000 {
+			self.column += 1;
+		}
 		c
 	}
 
@@ -214,3 +419,324 @@ impl<'a> Scanner<'a> {
 		self.current >= self.source.len()
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::{keywords, Error, Scanner};
+	use crate::parser::{transform::fold_constants, Expr, Parser, Stmt};
+	use crate::token::TokenType;
+
+	fn positions(source: &str) -> Vec<(String, usize, usize)> {
+		Scanner::new(source)
+			.scan_tokens()
+			.into_iter()
+			.map(|token| (token.lexeme, token.line, token.column))
+			.collect()
+	}
+
+	#[test]
+	fn test_keywords_are_classified() {
+		for (word, token_type) in keywords() {
+			assert!(token_type.is_keyword(), "{word}");
+			assert!(!token_type.is_operator(), "{word}");
+		}
+	}
+
+	#[test]
+	fn test_token_columns() {
+		let expected = [
+			("var", 1, 1),
+			("x", 1, 5),
+			("=", 1, 7),
+			("\"ą\nb\"", 1, 9),
+			(";", 2, 3),
+			("print", 3, 2),
+			("x", 3, 8),
+			(";", 3, 9),
+			("", 3, 10),
+		];
+		assert_eq!(
+			positions("var x = \"ą\nb\";\n\tprint x;"),
+			expected.map(|(lexeme, line, column)| (lexeme.to_string(), line, column))
+		);
+	}
+
+	#[test]
+	fn test_range_tokens() {
+		let token_types: Vec<_> = Scanner::new("1..10 1..=2.5 a.b ...")
+			.scan_tokens()
+			.into_iter()
+			.map(|token| token.token_type)
+			.collect();
+		assert_eq!(
+			token_types,
+			[
+				TokenType::Integer(1),
+				TokenType::DotDot,
+				TokenType::Integer(10),
+				TokenType::Integer(1),
+				TokenType::DotDotEqual,
+				TokenType::Number(2.5),
+				TokenType::Identifier("a".to_string()),
+				TokenType::Dot,
+				TokenType::Identifier("b".to_string()),
+				TokenType::DotDotDot,
+				TokenType::Eof,
+			]
+		);
+	}
+
+	#[test]
+	fn test_without_print_keyword() {
+		let token_types = |scanner: Scanner| {
+			scanner.scan_tokens().into_iter().map(|token| token.token_type).collect::<Vec<_>>()
+		};
+		assert_eq!(
+			token_types(Scanner::new("print printer")),
+			[
+				TokenType::Print,
+				TokenType::Identifier("printer".to_string()),
+				TokenType::Eof,
+			]
+		);
+		assert_eq!(
+			token_types(Scanner::new("print var").without_print_keyword()),
+			[
+				TokenType::Identifier("print".to_string()),
+				TokenType::Var,
+				TokenType::Eof,
+			]
+		);
+	}
+
+	#[test]
+	fn test_one_or_two_character_tokens() {
+		// Only a matching second character is consumed, so `!nil` isn't `!n` followed by `il`.
+		let token_types: Vec<_> = Scanner::new("!nil =x !=y <z")
+			.scan_tokens()
+			.into_iter()
+			.map(|token| token.token_type)
+			.collect();
+		assert_eq!(
+			token_types,
+			[
+				TokenType::Bang,
+				TokenType::Nil,
+				TokenType::Equal,
+				TokenType::Identifier("x".to_string()),
+				TokenType::BangEqual,
+				TokenType::Identifier("y".to_string()),
+				TokenType::Less,
+				TokenType::Identifier("z".to_string()),
+				TokenType::Eof,
+			]
+		);
+	}
+
+	#[test]
+	fn test_compound_assignment_tokens() {
+		let token_types: Vec<_> = Scanner::new("+= -= *= /= // /=")
+			.scan_tokens()
+			.into_iter()
+			.map(|token| token.token_type)
+			.collect();
+		assert_eq!(
+			token_types,
+			[
+				TokenType::PlusEqual,
+				TokenType::MinusEqual,
+				TokenType::StarEqual,
+				TokenType::SlashEqual,
+				TokenType::Eof,
+			]
+		);
+	}
+
+	#[test]
+	fn test_comment_tokens() {
+		let source = "// one\nvar /* two\n */ x; // three";
+		let comments: Vec<_> = Scanner::with_comments(source)
+			.scan_tokens()
+			.into_iter()
+			.filter_map(|token| match token.token_type {
+				TokenType::Comment(text) => Some((text, token.line, token.column)),
+				_ => None,
+			})
+			.collect();
+		assert_eq!(
+			comments,
+			[
+				("// one".to_string(), 1, 1),
+				("/* two\n */".to_string(), 2, 5),
+				("// three".to_string(), 3, 8),
+			]
+		);
+		assert_eq!(Scanner::new(source).scan_tokens().len(), 4);
+	}
+
+	#[test]
+	fn test_block_comments() {
+		let expected = [
+			("a", 1, 1),
+			("b", 3, 8),
+			("c", 4, 15),
+			("/", 4, 17),
+			("", 4, 18),
+		];
+		assert_eq!(
+			positions("a /* one\n/* two\n */ */ b\n/**//* * / */ c /"),
+			expected.map(|(lexeme, line, column)| (lexeme.to_string(), line, column))
+		);
+	}
+
+	#[test]
+	fn test_unterminated_block_comment() {
+		let mut errors = Vec::new();
+		let tokens = Scanner::new("a\n/* /* */\n\nb").scan(|line, err| errors.push((line, err)));
+		assert_eq!(tokens.len(), 2);
+		assert!(matches!(
+			errors[..],
+			[(2, Error::UnterminatedBlockComment { line: 2 })]
+		));
+	}
+
+	#[test]
+	fn test_unterminated_string() {
+		let mut errors = Vec::new();
+		let tokens = Scanner::new("a\nb\n\"abc\ndef").scan(|line, err| errors.push((line, err)));
+		assert_eq!(tokens.len(), 3);
+		assert!(matches!(
+			errors[..],
+			[(3, Error::UnterminatedString { line: 3 })]
+		));
+		assert_eq!(errors[0].1.to_string(), "Unterminated string");
+	}
+
+	fn numbers(source: &str) -> Vec<(String, f64)> {
+		Scanner::new(source)
+			.scan_tokens()
+			.into_iter()
+			.filter_map(|token| match token.token_type {
+				TokenType::Number(n) => Some((token.lexeme, n)),
+				TokenType::Integer(n) => Some((token.lexeme, n as f64)),
+				_ => None,
+			})
+			.collect()
+	}
+
+	#[test]
+	fn test_radix_literals() {
+		let expected = [
+			("0xFF", 255.0),
+			("0Xa0", 160.0),
+			("0b1010", 10.0),
+			("0o17", 15.0),
+			("0", 0.0),
+			("017", 17.0),
+		];
+		assert_eq!(
+			numbers("0xFF 0Xa0 0b1010 0o17 0 017"),
+			expected.map(|(lexeme, n)| (lexeme.to_string(), n))
+		);
+
+		let tokens = Scanner::new("0x1f + 0b11").scan_tokens();
+		let expr = Parser::new(tokens).parse_expression().unwrap();
+		assert_eq!(expr.to_string(), "(+ 31 3)");
+	}
+
+	#[test]
+	fn test_integer_literals() {
+		let token_types: Vec<_> = Scanner::new("1 1.0 0x10 9223372036854775807 9223372036854775808")
+			.scan_tokens()
+			.into_iter()
+			.map(|token| token.token_type)
+			.collect();
+		assert_eq!(
+			token_types,
+			[
+				TokenType::Integer(1),
+				TokenType::Number(1.0),
+				TokenType::Integer(16),
+				TokenType::Integer(i64::MAX),
+				TokenType::Number(9223372036854775808.0),
+				TokenType::Eof,
+			]
+		);
+	}
+
+	#[test]
+	fn test_digit_separators() {
+		let expected = [("1_000_000", 1_000_000.0), ("12.345_6", 12.345_6), ("0xff_ff", 65535.0)];
+		assert_eq!(
+			numbers("1_000_000 12.345_6 0xff_ff"),
+			expected.map(|(lexeme, n)| (lexeme.to_string(), n))
+		);
+
+		let tokens = Scanner::new("print 1_000 + 2_000;").scan_tokens();
+		let statements = fold_constants(Parser::new(tokens).parse().unwrap());
+		let [Stmt::Print(values)] = &statements[..] else {
+			panic!("expected a print statement, got {statements:?}");
+		};
+		let [Expr::Literal(token)] = &values[..] else {
+			panic!("expected a folded literal, got {values:?}");
+		};
+		assert_eq!(token.token_type, TokenType::Integer(3000));
+
+		// `_1` is an identifier, like in any other language with separators.
+		for source in ["1_", "1__0", "1_.5", "2.5_", "0x_1"] {
+			let mut errors = Vec::new();
+			Scanner::new(source).scan(|line, err| errors.push((line, err)));
+			assert!(
+				matches!(&errors[..], [(1, Error::InvalidNumber(literal))] if literal == source),
+				"{source}: {errors:?}"
+			);
+		}
+	}
+
+	#[test]
+	fn test_string_escapes() {
+		let tokens = Scanner::new(r#""a\nb" "\t\r\\\"\0ą""#).scan_tokens();
+		let strings = tokens
+			.iter()
+			.filter_map(|token| match &token.token_type {
+				TokenType::String(value) => Some((token.lexeme.as_str(), value.as_str())),
+				_ => None,
+			})
+			.collect::<Vec<_>>();
+		assert_eq!(
+			strings,
+			[(r#""a\nb""#, "a\nb"), (r#""\t\r\\\"\0ą""#, "\t\r\\\"\0ą")]
+		);
+
+		let tokens = Scanner::new(r#""a\nb""#).scan_tokens();
+		let expr = Parser::new(tokens).parse_expression().unwrap();
+		assert_eq!(expr.to_string(), "a\nb");
+	}
+
+	#[test]
+	fn test_invalid_string_escape() {
+		let mut errors = Vec::new();
+		let tokens = Scanner::new("\"a\n\\q \\w\"\n1").scan(|line, err| errors.push((line, err)));
+		assert_eq!(tokens.len(), 2);
+		assert!(
+			matches!(
+				errors[..],
+				[(2, Error::InvalidEscape { escape: 'q', line: 2 })]
+			),
+			"{errors:?}"
+		);
+	}
+
+	#[test]
+	fn test_invalid_radix_literals() {
+		for source in ["0x", "0b2", "0o8", "0xfg"] {
+			let mut errors = Vec::new();
+			let tokens = Scanner::new(source).scan(|line, err| errors.push((line, err)));
+			assert_eq!(tokens.len(), 1, "{source}");
+			assert!(
+				matches!(&errors[..], [(1, Error::InvalidNumber(literal))] if literal == source),
+				"{source}: {errors:?}"
+			);
+		}
+	}
+}
@@ -1,3 +1,4 @@
+use std::fmt::Display;
 use std::hash::{Hash, Hasher};
 use std::sync::atomic::{AtomicU64, Ordering};
 
@@ -7,14 +8,71 @@ pub struct Token {
 	pub lexeme: String,
 	//pub literal: Box<dyn std::any::Any>,
 	pub line: usize,
+	/// 1-based, counted in characters from the start of [Token::line]. 0 for tokens that don't
+	/// come from the source.
+	pub column: usize,
 	pub universal_index: u64,
 }
 
 pub fn next_universal_index() -> u64 {
+	#[cfg(test)]
+	if let Some(index) = TEST_UNIVERSAL_INDEX.with(|index| {
+		let current = index.get();
+		index.set(current.map(|current| current + 1));
+		current
+	}) {
+		return index;
+	}
+
 	static UNIVERSAL_INDEX: AtomicU64 = AtomicU64::new(0);
 	UNIVERSAL_INDEX.fetch_add(1, Ordering::Relaxed)
 }
 
+#[cfg(test)]
+thread_local! {
+	static TEST_UNIVERSAL_INDEX: std::cell::Cell<Option<u64>> = const { std::cell::Cell::new(None) };
+}
+
+/// Makes [next_universal_index] count from 0 again, so that a test can predict the indices of the
+/// tokens it creates. Only the calling thread is affected, which keeps tests running in parallel
+/// from interfering with each other.
+///
+/// Test-only: the indices minted afterwards are no longer unique across threads.
+#[cfg(test)]
+pub fn reset_universal_index() {
+	TEST_UNIVERSAL_INDEX.with(|index| index.set(Some(0)));
+}
+
+/// How numbers are displayed everywhere: integral values without a fractional part (`123`, not
+/// `123.0`), and anything else with as many digits as it takes to read the same value back.
+pub fn format_number(n: f64) -> String {
+	if n.is_finite() && n.fract() == 0.0 {
+		format!("{n:.0}")
+	} else {
+		n.to_string()
+	}
+}
+
+impl Token {
+	/// A token that doesn't come from the source, e.g. one made up by a desugaring. It has no
+	/// lexeme or column, but a fresh universal index like any other token.
+	pub fn synthetic(token_type: TokenType, line: usize) -> Token {
+		Token {
+			token_type,
+			lexeme: String::new(),
+			line,
+			column: 0,
+			universal_index: next_universal_index(),
+		}
+	}
+
+	/// The same token, moved to `line`, e.g. to report a desugared node at the line of the
+	/// syntax it came from.
+	pub fn with_line(self, line: usize) -> Token {
+		Token { line, ..self }
+	}
+}
+
 impl PartialEq for Token {
 	fn eq(&self, other: &Self) -> bool {
 		self.universal_index == other.universal_index
@@ -29,7 +87,7 @@ impl Hash for Token {
 	}
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum TokenType {
 	// Single-character tokens.
 	LeftParen,
@@ -41,6 +99,7 @@ pub enum TokenType {
 	Minus,
 	Plus,
 	Semicolon,
+	Colon,
 	Slash,
 	Star,
 
@@ -53,11 +112,26 @@ pub enum TokenType {
 	GreaterEqual,
 	Less,
 	LessEqual,
+	QuestionQuestion,
+	QuestionDot,
+	DotDot,
+	DotDotEqual,
+	DotDotDot,
+	FatArrow,
+	PlusEqual,
+	MinusEqual,
+	StarEqual,
+	SlashEqual,
 
 	// Literals.
 	Identifier(String),
 	String(String),
 	Number(f64),
+	/// A number literal written without a fractional part, e.g. `1` or `0xFF`, as long as it fits.
+	Integer(i64),
+	/// A `//` or `/* */` comment as written, delimiters included. Only scanned on request, see
+	/// [crate::scanner::Scanner::with_comments].
+	Comment(String),
 
 	// Keywords.
 	And,
@@ -76,10 +150,182 @@ pub enum TokenType {
 	True,
 	Var,
 	While,
+	Throw,
+	Try,
+	Catch,
+	Assert,
+	Is,
+	Break,
+	Continue,
+	Match,
+	Import,
+	As,
+	/// A literal for "no value", which embedders can tell apart from `nil`.
+	Undefined,
+	Global,
+	Local,
 
 	Eof,
 }
 
+// Classification for tools like syntax highlighters. The categories overlap: `true`, `false`,
+// `nil` and `undefined` are keywords as well as literals.
+#[allow(dead_code)]
+impl TokenType {
+	/// Whether this is one of the reserved words in [crate::scanner]'s keyword table.
+	pub fn is_keyword(&self) -> bool {
+		matches!(
+			self,
+			TokenType::And
+				| TokenType::Class
+				| TokenType::Else
+				| TokenType::False
+				| TokenType::Fun
+				| TokenType::For
+				| TokenType::If
+				| TokenType::Nil
+				| TokenType::Or
+				| TokenType::Print
+				| TokenType::Return
+				| TokenType::Super
+				| TokenType::This
+				| TokenType::True
+				| TokenType::Var
+				| TokenType::While
+				| TokenType::Throw
+				| TokenType::Try
+				| TokenType::Catch
+				| TokenType::Assert
+				| TokenType::Is
+				| TokenType::Break
+				| TokenType::Continue
+				| TokenType::Match
+				| TokenType::Import
+				| TokenType::As
+				| TokenType::Undefined
+				| TokenType::Global
+				| TokenType::Local
+		)
+	}
+
+	/// Whether this is a symbolic operator, e.g. `+`, `==`, `.` or `..=`. Operators spelled as
+	/// words, like `and`, count as keywords instead.
+	pub fn is_operator(&self) -> bool {
+		matches!(
+			self,
+			TokenType::Dot
+				| TokenType::Minus
+				| TokenType::Plus
+				| TokenType::Slash
+				| TokenType::Star
+				| TokenType::Bang
+				| TokenType::BangEqual
+				| TokenType::Equal
+				| TokenType::EqualEqual
+				| TokenType::Greater
+				| TokenType::GreaterEqual
+				| TokenType::Less
+				| TokenType::LessEqual
+				| TokenType::QuestionQuestion
+				| TokenType::QuestionDot
+				| TokenType::DotDot
+				| TokenType::DotDotEqual
+				| TokenType::DotDotDot
+				| TokenType::FatArrow
+				| TokenType::PlusEqual
+				| TokenType::MinusEqual
+				| TokenType::StarEqual
+				| TokenType::SlashEqual
+		)
+	}
+
+	/// Whether this token is a value by itself.
+	pub fn is_literal(&self) -> bool {
+		matches!(
+			self,
+			TokenType::String(_)
+				| TokenType::Number(_)
+				| TokenType::Integer(_)
+				| TokenType::True
+				| TokenType::False
+				| TokenType::Nil
+				| TokenType::Undefined
+		)
+	}
+}
+
+impl Display for TokenType {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			TokenType::LeftParen => write!(f, "("),
+			TokenType::RightParen => write!(f, ")"),
+			TokenType::LeftBrace => write!(f, "{{"),
+			TokenType::RightBrace => write!(f, "}}"),
+			TokenType::Comma => write!(f, ","),
+			TokenType::Colon => write!(f, ":"),
+			TokenType::Dot => write!(f, "."),
+			TokenType::Minus => write!(f, "-"),
+			TokenType::Plus => write!(f, "+"),
+			TokenType::Semicolon => write!(f, ";"),
+			TokenType::Slash => write!(f, "/"),
+			TokenType::Star => write!(f, "*"),
+			TokenType::Bang => write!(f, "!"),
+			TokenType::BangEqual => write!(f, "!="),
+			TokenType::Equal => write!(f, "="),
+			TokenType::EqualEqual => write!(f, "=="),
+			TokenType::Greater => write!(f, ">"),
+			TokenType::GreaterEqual => write!(f, ">="),
+			TokenType::Less => write!(f, "<"),
+			TokenType::LessEqual => write!(f, "<="),
+			TokenType::QuestionQuestion => write!(f, "??"),
+			TokenType::QuestionDot => write!(f, "?."),
+			TokenType::DotDot => write!(f, ".."),
+			TokenType::DotDotEqual => write!(f, "..="),
+			TokenType::DotDotDot => write!(f, "..."),
+			TokenType::FatArrow => write!(f, "=>"),
+			TokenType::PlusEqual => write!(f, "+="),
+			TokenType::MinusEqual => write!(f, "-="),
+			TokenType::StarEqual => write!(f, "*="),
+			TokenType::SlashEqual => write!(f, "/="),
+			TokenType::Identifier(name) => write!(f, "{name}"),
+			TokenType::String(s) => write!(f, "\"{s}\""),
+			TokenType::Number(n) => write!(f, "{}", format_number(*n)),
+			TokenType::Integer(n) => write!(f, "{n}"),
+			TokenType::Comment(text) => write!(f, "{text}"),
+			TokenType::And => write!(f, "and"),
+			TokenType::Class => write!(f, "class"),
+			TokenType::Else => write!(f, "else"),
+			TokenType::False => write!(f, "false"),
+			TokenType::Fun => write!(f, "fun"),
+			TokenType::For => write!(f, "for"),
+			TokenType::If => write!(f, "if"),
+			TokenType::Nil => write!(f, "nil"),
+			TokenType::Or => write!(f, "or"),
+			TokenType::Print => write!(f, "print"),
+			TokenType::Return => write!(f, "return"),
+			TokenType::Super => write!(f, "super"),
+			TokenType::This => write!(f, "this"),
+			TokenType::True => write!(f, "true"),
+			TokenType::Var => write!(f, "var"),
+			TokenType::While => write!(f, "while"),
+			TokenType::Throw => write!(f, "throw"),
+			TokenType::Try => write!(f, "try"),
+			TokenType::Catch => write!(f, "catch"),
+			TokenType::Assert => write!(f, "assert"),
+			TokenType::Is => write!(f, "is"),
+			TokenType::Break => write!(f, "break"),
+			TokenType::Continue => write!(f, "continue"),
+			TokenType::Match => write!(f, "match"),
+			TokenType::Import => write!(f, "import"),
+			TokenType::As => write!(f, "as"),
+			TokenType::Undefined => write!(f, "undefined"),
+			TokenType::Global => write!(f, "global"),
+			TokenType::Local => write!(f, "local"),
+			TokenType::Eof => write!(f, "end of file"),
+		}
+	}
+}
+
 /*
 #[derive(Debug, Clone)]
 pub enum BetterTokenType {
@@ -139,3 +385,93 @@ pub enum Keyword {
 	While,
 }
 */
+
+#[cfg(test)]
+mod tests {
+	use super::{format_number, next_universal_index, Token, TokenType};
+	use std::collections::HashSet;
+
+	#[test]
+	fn test_format_number() {
+		assert_eq!(format_number(123.0), "123");
+		assert_eq!(format_number(45.67), "45.67");
+		assert_eq!(format_number(1e10), "10000000000");
+		assert_eq!(format_number(-2.0), "-2");
+		assert_eq!(format_number(0.1 + 0.2), "0.30000000000000004");
+	}
+
+	#[test]
+	fn test_synthetic() {
+		let a = Token::synthetic(TokenType::Nil, 3);
+		let b = Token::synthetic(TokenType::Nil, 3);
+		assert_eq!(a.token_type, TokenType::Nil);
+		assert_eq!((a.lexeme.as_str(), a.line, a.column), ("", 3, 0));
+		assert_ne!(a, b);
+
+		let moved = a.clone().with_line(7);
+		assert_eq!((moved.line, moved.column), (7, 0));
+		assert_eq!(moved, a);
+	}
+
+	#[test]
+	fn test_token_type_classification() {
+		for keyword in [TokenType::Fun, TokenType::While, TokenType::And, TokenType::True] {
+			assert!(keyword.is_keyword(), "{keyword:?}");
+		}
+		for operator in [TokenType::Plus, TokenType::EqualEqual, TokenType::Bang, TokenType::Dot] {
+			assert!(operator.is_operator(), "{operator:?}");
+			assert!(!operator.is_keyword(), "{operator:?}");
+		}
+		for literal in [
+			TokenType::Number(1.5),
+			TokenType::Integer(1),
+			TokenType::String("s".to_string()),
+			TokenType::Nil,
+		] {
+			assert!(literal.is_literal(), "{literal:?}");
+			assert!(!literal.is_operator(), "{literal:?}");
+		}
+		for other in [
+			TokenType::Identifier("fun".to_string()),
+			TokenType::LeftParen,
+			TokenType::Semicolon,
+			TokenType::Eof,
+		] {
+			assert!(!other.is_keyword() && !other.is_operator() && !other.is_literal());
+		}
+	}
+
+	#[test]
+	fn test_token_type_display() {
+		assert_eq!(TokenType::Plus.to_string(), "+");
+		assert_eq!(TokenType::EqualEqual.to_string(), "==");
+		assert_eq!(TokenType::LeftBrace.to_string(), "{");
+		assert_eq!(TokenType::While.to_string(), "while");
+		assert_eq!(TokenType::Identifier("foo".to_string()).to_string(), "foo");
+		assert_eq!(TokenType::String("a b".to_string()).to_string(), "\"a b\"");
+		assert_eq!(TokenType::Number(45.67).to_string(), "45.67");
+		assert_eq!(TokenType::Number(3.0).to_string(), "3");
+		assert_eq!(TokenType::Integer(-12).to_string(), "-12");
+	}
+
+	#[test]
+	fn test_universal_index_is_unique_across_threads() {
+		let threads = (0..8)
+			.map(|_| {
+				std::thread::spawn(|| {
+					(0..10_000)
+						.map(|_| next_universal_index())
+						.collect::<Vec<_>>()
+				})
+			})
+			.collect::<Vec<_>>();
+
+		let mut seen = HashSet::new();
+		for thread in threads {
+			for index in thread.join().unwrap() {
+				assert!(seen.insert(index), "duplicate universal index {index}");
+			}
+		}
+		assert_eq!(seen.len(), 80_000);
+	}
+}